@@ -0,0 +1,37 @@
+use std::time::Instant;
+
+use ez80::*;
+
+// Measures the emulation speed on a tight DJNZ loop. Run it with
+// `cargo run --release --bin ez80bench [instructions]` to compare
+// releases.
+fn main() {
+    let instructions: u64 = std::env::args().nth(1)
+        .map(|arg| arg.parse().expect("the argument is the number of instructions"))
+        .unwrap_or(50_000_000);
+
+    let mut machine = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    let code = [
+        0x10, 0xfe, // DJNZ $000000
+        0x0d,       // DEC C
+        0x18, 0xfb, // JR $000000
+    ];
+    for (i, e) in code.iter().enumerate() {
+        machine.poke(i as u32, *e);
+    }
+    cpu.state.set_pc(0x000000);
+
+    let start = Instant::now();
+    for _ in 0..instructions {
+        cpu.execute_instruction(&mut machine);
+    }
+    let seconds = start.elapsed().as_secs_f64();
+
+    println!("{} instructions, {} cycles in {:.3}s", instructions, cpu.state.cycles, seconds);
+    println!("{:.2} MIPS, {:.2} emulated MHz",
+        instructions as f64 / seconds / 1e6,
+        cpu.state.cycles as f64 / seconds / 1e6);
+}
@@ -0,0 +1,7 @@
+use ez80::*;
+
+// Prints the eZ80 opcodes and how they are handled, as CSV
+fn main() {
+    let coverage = ez80_coverage();
+    write_coverage_csv(&mut std::io::stdout(), &coverage).unwrap();
+}
@@ -0,0 +1,141 @@
+use std::ops::Range;
+
+use super::cpu::Cpu;
+
+const CS_LBR: u8 = 0xa8;
+const CS_BMC: u8 = 0xf0;
+
+// CSx_CTL bits
+const CS_EN: u8 = 0x08;
+const CS_IO: u8 = 0x10;
+
+/// The registers of one of the chip selects of the eZ80
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChipSelect {
+    /// Lower bound, ADDR[23:16] in memory, ADDR[15:8] in IO
+    pub lbr: u8,
+    /// Upper bound, ADDR[23:16] in memory
+    pub ubr: u8,
+    /// Control: wait states in bits 7-5, IO in bit 4, enable in bit 3
+    pub ctl: u8,
+    /// Bus mode control
+    pub bmc: u8,
+}
+
+impl ChipSelect {
+    pub fn is_enabled(&self) -> bool {
+        self.ctl & CS_EN != 0
+    }
+
+    pub fn is_io(&self) -> bool {
+        self.ctl & CS_IO != 0
+    }
+
+    pub fn wait_states(&self) -> u32 {
+        (self.ctl >> 5) as u32
+    }
+
+    /// Returns the memory addresses decoded
+    pub fn range(&self) -> Range<u32> {
+        (self.lbr as u32) << 16..((self.ubr as u32) + 1) << 16
+    }
+}
+
+/// The chip select registers CS0 to CS3 of the eZ80, that decode the
+/// memory and IO addresses to the external devices
+///
+/// A Machine routes the on-chip ports $a8-$b3 and $f0-$f3 to it, and
+/// uses select to find the device of each access, so the memory map
+/// follows what the firmware programs at boot. Only the eZ80 bus mode
+/// is modelled: the wait states of CSx_CTL apply whatever the BMC.
+#[derive(Clone, Debug)]
+pub struct ChipSelects {
+    cs: [ChipSelect; 4],
+}
+
+impl ChipSelects {
+    /// Returns the chip selects after reset: CS0 decodes the whole
+    /// memory with 7 wait states, the others are disabled
+    pub fn new() -> ChipSelects {
+        let disabled = ChipSelect { lbr: 0x00, ubr: 0x00, ctl: 0x00, bmc: 0x02 };
+        ChipSelects {
+            cs: [ChipSelect { lbr: 0x00, ubr: 0xff, ctl: 0xe8, bmc: 0x02 }, disabled, disabled, disabled],
+        }
+    }
+
+    /// Returns the registers of the chip select n, 0 to 3
+    pub fn get(&self, n: usize) -> &ChipSelect {
+        &self.cs[n]
+    }
+
+    /// Reads a chip select register. Returns None for other ports.
+    pub fn port_in(&self, port: u8) -> Option<u8> {
+        match port {
+            CS_LBR..=0xb3 => {
+                let cs = &self.cs[((port - CS_LBR) / 3) as usize];
+                Some(match (port - CS_LBR) % 3 {
+                    0 => cs.lbr,
+                    1 => cs.ubr,
+                    _ => cs.ctl,
+                })
+            }
+            CS_BMC..=0xf3 => Some(self.cs[(port - CS_BMC) as usize].bmc),
+            _ => None,
+        }
+    }
+
+    /// Writes a chip select register. Returns false for other ports.
+    pub fn port_out(&mut self, port: u8, value: u8) -> bool {
+        match port {
+            CS_LBR..=0xb3 => {
+                let cs = &mut self.cs[((port - CS_LBR) / 3) as usize];
+                match (port - CS_LBR) % 3 {
+                    0 => cs.lbr = value,
+                    1 => cs.ubr = value,
+                    _ => cs.ctl = value,
+                }
+                true
+            }
+            CS_BMC..=0xf3 => {
+                self.cs[(port - CS_BMC) as usize].bmc = value;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the chip select of a memory address. With overlapping
+    /// ranges, the lowest numbered applies.
+    pub fn select(&self, address: u32) -> Option<usize> {
+        let upper = (address >> 16) as u8;
+        self.cs.iter().position(|cs| cs.is_enabled() && !cs.is_io()
+            && cs.lbr <= upper && upper <= cs.ubr)
+    }
+
+    /// Returns the chip select of an IO address
+    pub fn select_io(&self, address: u16) -> Option<usize> {
+        let upper = (address >> 8) as u8;
+        self.cs.iter().position(|cs| cs.is_enabled() && cs.is_io() && cs.lbr == upper)
+    }
+
+    /// Returns the wait states of the memory address
+    pub fn wait_states(&self, address: u32) -> u32 {
+        self.select(address).map_or(0, |n| self.cs[n].wait_states())
+    }
+
+    /// Replaces the memory wait states of the cpu with the ones of the
+    /// chip selects. To call after the registers change. The cpu uses
+    /// them on its own addresses, before any address translation.
+    pub fn apply_wait_states(&self, cpu: &mut Cpu) {
+        cpu.clear_memory_wait_states();
+        for cs in self.cs.iter().filter(|cs| cs.is_enabled() && !cs.is_io()) {
+            cpu.add_memory_wait_states(cs.range(), cs.wait_states());
+        }
+    }
+}
+
+impl Default for ChipSelects {
+    fn default() -> Self {
+        Self::new()
+    }
+}
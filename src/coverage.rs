@@ -0,0 +1,80 @@
+use std::fmt;
+use std::io::{self, Write};
+
+use super::decoder_ez80::*;
+
+// Prefixes, not opcodes, when there is no previous prefix
+const PREFIX_BYTES: [u8; 8] = [0x40, 0x49, 0x52, 0x5b, 0xcb, 0xdd, 0xed, 0xfd];
+
+/// How the eZ80 decoder handles an opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpcodeStatus {
+    /// Executed as documented
+    Implemented,
+    /// Invalid on the eZ80, executed as a NOP
+    Invalid,
+    /// Not in the decoder. Executing it panics.
+    Undefined,
+}
+
+impl fmt::Display for OpcodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            OpcodeStatus::Implemented => "implemented",
+            OpcodeStatus::Invalid => "invalid",
+            OpcodeStatus::Undefined => "undefined",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+/// An entry of the decoding tables of the eZ80
+#[derive(Clone, Debug)]
+pub struct OpcodeCoverage {
+    /// Prefix bytes in hex, like "ed" or "ddcb". Empty for no prefix.
+    pub prefix: &'static str,
+    pub code: u8,
+    /// The instruction, if the decoder has one
+    pub name: Option<String>,
+    pub status: OpcodeStatus,
+}
+
+/// Returns all the opcodes of the eZ80 decoder and how they are handled.
+///
+/// The DD and FD prefixed instructions not listed reuse the entry without
+/// prefix, with IX or IY instead of HL.
+pub fn ez80_coverage() -> Vec<OpcodeCoverage> {
+    let decoder = DecoderEZ80::new();
+    let mut coverage = Vec::new();
+    for (prefix, table) in decoder.tables().iter() {
+        let indexed_only = *prefix == "dd" || *prefix == "fd";
+        for (code, opcode) in table.iter().enumerate() {
+            if prefix.is_empty() && PREFIX_BYTES.contains(&(code as u8)) {
+                continue;
+            }
+            let (name, status) = match opcode {
+                Some(o) if o.is_invalid() => (Some(o.name.clone()), OpcodeStatus::Invalid),
+                Some(o) => (Some(o.name.clone()), OpcodeStatus::Implemented),
+                None if indexed_only => continue,
+                None => (None, OpcodeStatus::Undefined),
+            };
+            coverage.push(OpcodeCoverage {
+                prefix,
+                code: code as u8,
+                name,
+                status,
+            });
+        }
+    }
+    coverage
+}
+
+/// Writes the coverage as CSV with the columns opcode, status and name
+pub fn write_coverage_csv(out: &mut dyn Write, coverage: &[OpcodeCoverage]) -> io::Result<()> {
+    writeln!(out, "opcode,status,name")?;
+    for entry in coverage {
+        let name = entry.name.as_deref().unwrap_or("").replace('"', "\"\"");
+        writeln!(out, "{}{:02x},{},\"{}\"", entry.prefix, entry.code, entry.status, name)?;
+    }
+    Ok(())
+}
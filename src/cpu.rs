@@ -1,21 +1,51 @@
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+use super::debugger::*;
 use super::decoder_ez80::*;
 use super::decoder_z80::*;
 use super::decoder_8080::*;
 use super::environment::*;
+use super::iodevice::*;
+use super::lockstep::*;
 use super::machine::*;
+use super::memtiming::*;
 use super::opcode::*;
 use super::registers::*;
+use super::scheduler::*;
 use super::state::*;
+use super::tracer::*;
+use super::translation::*;
+
+// Calls deeper than this forget the outermost frames
+const MAX_STACK_FRAMES: usize = 1024;
+
+/// Code run by the embedder instead of, or before, the instruction at
+/// an address. See Cpu::set_pc_hook.
+pub type PcHook = Box<dyn FnMut(&mut Environment)>;
 
-const NMI_ADDRESS: u32 = 0x0066;
+/// Called on invalid opcodes with the registers before the
+/// instruction. Returns what to do with it. See Cpu::set_illegal_handler.
+pub type IllegalHandler = Box<dyn FnMut(&IllegalInstruction, &Registers) -> IllegalPolicy>;
 
 /// The Z80 cpu emulator.
 /// 
 /// Executes Z80 instructions changing the cpu State and Machine
 pub struct Cpu {
     pub state: State,
-    trace: bool,
+    tracer: Option<Box<dyn Tracer>>,
     decoder: Box<dyn Decoder>,
+    debugger: Debugger,
+    io_devices: Vec<IoSlot>,
+    pub(crate) translation: Option<Box<dyn AddressTranslation>>,
+    memory_regions: Vec<MemoryRegion>,
+    illegal_policy: IllegalPolicy,
+    illegal_handler: Option<IllegalHandler>,
+    last_illegal: Option<IllegalInstruction>,
+    // The illegal instruction stopped on, run by the next step
+    illegal_resume_pc: Option<u32>,
+    pc_hooks: HashMap<u32, PcHook>,
+    call_stack: VecDeque<StackFrame>,
 }
 
 pub(crate) trait Decoder {
@@ -31,33 +61,45 @@ impl Cpu {
 
     /// Returns a Z80 Cpu instance
     pub fn new_z80() -> Cpu {
-        Cpu {
-            state: State::new(),
-            trace: false,
-            decoder: Box::new(DecoderZ80::new())
-        }
+        Self::with_decoder(Box::new(DecoderZ80::new()))
     }
 
     pub fn new_ez80() -> Cpu {
-        Cpu {
-            state: State::new(),
-            trace: false,
-            decoder: Box::new(DecoderEZ80::new())
-        }
+        Self::with_decoder(Box::new(DecoderEZ80::new()))
     }
 
     /// Returns an Intel 8080 Cpu instance
     pub fn new_8080() -> Cpu {
-        let mut cpu = Cpu {
-            state: State::new(),
-            trace: false,
-            decoder: Box::new(Decoder8080::new())
-        };
-
+        let mut cpu = Self::with_decoder(Box::new(Decoder8080::new()));
         cpu.state.reg.set_8080();
         cpu
     }
 
+    fn with_decoder(decoder: Box<dyn Decoder>) -> Cpu {
+        Cpu {
+            state: State::new(),
+            tracer: None,
+            decoder,
+            debugger: Debugger::new(),
+            io_devices: Vec::new(),
+            translation: None,
+            memory_regions: Vec::new(),
+            illegal_policy: IllegalPolicy::Nop,
+            illegal_handler: None,
+            last_illegal: None,
+            illegal_resume_pc: None,
+            pc_hooks: HashMap::new(),
+            call_stack: VecDeque::new(),
+        }
+    }
+
+}
+
+fn push_frame(call_stack: &mut VecDeque<StackFrame>, frame: StackFrame) {
+    if call_stack.len() == MAX_STACK_FRAMES {
+        call_stack.pop_front();
+    }
+    call_stack.push_back(frame);
 }
 
 impl Default for Cpu {
@@ -73,63 +115,392 @@ impl Cpu {
     ///
     /// * `sys` - A representation of the emulated machine that has the Machine trait
     ///
-    pub fn execute_instruction(&mut self, sys: &mut dyn Machine) {
+    /// Returns what the instruction did. The status is
+    /// StepResult::Breakpoint when a breakpoint or a watchpoint pauses
+    /// the execution.
+    ///
+    pub fn execute_instruction(&mut self, sys: &mut dyn Machine) -> InstructionResult {
+        let result = self.execute_step(sys);
+        self.tick_devices(result.cycles);
+        result
+    }
+
+    fn tick_devices(&mut self, cycles: u32) {
+        let mut lines = CpuLines { state: &mut self.state };
+        for slot in self.io_devices.iter_mut() {
+            slot.device.tick(cycles, &mut lines);
+        }
+    }
+
+    fn execute_step(&mut self, sys: &mut dyn Machine) -> InstructionResult {
         if self.is_halted() {
             // The CPU is in HALT state. Only interrupts can execute.
-            return
+            // The clock keeps running, fetching NOPs that refresh R.
+            sys.use_cycles(1);
+            self.state.cycles += 1;
+            self.state.reg.refresh(1);
+            return InstructionResult {
+                cycles: 1,
+                halt: true,
+                ..Default::default()
+            }
         }
 
+        let start_cycles = self.state.cycles;
+
         let mut env = Environment::new(&mut self.state, sys);
+        env.translation = self.translation.as_deref();
+        env.memory_regions = &self.memory_regions;
+        if let Some(tracer) = self.tracer.as_deref_mut() {
+            env.set_tracer(Some(tracer));
+        }
         if env.state.reset_pending {
-            env.state.reset_pending = false;
-            env.state.nmi_pending = false;
-            env.state.halted = false;
-            env.state.set_pc(0x0000);
-            env.state.reg.set8(Reg8::I, 0x00);
-            env.state.reg.set8(Reg8::R, 0x00);
-            env.state.reg.set_interrupts(false);
-            env.state.reg.set_interrupt_mode(0);
+            env.state.reset();
+            self.call_stack.clear();
         }
         else if env.state.nmi_pending {
             env.state.nmi_pending = false;
-            env.state.halted = false;
-            env.state.reg.start_nmi();
-            env.subroutine_call(NMI_ADDRESS);
+            push_frame(&mut self.call_stack, StackFrame {
+                address: NMI_ADDRESS,
+                return_address: env.state.pc(),
+                adl: env.state.reg.adl,
+                interrupt: true,
+            });
+            env.nmi();
+        }
+        else if let Some(number) = env.state.active_irq() {
+            let return_address = env.state.pc();
+            let adl = env.state.reg.adl;
+            env.interrupt(number);
+            push_frame(&mut self.call_stack, StackFrame {
+                address: env.state.pc(),
+                return_address,
+                adl,
+                interrupt: true,
+            });
         }
 
         let pc = env.state.pc();
-        let opcode = self.decoder.decode(&mut env);
-        if self.trace {
-            print!("==> {:06x}: {:20}", pc, opcode.disasm(&env).0);
+        if self.debugger.check_breakpoint(pc, &env.state.reg) {
+            env.flush_cycles();
+            return InstructionResult {
+                cycles: (env.state.cycles - start_cycles) as u32,
+                status: StepResult::Breakpoint(BreakReason::Breakpoint(pc)),
+                ..Default::default()
+            }
         }
-        opcode.execute(&mut env);
+        env.watchpoints = &self.debugger.watchpoints;
+        env.io_devices = &mut self.io_devices;
+
+        let resuming = self.illegal_resume_pc.take() == Some(pc);
+        let start_pc = env.state.reg.pc;
+        let adl = env.state.reg.adl;
+        let mbase = env.state.reg.mbase;
+        let mut hooked = false;
+        if let Some(hook) = self.pc_hooks.get_mut(&pc) {
+            hook(&mut env);
+            // If the hook has changed PC, it has replaced the instruction
+            hooked = env.state.reg.pc != start_pc;
+        }
+
+        let mut illegal = false;
+        let mut asm = None;
+        if hooked {
+            env.state.ei_delay = false;
+        } else {
+            let opcode = self.decoder.decode(&mut env);
+            illegal = (self.illegal_handler.is_some() || self.illegal_policy != IllegalPolicy::Nop)
+                && opcode.is_invalid();
+            // Once stopped on, the instruction runs as a NOP on resume
+            if illegal && !resuming {
+                let instruction = IllegalInstruction {
+                    pc,
+                    bytes: env.bytes_to_pc(pc),
+                    instructions_executed: env.state.instructions_executed,
+                    backtrace: self.call_stack.iter().rev().copied().collect(),
+                };
+                let policy = match self.illegal_handler.as_mut() {
+                    Some(handler) => {
+                        let mut reg = env.state.reg.clone();
+                        reg.pc = start_pc;
+                        handler(&instruction, &reg)
+                    }
+                    None => self.illegal_policy,
+                };
+                self.last_illegal = Some(instruction);
+                if policy == IllegalPolicy::Stop {
+                    self.illegal_resume_pc = Some(pc);
+                    // Back to the start, the fetch cycles are not used
+                    env.state.reg.pc = start_pc;
+                    env.clear_index();
+                    env.state.clear_sz_prefix();
+                    env.trace_no_instruction(pc);
+                    return InstructionResult {
+                        cycles: (env.state.cycles - start_cycles) as u32,
+                        illegal: true,
+                        status: StepResult::Breakpoint(BreakReason::IllegalInstruction(pc)),
+                        ..Default::default()
+                    }
+                }
+            }
+            env.state.reg.refresh(env.opcode_fetches);
+            if env.is_traced() {
+                let (text, operand_len) = opcode.disasm(&env);
+                let mask = if env.state.reg.adl { 0xffffff } else { 0xffff };
+                let len = (env.state.pc().wrapping_sub(pc) & mask) + operand_len;
+                asm = Some((text, len));
+            }
+            // Interrupts enabled by EI are accepted after this instruction
+            env.state.ei_delay = false;
+            opcode.execute(&mut env);
+        }
+
+        // The hooks and the instructions end the same way
+        env.flush_cycles();
         env.clear_index();
         env.state.clear_sz_prefix();
         env.state.instructions_executed += 1;
-        env.state.reg.set8(Reg8::R, env.state.reg.get8(Reg8::R).wrapping_add(1));
-
-        if self.trace {
-            print!(" PC:{:06x} AF:{:04x} BC:{:06x} DE:{:06x} HL:{:06x} SPS:{:04x} SPL:{:06x} IX:{:06x} IY:{:06x} MB {:02x} ADL {:01x} MADL {:01x} tick {}",
-                self.state.pc(),
-                self.state.reg.get16(Reg16::AF),
-                self.state.reg.get24(Reg16::BC),
-                self.state.reg.get24(Reg16::DE),
-                self.state.reg.get24(Reg16::HL),
-                self.state.reg.get16(Reg16::SP),
-                self.state.reg.get24(Reg16::SP),
-                self.state.reg.get24(Reg16::IX),
-                self.state.reg.get24(Reg16::IY),
-                self.state.reg.mbase,
-                self.state.reg.adl as i32,
-                self.state.reg.madl as i32,
-                self.state.instructions_executed,
-            );
-            println!();
-            /*println!(" [{:02x} {:02x} {:02x} {:02x}]", sys.peek(pc),
-                sys.peek(pc.wrapping_add(1)),
-                sys.peek(pc.wrapping_add(2)),
-                sys.peek(pc.wrapping_add(3)));*/
+        let mut result = InstructionResult {
+            cycles: (env.state.cycles - start_cycles) as u32,
+            branch: hooked || env.call || env.ret || env.state.reg.pc != env.next_pc,
+            call: env.call,
+            ret: env.ret,
+            halt: env.state.halted,
+            io: env.io,
+            illegal,
+            status: StepResult::Continue,
+        };
+        if let Some(reason) = env.watch_hit.get() {
+            result.status = StepResult::Breakpoint(reason);
         }
+        if env.call {
+            let return_address = if adl {
+                env.next_pc
+            } else {
+                ((mbase as u32) << 16) + (env.next_pc & 0xffff)
+            };
+            push_frame(&mut self.call_stack, StackFrame {
+                address: env.state.pc(),
+                return_address,
+                adl,
+                interrupt: false,
+            });
+        } else if env.ret {
+            self.call_stack.pop_back();
+        }
+
+        if let Some((asm, len)) = asm {
+            env.trace_instruction(pc, asm, len);
+            if env.call {
+                env.trace_subroutine_call(env.state.pc());
+            }
+        } else if hooked {
+            env.trace_no_instruction(pc);
+        }
+
+        result
+    }
+
+    /// Executes instructions until at least the given bus cycles are
+    /// used, or a breakpoint or watchpoint stops the execution. The
+    /// last instruction can go over the budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `sys` - A representation of the emulated machine that has the Machine trait
+    /// * `cycles` - The budget of bus cycles
+    ///
+    pub fn run_for_cycles(&mut self, sys: &mut dyn Machine, cycles: u64) -> RunResult {
+        let mut run = RunResult::default();
+        let start_instructions = self.state.instructions_executed;
+        while run.cycles < cycles {
+            if self.is_halted() {
+                // Nothing to do until an interrupt, advance the clock
+                let idle = cycles - run.cycles;
+                self.state.cycles += idle;
+                self.state.reg.refresh((idle % 0x80) as u32);
+                // use_cycles and tick take at most u32::MAX cycles at a time
+                let mut pending = idle;
+                while pending > 0 {
+                    let step = pending.min(u32::MAX as u64) as u32;
+                    sys.use_cycles(step);
+                    self.tick_devices(step);
+                    pending -= step as u64;
+                }
+                run.cycles = cycles;
+                break;
+            }
+            let result = self.execute_instruction(sys);
+            run.cycles += result.cycles as u64;
+            run.status = result.status;
+            if result.status != StepResult::Continue {
+                break;
+            }
+        }
+        run.instructions = self.state.instructions_executed - start_instructions;
+        run.halt = self.is_halted();
+        run
+    }
+
+    /// Executes instructions up to the cycle of the next event of the
+    /// scheduler, or for limit cycles if there are none. Stops early on
+    /// breakpoints and watchpoints. The last instruction can go over
+    /// the event cycle, like with run_for_cycles.
+    pub fn run_to_event<E>(&mut self, sys: &mut dyn Machine, scheduler: &Scheduler<E>, limit: u64) -> RunResult {
+        let cycles = match scheduler.next_at() {
+            Some(at) => at.saturating_sub(self.state.cycles).min(limit),
+            None => limit,
+        };
+        self.run_for_cycles(sys, cycles)
+    }
+
+    /// Executes instructions until the condition is true, a breakpoint
+    /// or watchpoint stops the execution, or limit instructions are
+    /// executed. The condition is checked before each instruction.
+    ///
+    /// # Arguments
+    ///
+    /// * `sys` - A representation of the emulated machine that has the Machine trait
+    /// * `condition` - Predicate over the cpu and the machine
+    /// * `limit` - Maximum number of steps. A step waiting on HALT counts.
+    ///
+    pub fn run_until<F>(&mut self, sys: &mut dyn Machine, mut condition: F, limit: u64) -> RunResult
+            where F: FnMut(&Cpu, &dyn Machine) -> bool {
+        let mut run = RunResult::default();
+        let start_instructions = self.state.instructions_executed;
+        for _ in 0..limit {
+            if condition(self, &*sys) {
+                run.condition = true;
+                break;
+            }
+            let result = self.execute_instruction(sys);
+            run.cycles += result.cycles as u64;
+            run.status = result.status;
+            if result.status != StepResult::Continue {
+                break;
+            }
+        }
+        if !run.condition && run.status == StepResult::Continue {
+            run.condition = condition(self, &*sys);
+        }
+        run.instructions = self.state.instructions_executed - start_instructions;
+        run.halt = self.is_halted();
+        run
+    }
+
+    /// Executes up to fuel instructions, to bound the time spent in the
+    /// guest on each host frame. Stops early on breakpoints, watchpoints
+    /// and HALT. The fuel is used up if the result has status Continue
+    /// and halt false.
+    ///
+    /// # Arguments
+    ///
+    /// * `sys` - A representation of the emulated machine that has the Machine trait
+    /// * `fuel` - Maximum number of instructions executed
+    ///
+    pub fn execute_with_fuel(&mut self, sys: &mut dyn Machine, fuel: u64) -> RunResult {
+        let mut run = RunResult::default();
+        let start_instructions = self.state.instructions_executed;
+        while self.state.instructions_executed - start_instructions < fuel && !self.is_halted() {
+            let result = self.execute_instruction(sys);
+            run.cycles += result.cycles as u64;
+            run.status = result.status;
+            if result.status != StepResult::Continue {
+                break;
+            }
+        }
+        run.instructions = self.state.instructions_executed - start_instructions;
+        run.halt = self.is_halted();
+        run
+    }
+
+    /// Executes instructions in lockstep with another core, comparing
+    /// the registers every interval instructions and at the end. Stops
+    /// at the first difference, on breakpoints and watchpoints, or after
+    /// limit instructions.
+    ///
+    /// # Arguments
+    ///
+    /// * `sys` - A representation of the emulated machine that has the Machine trait
+    /// * `reference` - The core to compare with, started in the same state
+    /// * `compare` - Returns true if the registers of the reference and of the Cpu match
+    /// * `interval` - Instructions executed between comparisons
+    /// * `limit` - Maximum number of instructions executed
+    ///
+    pub fn run_lockstep<F>(&mut self, sys: &mut dyn Machine, reference: &mut dyn ReferenceCore,
+            mut compare: F, interval: u64, limit: u64) -> Result<RunResult, Box<Divergence>>
+            where F: FnMut(&Registers, &Registers) -> bool {
+        let mut run = RunResult::default();
+        let start_instructions = self.state.instructions_executed;
+        let start_reference_cycles = reference.cycles();
+        let interval = interval.max(1);
+        for n in 1..=limit {
+            let pc = self.state.pc();
+            let result = self.execute_instruction(sys);
+            reference.step();
+            run.cycles += result.cycles as u64;
+            run.status = result.status;
+            if result.status != StepResult::Continue || n == limit || n % interval == 0 {
+                let expected = reference.registers();
+                let expected_cycles = reference.cycles()
+                    .zip(start_reference_cycles)
+                    .map(|(cycles, start)| cycles.saturating_sub(start));
+                if !compare(&expected, &self.state.reg)
+                        || expected_cycles.is_some_and(|cycles| cycles != run.cycles) {
+                    return Err(Box::new(Divergence {
+                        instructions: self.state.instructions_executed - start_instructions,
+                        pc,
+                        expected,
+                        actual: self.state.reg.clone(),
+                        expected_cycles,
+                        actual_cycles: run.cycles,
+                    }));
+                }
+            }
+            if result.status != StepResult::Continue {
+                break;
+            }
+        }
+        run.instructions = self.state.instructions_executed - start_instructions;
+        run.halt = self.is_halted();
+        Ok(run)
+    }
+
+    /// Runs up to the instruction at start, then measures the cycles and
+    /// instructions used until PC reaches stop. Useful to benchmark a
+    /// routine of the guest. The condition of the result is true if the
+    /// measure is complete. Each phase gets up to limit steps, like
+    /// run_until.
+    ///
+    /// # Arguments
+    ///
+    /// * `sys` - A representation of the emulated machine that has the Machine trait
+    /// * `start` - Address of the first instruction measured
+    /// * `stop` - Address of the instruction after the last one measured
+    /// * `limit` - Maximum number of steps of each phase
+    ///
+    pub fn measure(&mut self, sys: &mut dyn Machine, start: u32, stop: u32, limit: u64) -> RunResult {
+        let run = self.run_until(sys, |cpu, _| cpu.state.pc() == start, limit);
+        if !run.condition {
+            return run;
+        }
+
+        // The first instruction is run apart, so that start can be stop
+        let start_instructions = self.state.instructions_executed;
+        let first = self.execute_instruction(sys);
+        if first.status != StepResult::Continue {
+            return RunResult {
+                cycles: first.cycles as u64,
+                instructions: self.state.instructions_executed - start_instructions,
+                halt: self.is_halted(),
+                status: first.status,
+                condition: false,
+            };
+        }
+        let mut run = self.run_until(sys, |cpu, _| cpu.state.pc() == stop, limit);
+        run.cycles += first.cycles as u64;
+        run.instructions = self.state.instructions_executed - start_instructions;
+        run
     }
 
     /// Returns the instrction in PC disassembled. PC is advanced.
@@ -140,20 +511,209 @@ impl Cpu {
     ///  
     pub fn disasm_instruction(&mut self, sys: &mut dyn Machine) -> String {
         let mut env = Environment::new(&mut self.state, sys);
+        env.translation = self.translation.as_deref();
         let opcode = self.decoder.decode(&mut env);
         let (asm, pc_inc) = opcode.disasm(&env);
         for _ in 0..pc_inc { env.advance_pc(); }
         asm
     }
 
+    /// Returns the instruction at address disassembled and its length
+    /// in bytes, prefixes included. The state of the cpu is not
+    /// modified.
+    pub(crate) fn disasm_at(&self, sys: &mut dyn Machine, adl: bool, address: u32) -> (String, usize) {
+        let mut state = self.state.clone();
+        state.reg.adl = adl;
+        state.reg.mbase = (address >> 16) as u8;
+        state.set_pc(address);
+        state.clear_sz_prefix();
+        state.index = Reg16::HL;
+
+        let mut env = Environment::new(&mut state, sys);
+        env.translation = self.translation.as_deref();
+        let opcode = self.decoder.decode(&mut env);
+        let (asm, pc_inc) = opcode.disasm(&env);
+        let mask = if adl { 0xffffff } else { 0xffff };
+        let decoded = env.state.reg.pc.wrapping_sub(address) & mask;
+        (asm, (decoded + pc_inc) as usize)
+    }
+
     /// Activates or deactivates traces of the instruction executed and
-    /// the state of the registers.
+    /// the state of the registers on stdout. It replaces the tracer
+    /// installed with set_tracer.
     /// 
     /// # Arguments
     /// 
     /// * `trace` - A bool defining the trace state to set
     pub fn set_trace(&mut self, trace: bool) {
-        self.trace = trace;
+        if trace {
+            self.set_tracer(LogTracer::new(std::io::stdout()));
+        } else {
+            self.clear_tracer();
+        }
+    }
+
+    /// Installs a receiver of the execution events
+    pub fn set_tracer<T: Tracer + 'static>(&mut self, tracer: T) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    /// Removes the tracer
+    pub fn clear_tracer(&mut self) {
+        self.tracer = None;
+    }
+
+    /// Attaches a device to the IO bus. The accesses to the ports in the
+    /// range go to the device instead of the Machine, with wait_states
+    /// extra cycles each. A range below $0100 claims on-chip ports, that
+    /// no longer reach Machine::internal_port_in and internal_port_out.
+    pub fn add_io_device<D: IoDevice + 'static>(&mut self, ports: Range<u16>, wait_states: u32, device: D) {
+        self.io_devices.push(IoSlot {
+            ports,
+            wait_states,
+            device: Box::new(device),
+        });
+    }
+
+    /// Detaches the device in the port range
+    pub fn remove_io_device(&mut self, ports: Range<u16>) {
+        self.io_devices.retain(|s| s.ports != ports);
+    }
+
+    /// Adds wait_states extra cycles to each access to the memory in
+    /// the range, like on external RAM or flash. The range is of cpu
+    /// addresses. With overlapping ranges, the first one added applies.
+    pub fn add_memory_wait_states(&mut self, range: Range<u32>, wait_states: u32) {
+        self.memory_regions.push(MemoryRegion {
+            range,
+            wait_states,
+        });
+    }
+
+    /// Removes the wait states of all the memory regions
+    pub fn clear_memory_wait_states(&mut self) {
+        self.memory_regions.clear();
+    }
+
+    /// Enables the emulator services on the ports port to port+6, or
+    /// disables them with None. They let the guest detect the emulator,
+    /// read the clock and ask the host for services:
+    ///
+    /// * IN port returns $5a
+    /// * OUT port latches state.cycles, or the host time in milliseconds
+    ///   if the value is 1, see set_host_clock
+    /// * IN port+1 to port+6 return the latched value, the low byte first
+    /// * OUT port+1 writes a byte to the console, see take_services_output
+    /// * OUT port+2 stops the execution with BreakReason::Exit(value)
+    /// * OUT port+3 stops the execution with BreakReason::Snapshot
+    ///
+    /// They take precedence over the Machine and the IoDevices.
+    pub fn set_services_port(&mut self, port: Option<u16>) {
+        self.state.services_port = port;
+    }
+
+    /// Sets the source of the host time of the emulator services. With
+    /// HostClock::Virtual, two runs with the same input behave the
+    /// same.
+    pub fn set_host_clock(&mut self, clock: HostClock) {
+        self.state.host_clock = clock;
+    }
+
+    /// Returns the bytes written to the console of the emulator services
+    /// since the last call
+    pub fn take_services_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.state.services_output)
+    }
+
+    /// Installs a translation of the cpu addresses to the addresses of
+    /// the Machine
+    pub fn set_address_translation<T: AddressTranslation + 'static>(&mut self, translation: T) {
+        self.translation = Some(Box::new(translation));
+    }
+
+    /// Removes the address translation, the cpu addresses reach the
+    /// Machine unchanged
+    pub fn clear_address_translation(&mut self) {
+        self.translation = None;
+    }
+
+    /// Requests a maskable interrupt. It is accepted if enabled, and not
+    /// right after EI: like on the hardware, the instruction after EI
+    /// is executed first. Returns whether it was accepted, a device
+    /// keeping its interrupt line active should request it again after
+    /// the next instruction.
+    ///
+    /// Equivalent to Environment::interrupt, but reported to the tracer.
+    pub fn interrupt(&mut self, sys: &mut dyn Machine, number: u32) -> bool {
+        let mut env = Environment::new(&mut self.state, sys);
+        env.translation = self.translation.as_deref();
+        if let Some(tracer) = self.tracer.as_deref_mut() {
+            env.set_tracer(Some(tracer));
+        }
+        env.memory_regions = &self.memory_regions;
+        let return_address = env.state.pc();
+        let adl = env.state.reg.adl;
+        let accepted = env.interrupt(number);
+        if accepted {
+            push_frame(&mut self.call_stack, StackFrame {
+                address: env.state.pc(),
+                return_address,
+                adl,
+                interrupt: true,
+            });
+        }
+        accepted
+    }
+
+    /// Returns the subroutines and interrupt handlers in progress, the
+    /// innermost first. They are tracked on CALL, RST, interrupts and
+    /// returns, code changing the stack in other ways can confuse it.
+    pub fn backtrace(&self) -> Vec<StackFrame> {
+        self.call_stack.iter().rev().copied().collect()
+    }
+
+    /// Calls the hook when PC reaches the address, before the
+    /// instruction there. If the hook changes PC, for example with
+    /// Environment::subroutine_return to emulate a firmware call, the
+    /// instruction is not executed. Otherwise it is executed after the
+    /// hook. Replaces the previous hook on the address.
+    pub fn set_pc_hook(&mut self, address: u32, hook: PcHook) {
+        self.pc_hooks.insert(address, hook);
+    }
+
+    pub fn remove_pc_hook(&mut self, address: u32) {
+        self.pc_hooks.remove(&address);
+    }
+
+    /// Selects what happens on invalid opcodes. By default they are
+    /// executed as NOP.
+    pub fn set_illegal_policy(&mut self, policy: IllegalPolicy) {
+        self.illegal_policy = policy;
+    }
+
+    /// Installs a handler called on each invalid opcode. The policy it
+    /// returns applies instead of the one of set_illegal_policy.
+    pub fn set_illegal_handler(&mut self, handler: IllegalHandler) {
+        self.illegal_handler = Some(handler);
+    }
+
+    pub fn clear_illegal_handler(&mut self) {
+        self.illegal_handler = None;
+    }
+
+    /// Returns the last invalid opcode found with IllegalPolicy::Skip,
+    /// IllegalPolicy::Stop or a handler. To know how the guest got there, install a
+    /// RingTracer.
+    pub fn last_illegal_instruction(&self) -> Option<&IllegalInstruction> {
+        self.last_illegal.as_ref()
+    }
+
+    /// Runs up to iterations of the repeated block instructions LDIR,
+    /// LDDR, INIR, INDR, OTIR and OTDR in each step, to copy large blocks
+    /// faster. The cycles used are the same, but interrupts and
+    /// breakpoints are checked less often. The default is 1.
+    pub fn set_block_burst(&mut self, iterations: u32) {
+        self.state.block_burst = iterations.max(1);
     }
 
     /// Set eZ80 ADL state
@@ -166,20 +726,98 @@ impl Cpu {
         &mut self.state.reg
     }
 
+    /// Returns a Registers struct to read the Z80 registers
+    pub fn registers_ref(&self) -> &Registers {
+        &self.state.reg
+    }
+
     /// Returns if the Cpu has executed a HALT
     pub fn is_halted(&self) -> bool {
-        self.state.halted && !self.state.nmi_pending && !self.state.reset_pending
+        self.state.is_halted()
+    }
+
+    /// Activates the interrupt line of the vector number. Like a level
+    /// triggered line, the interrupt is accepted before each instruction
+    /// while it is enabled, until deassert_irq. With several lines
+    /// active, the lowest vector number has priority.
+    pub fn assert_irq(&mut self, number: u32) {
+        self.state.irq_lines.insert(number);
+    }
+
+    /// Deactivates the interrupt line of the vector number, usually when
+    /// the handler acknowledges the device
+    pub fn deassert_irq(&mut self, number: u32) {
+        self.state.irq_lines.remove(&number);
     }
 
-    /// Non maskable interrupt request
+    /// Non maskable interrupt request. It is accepted before the next
+    /// instruction.
     pub fn signal_nmi(&mut self) {
         self.state.nmi_pending = true
     }
 
-    /// Signal reset
+    /// Signal reset. It is applied before the next instruction.
     pub fn signal_reset(&mut self) {
         self.state.reset_pending = true
     }
+
+    /// Resets the cpu now, see State::reset
+    pub fn reset(&mut self) {
+        self.state.reset();
+        self.call_stack.clear();
+    }
+
+    /// Accepts a non maskable interrupt now: the execution continues
+    /// at $0066. IFF1 is saved in IFF2 to be restored by RETN.
+    pub fn nmi(&mut self, sys: &mut dyn Machine) {
+        let mut env = Environment::new(&mut self.state, sys);
+        env.translation = self.translation.as_deref();
+        env.memory_regions = &self.memory_regions;
+        if let Some(tracer) = self.tracer.as_deref_mut() {
+            env.set_tracer(Some(tracer));
+        }
+        push_frame(&mut self.call_stack, StackFrame {
+            address: NMI_ADDRESS,
+            return_address: env.state.pc(),
+            adl: env.state.reg.adl,
+            interrupt: true,
+        });
+        env.state.nmi_pending = false;
+        env.nmi();
+    }
+
+    /// Pauses the execution before running the instruction in [address]
+    pub fn add_breakpoint(&mut self, address: u32) {
+        self.debugger.add_breakpoint(address, None);
+    }
+
+    /// Pauses the execution before running the instruction in [address]
+    /// if the condition on the registers is true at that point
+    pub fn add_conditional_breakpoint<F>(&mut self, address: u32, condition: F)
+            where F: Fn(&Registers) -> bool + 'static {
+        self.debugger.add_breakpoint(address, Some(Box::new(condition)));
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u32) {
+        self.debugger.remove_breakpoint(address);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debugger.clear_breakpoints();
+    }
+
+    /// Pauses the execution after an instruction accesses memory in [range]
+    pub fn add_watchpoint(&mut self, range: Range<u32>, kind: WatchKind) {
+        self.debugger.add_watchpoint(range, kind);
+    }
+
+    pub fn remove_watchpoint(&mut self, range: Range<u32>) {
+        self.debugger.remove_watchpoint(range);
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.debugger.clear_watchpoints();
+    }
 }
 
 
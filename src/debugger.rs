@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::registers::*;
+
+/// Outcome of Cpu::execute_instruction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct InstructionResult {
+    /// Bus cycles used by the instruction
+    pub cycles: u32,
+    /// PC was not advanced to the next instruction: a jump, call,
+    /// return or a repeated block instruction
+    pub branch: bool,
+    /// A subroutine was called with CALL or RST
+    pub call: bool,
+    /// A subroutine returned with RET, RETI or RETN
+    pub ret: bool,
+    /// The cpu is halted
+    pub halt: bool,
+    /// Ports were read or written
+    pub io: bool,
+    /// The opcode is invalid. Only reported with an illegal handler or
+    /// a policy other than IllegalPolicy::Nop.
+    pub illegal: bool,
+    /// Whether the execution can continue
+    pub status: StepResult,
+}
+
+/// Outcome of Cpu::run_for_cycles and Cpu::run_until
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunResult {
+    /// Bus cycles used
+    pub cycles: u64,
+    /// Instructions executed
+    pub instructions: u64,
+    /// The cpu is halted at the end of the run
+    pub halt: bool,
+    /// Whether a breakpoint has stopped the run before the budget was used
+    pub status: StepResult,
+    /// The condition of run_until was met
+    pub condition: bool,
+}
+
+/// Whether a breakpoint has paused the execution
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StepResult {
+    /// The execution can continue
+    #[default]
+    Continue,
+    /// The execution has been paused to return control to the embedder
+    Breakpoint(BreakReason),
+}
+
+/// Why the execution was paused
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakReason {
+    /// PC reached a breakpoint. The instruction there has not been
+    /// executed, it will be on the next call to execute_instruction.
+    Breakpoint(u32),
+    /// The instruction just executed read a watched address
+    Read(u32),
+    /// The instruction just executed wrote a watched address
+    Write(u32),
+    /// The instruction at the address is invalid and has not been
+    /// executed, with IllegalPolicy::Stop
+    IllegalInstruction(u32),
+    /// The guest asked to exit with a status code, through the
+    /// emulator services
+    Exit(u8),
+    /// The guest asked for a snapshot, through the emulator services
+    Snapshot,
+}
+
+/// What the cpu does on an invalid opcode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IllegalPolicy {
+    /// Execute it as a NOP, like the hardware
+    #[default]
+    Nop,
+    /// Execute it as a NOP and record it, see Cpu::last_illegal_instruction
+    Skip,
+    /// Record it and pause before executing it. Like on a breakpoint,
+    /// the next step executes it, as a NOP.
+    Stop,
+}
+
+/// An invalid opcode found by the cpu
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IllegalInstruction {
+    /// Address of the instruction, prefixes included
+    pub pc: u32,
+    /// The bytes of the instruction, prefixes included
+    pub bytes: Vec<u8>,
+    /// Instructions executed before it
+    pub instructions_executed: u64,
+    /// The subroutines and handlers in progress, see Cpu::backtrace
+    pub backtrace: Vec<StackFrame>,
+}
+
+/// A subroutine or interrupt handler in progress, see Cpu::backtrace
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackFrame {
+    /// Entry of the subroutine or handler
+    pub address: u32,
+    /// Where the execution continues on return
+    pub return_address: u32,
+    /// ADL mode of the caller
+    pub adl: bool,
+    /// Entered by an interrupt or NMI instead of CALL or RST
+    pub interrupt: bool,
+}
+
+/// Memory accesses that trigger a watchpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Watchpoint {
+    range: Range<u32>,
+    kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub(crate) fn on_read(&self, address: u32) -> bool {
+        self.kind != WatchKind::Write && self.range.contains(&address)
+    }
+
+    pub(crate) fn on_write(&self, address: u32) -> bool {
+        self.kind != WatchKind::Read && self.range.contains(&address)
+    }
+}
+
+type Condition = dyn Fn(&Registers) -> bool;
+
+pub(crate) struct Debugger {
+    breakpoints: HashMap<u32, Option<Box<Condition>>>,
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    // Address of the breakpoint that has just stopped the execution. It
+    // is ignored once to be able to resume.
+    resume_pc: Option<u32>,
+}
+
+impl Debugger {
+    pub(crate) fn new() -> Debugger {
+        Debugger {
+            breakpoints: HashMap::new(),
+            watchpoints: Vec::new(),
+            resume_pc: None,
+        }
+    }
+
+    pub(crate) fn add_breakpoint(&mut self, address: u32, condition: Option<Box<Condition>>) {
+        self.breakpoints.insert(address, condition);
+    }
+
+    pub(crate) fn remove_breakpoint(&mut self, address: u32) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub(crate) fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.resume_pc = None;
+    }
+
+    pub(crate) fn add_watchpoint(&mut self, range: Range<u32>, kind: WatchKind) {
+        self.watchpoints.push(Watchpoint { range, kind });
+    }
+
+    pub(crate) fn remove_watchpoint(&mut self, range: Range<u32>) {
+        self.watchpoints.retain(|w| w.range != range);
+    }
+
+    pub(crate) fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    /// Returns true if the execution has to stop before the instruction in pc
+    pub(crate) fn check_breakpoint(&mut self, pc: u32, reg: &Registers) -> bool {
+        if self.breakpoints.is_empty() {
+            return false;
+        }
+        if self.resume_pc.take() == Some(pc) {
+            return false;
+        }
+
+        let hit = match self.breakpoints.get(&pc) {
+            None => false,
+            Some(None) => true,
+            Some(Some(condition)) => condition(reg),
+        };
+        if hit {
+            self.resume_pc = Some(pc);
+        }
+        hit
+    }
+}
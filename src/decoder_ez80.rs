@@ -31,7 +31,7 @@ pub struct DecoderEZ80 {
 
 impl Decoder for DecoderEZ80 {
     fn decode(&self, env: &mut Environment) -> &Opcode {
-        let mut b0 = env.advance_pc();
+        let mut b0 = env.advance_opcode();
 
         // Process prefixes even if reapeated
         loop {
@@ -42,7 +42,7 @@ impl Decoder for DecoderEZ80 {
                 0x5B => env.state.sz_prefix = SizePrefix::LIL,
                 _ => break,
             }
-            b0 = env.advance_pc();
+            b0 = env.advance_opcode();
         }
         loop {
             match b0 {
@@ -50,7 +50,7 @@ impl Decoder for DecoderEZ80 {
                 0xfd => env.set_index(Reg16::IY),
                 _ => break,
             }
-            b0 = env.advance_pc();
+            b0 = env.advance_opcode();
         }
         
         let opcode = match b0 {
@@ -59,12 +59,12 @@ impl Decoder for DecoderEZ80 {
                     env.load_displacement();
                     &self.prefix_cb_indexed[env.advance_pc() as usize]
                 } else {
-                    &self.prefix_cb[env.advance_pc() as usize]
+                    &self.prefix_cb[env.advance_opcode() as usize]
                 }
             },
             0xed => {
                 env.clear_index(); // With ed, the current prefix is ignored
-                &self.prefix_ed[env.advance_pc() as usize]
+                &self.prefix_ed[env.advance_opcode() as usize]
             },
             // XXX hack. should put all dd, fd opcodes in this table
             0x0f | 0x1f | 0x2f | 0x07 | 0x17 | 0x27 | 0x31 | 0x37 | 0x3e | 0x3f | 0x86
@@ -98,6 +98,19 @@ impl Decoder for DecoderEZ80 {
 }
 
 impl DecoderEZ80 {
+    /// The decoding tables with the prefix bytes that select them. The
+    /// DD and FD tables only have the few eZ80 specific opcodes.
+    pub(crate) fn tables(&self) -> [(&'static str, &[Option<Opcode>; 256]); 6] {
+        [
+            ("", &self.no_prefix),
+            ("cb", &self.prefix_cb),
+            ("ddcb", &self.prefix_cb_indexed),
+            ("ed", &self.prefix_ed),
+            ("dd", &self.prefix_dd),
+            ("fd", &self.prefix_fd),
+        ]
+    }
+
     pub fn new() -> DecoderEZ80 {
 
         let mut decoder = DecoderEZ80 {
@@ -447,12 +460,12 @@ impl DecoderEZ80 {
                         _ => Some(build_out0_n_r(R[p.y])),
                     }
                     2 => match p.p {
-                        0 | 1 | 2 => Some(build_lea_rr_ind_offset(RP[p.p], Reg16::IX)),
+                        0..=2 => Some(build_lea_rr_ind_offset(RP[p.p], Reg16::IX)),
                         3 => Some(build_lea_rr_ind_offset(Reg16::IX, Reg16::IX)),
                         _ => Some(build_noni_nop()), // Invalid instruction NONI + NOP
                     },
                     3 => match p.p {
-                        0 | 1 | 2 => Some(build_lea_rr_ind_offset(RP[p.p], Reg16::IY)),
+                        0..=2 => Some(build_lea_rr_ind_offset(RP[p.p], Reg16::IY)),
                         3 => Some(build_lea_rr_ind_offset(Reg16::IY, Reg16::IY)),
                         _ => Some(build_noni_nop()), // Invalid instruction NONI + NOP
                     },
@@ -493,7 +506,7 @@ impl DecoderEZ80 {
                         1 | 3 | 5 | 7 => Some(build_mlt_rr(RP[p.p])),
                         2 => Some(build_lea_rr_ind_offset(Reg16::IX, Reg16::IY)),
                         4 => Some(build_tst_a_n()),
-                        6 => Some(build_log_unimplemented("0x74: TSTIO n")),
+                        6 => Some(build_tstio_n()),
                         _ => Some(build_neg()), // NEG
                     },
                     5 => match p.y {
@@ -507,7 +520,7 @@ impl DecoderEZ80 {
                     6 => match p.y {
                         4 => Some(build_pea(Reg16::IY)),
                         5 => Some(build_ld_a_mb()),
-                        6 => Some(build_log_unimplemented("SLP")), // 0x76
+                        6 => Some(build_slp()), // 0x76
                         7 => Some(build_rsmix()),
                         _ => Some(build_im(IM[p.y])) // IM #
                     }
@@ -534,43 +547,40 @@ impl DecoderEZ80 {
                             3 => Some(build_out_block(BLI_A[p.y-4])), // Block OUTxx
                             _ => panic!("Unreacheable")
                         }
+                    } else if p.z == 2 {
+                        // y < 4, the bli rows are above
+                        Some(build_in_block_m(BLI_M[p.y])) // 0x82, 0x8a, 0x92, 0x9a
                     } else if p.z == 3 {
-                        match p.y {
-                            0 => Some(build_log_unimplemented("OTIM")), // 0x83
-                            1 => Some(build_log_unimplemented("OTDM")), // 0x8b
-                            2 => Some(build_log_unimplemented("OTIMR")), // 0x93
-                            3 => Some(build_log_unimplemented("OTDMR")), // 0x9b
-                            _ => Some(build_noni_nop()),
-                        }
+                        Some(build_out_block_m(BLO_M[p.y])) // 0x83, 0x8b, 0x93, 0x9b
                     } else if p.z == 4 {
                         match p.y {
-                            0 => Some(build_log_unimplemented("INI2")), // 0x84
-                            1 => Some(build_log_unimplemented("IND2")), // 0x8c
-                            2 => Some(build_log_unimplemented("INI2R")), // 0x94
-                            3 => Some(build_log_unimplemented("IND2R")), // 0x9c
-                            4 => Some(build_log_unimplemented("OUTI2")), // 0xa4
-                            5 => Some(build_log_unimplemented("OUTD2")), // 0xac
-                            6 => Some(build_log_unimplemented("OUTI2R")), // 0xb4
-                            7 => Some(build_log_unimplemented("OTD2R")), // 0xbc
-                            _ => Some(build_noni_nop()),
+                            0 => Some(build_in_block_2((true, "INI2"))), // 0x84
+                            1 => Some(build_in_block_2((false, "IND2"))), // 0x8c
+                            2 => Some(build_in_block_de((true, true, "INI2R"))), // 0x94
+                            3 => Some(build_in_block_de((false, true, "IND2R"))), // 0x9c
+                            4 => Some(build_out_block_2((true, "OUTI2"))), // 0xa4
+                            5 => Some(build_out_block_2((false, "OUTD2"))), // 0xac
+                            6 => Some(build_out_block_de((true, true, "OTI2R"))), // 0xb4
+                            7 => Some(build_out_block_de((false, true, "OTD2R"))), // 0xbc
+                            _ => panic!("Unreacheable")
                         }
                     } else {
                         Some(build_noni_nop()) // NONI + NOP
                     },
                 3 => match p.z {
                     2 => match p.y {
-                        0 => Some(build_log_unimplemented("INIRX")), // 0xc2
-                        1 => Some(build_log_unimplemented("INDRX")), // 0xca
+                        0 => Some(build_in_block_de((true, false, "INIRX"))), // 0xc2
+                        1 => Some(build_in_block_de((false, false, "INDRX"))), // 0xca
                         _ => Some(build_noni_nop()), // Invalid instruction NONI + NOP
                     }
                     3 => match p.y {
-                        0 => Some(build_log_unimplemented("OTIRX")), // 0xc3
-                        1 => Some(build_log_unimplemented("OTDRX")), // 0xcb
+                        0 => Some(build_out_block_de((true, false, "OTIRX"))), // 0xc3
+                        1 => Some(build_out_block_de((false, false, "OTDRX"))), // 0xcb
                         _ => Some(build_noni_nop()), // Invalid instruction NONI + NOP
                     }
                     7 => match p.y {
-                        0 => Some(build_log_unimplemented("ld i,hl")),
-                        2 => Some(build_log_unimplemented("ld hl,i")),
+                        0 => Some(build_ld_i_hl()), // 0xc7
+                        2 => Some(build_ld_hl_i()), // 0xd7
                         _ => Some(build_noni_nop()), // Invalid instruction NONI + NOP
                     },
                     _ => Some(build_noni_nop()), // Invalid instruction NONI + NOP
@@ -686,12 +696,17 @@ pub const BLI_A: [(bool, bool, &str); 4] = [
     (false, true, "DR")
 ];
 
-pub fn build_log_unimplemented(name: &'static str) -> Opcode {
-    Opcode {
-        name: name.to_string(),
-        action: Box::new(move |_: &mut Environment| {
-            println!("Unimplemented opcode: {}", name);
-        })
-    }
-}
+pub const BLI_M: [(bool, bool, &str); 4] = [
+    (true,  false, "INIM"),
+    (false, false, "INDM"),
+    (true,  true, "INIMR"),
+    (false, true, "INDMR")
+];
+
+pub const BLO_M: [(bool, bool, &str); 4] = [
+    (true,  false, "OTIM"),
+    (false, false, "OTDM"),
+    (true,  true, "OTIMR"),
+    (false, true, "OTDMR")
+];
 
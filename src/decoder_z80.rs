@@ -26,18 +26,18 @@ pub struct DecoderZ80 {
 
 impl Decoder for DecoderZ80 {
     fn decode(&self, env: &mut Environment) -> &Opcode {
-        let mut b0 = env.advance_pc();
+        let mut b0 = env.advance_opcode();
 
         // Process prefixes even if reapeated
         while b0 == 0xdd || b0 == 0xfd {
             if b0 == 0xdd {
                 // DD prefix
                 env.set_index(Reg16::IX);
-                b0 = env.advance_pc()
+                b0 = env.advance_opcode()
             } else {
                 // FD prefix
                 env.set_index(Reg16::IY);
-                b0 = env.advance_pc()
+                b0 = env.advance_opcode()
             }
         }
         
@@ -47,12 +47,12 @@ impl Decoder for DecoderZ80 {
                     env.load_displacement();
                     &self.prefix_cb_indexed[env.advance_pc() as usize]
                 } else {
-                    &self.prefix_cb[env.advance_pc() as usize]
+                    &self.prefix_cb[env.advance_opcode() as usize]
                 }
             },
             0xed => {
                 env.clear_index(); // With ed, the current prefix is ignored
-                &self.prefix_ed[env.advance_pc() as usize]
+                &self.prefix_ed[env.advance_opcode() as usize]
             },
             _ => {
                 if self.has_displacement[b0 as usize] && env.is_alt_index() {
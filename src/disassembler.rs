@@ -10,6 +10,18 @@ pub struct Disasm {
     pub bytes: Vec<u8>
 }
 
+/**
+ * Disassemble the instruction at an address.
+ *
+ * Returns the text of the instruction, with the .SIS/.LIS/.SIL/.LIL
+ * suffix if any, and its length in bytes. Uses the ADL mode of the
+ * cpu unless adl_override is given. The cpu state is not modified.
+ */
+pub fn disassemble_instruction(machine: &mut dyn Machine, cpu: &Cpu, adl_override: Option<bool>, address: u32) -> (String, usize) {
+    let adl = adl_override.unwrap_or(cpu.state.reg.adl);
+    cpu.disasm_at(machine, adl, address)
+}
+
 /**
  * Disassemble a section of code.
  *
@@ -36,6 +48,7 @@ pub fn disassemble(machine: &mut dyn Machine, cpu: &mut Cpu, adl_override: Optio
         {
             let opcode_end = cpu.state.pc();
             let mut env = Environment::new(&mut cpu.state, machine);
+            env.translation = cpu.translation.as_deref();
             env.state.reg.pc = opcode_start;
             while env.state.reg.pc != opcode_end {
                 instruction_bytes.push(env.advance_pc());
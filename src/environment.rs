@@ -1,17 +1,163 @@
+use std::cell::{Cell, RefCell};
+
+use super::debugger::{BreakReason, Watchpoint};
+use super::iodevice::*;
 use super::machine::*;
+use super::memtiming::*;
 use super::registers::*;
 use super::state::{ State, SizePrefix };
+use super::tracer::*;
+use super::translation::*;
+
+pub(crate) const NMI_ADDRESS: u32 = 0x0066;
+
+// The emulator services: the id and 6 bytes of the latch
+const SERVICES_PORTS: u16 = 7;
+const SERVICES_ID: u8 = 0x5a;
+const SERVICES_HOST_TIME: u8 = 0x01;
 
 pub struct Environment<'a> {
     pub state: &'a mut State,
-    pub sys: &'a mut dyn Machine
+    pub sys: &'a mut dyn Machine,
+    // bus cycles used since the last flush to state.cycles
+    cycles: Cell<u32>,
+    pub(crate) watchpoints: &'a [Watchpoint],
+    pub(crate) watch_hit: Cell<Option<BreakReason>>,
+    // PC after the last byte fetched, to detect jumps
+    pub(crate) next_pc: u32,
+    pub(crate) call: bool,
+    pub(crate) ret: bool,
+    pub(crate) io: bool,
+    tracer: RefCell<Option<&'a mut dyn Tracer>>,
+    pub(crate) io_devices: &'a mut [IoSlot],
+    pub(crate) translation: Option<&'a dyn AddressTranslation>,
+    pub(crate) memory_regions: &'a [MemoryRegion],
+    // Opcode fetches (M1 cycles) of the instruction decoded, for R
+    pub(crate) opcode_fetches: u32,
 }
 
-impl <'a> Environment<'_> {
+impl <'a> Environment<'a> {
     pub fn new(state: &'a mut State, sys: &'a mut dyn Machine) -> Environment<'a> {
         Environment {
             state,
-            sys
+            sys,
+            cycles: Cell::new(0),
+            watchpoints: &[],
+            watch_hit: Cell::new(None),
+            next_pc: 0,
+            call: false,
+            ret: false,
+            io: false,
+            tracer: RefCell::new(None),
+            io_devices: &mut [],
+            translation: None,
+            memory_regions: &[],
+            opcode_fetches: 0,
+        }
+    }
+
+    pub(crate) fn set_tracer(&mut self, tracer: Option<&'a mut dyn Tracer>) {
+        self.tracer = RefCell::new(tracer);
+    }
+
+    pub(crate) fn is_traced(&self) -> bool {
+        self.tracer.borrow().is_some()
+    }
+
+    fn trace<F: FnOnce(&mut dyn Tracer)>(&self, event: F) {
+        if let Some(tracer) = self.tracer.borrow_mut().as_mut() {
+            event(*tracer);
+        }
+    }
+
+    pub(crate) fn trace_instruction(&self, pc: u32, asm: String, len: u32) {
+        self.trace(|t| t.instruction(&InstructionTrace {
+            pc,
+            len,
+            asm,
+            reg: self.state.reg.clone(),
+            instructions_executed: self.state.instructions_executed,
+            cycles: self.state.cycles,
+        }));
+    }
+
+    pub(crate) fn trace_no_instruction(&self, pc: u32) {
+        self.trace(|t| t.no_instruction(pc, self.state.cycles));
+    }
+
+    pub(crate) fn trace_interrupt(&self, address: u32) {
+        self.trace(|t| t.interrupt(address));
+    }
+
+    pub(crate) fn trace_subroutine_call(&self, address: u32) {
+        self.trace(|t| t.subroutine_call(address));
+    }
+
+    /// Adds the bus cycles used so far to the virtual clock in state.cycles
+    pub(crate) fn flush_cycles(&mut self) {
+        self.state.cycles += self.cycles.take() as u64;
+    }
+
+    /// Uses extra cycles not related to a bus access
+    pub fn use_cycles(&self, cycles: u32) {
+        self.cycles.set(self.cycles.get() + cycles);
+        self.sys.use_cycles(cycles);
+    }
+
+    // Access to the Machine memory, through the address translation
+    fn sys_peek(&self, address: u32) -> u8 {
+        match self.translation {
+            None => self.sys.peek(address),
+            Some(t) => self.sys.peek(t.translate(address)),
+        }
+    }
+
+    fn sys_poke(&mut self, address: u32, value: u8) {
+        match self.translation {
+            None => self.sys.poke(address, value),
+            Some(t) => self.sys.poke(t.translate(address), value),
+        }
+    }
+
+    // Every memory or port access takes a bus cycle, plus the wait
+    // states of the memory region. Those are extra cycles for the
+    // Machine, like the ones of use_cycles.
+    fn use_bus_cycle(&self, address: u32) {
+        let wait_states = wait_states(self.memory_regions, address);
+        self.cycles.set(self.cycles.get() + 1 + wait_states);
+        if wait_states > 0 {
+            self.sys.use_cycles(wait_states);
+        }
+    }
+
+    fn fetch(&self, address: u32) -> u8 {
+        self.use_bus_cycle(address);
+        self.sys_peek(address)
+    }
+
+    fn read(&self, address: u32) -> u8 {
+        if self.watchpoints.iter().any(|w| w.on_read(address)) {
+            self.watch(BreakReason::Read(address));
+        }
+        self.use_bus_cycle(address);
+        let value = self.sys_peek(address);
+        self.trace(|t| t.memory_read(address, value));
+        value
+    }
+
+    fn write(&mut self, address: u32, value: u8) {
+        if self.watchpoints.iter().any(|w| w.on_write(address)) {
+            self.watch(BreakReason::Write(address));
+        }
+        self.use_bus_cycle(address);
+        self.trace(|t| t.memory_write(address, value));
+        self.sys_poke(address, value);
+    }
+
+    // Only the first watchpoint hit by an instruction is reported
+    fn watch(&self, reason: BreakReason) {
+        if self.watch_hit.get().is_none() {
+            self.watch_hit.set(Some(reason));
         }
     }
 
@@ -32,88 +178,149 @@ impl <'a> Environment<'_> {
         }
     }
 
-    pub fn interrupt(&mut self, number: u32) -> () {
-        if self.state.reg.get_iff1() {
-            let vector_address = ((self.state.reg.get8(Reg8::I) as u32) << 8) + number;
-            let vector = self.peek16(vector_address) as u32;
-
-            self.state.reg.set_interrupts(false);
-            if self.state.reg.madl {
-                let pc = self.state.pc();
-                if self.state.reg.adl {
-                    self.push(pc);
-                    self.push_byte_spl(3);
-                    self.state.set_pc(vector);
-                } else {
-                    self.push_byte_spl((pc >> 8) as u8);
-                    self.push_byte_spl(pc as u8);
-                    self.push_byte_spl(2);
-                    self.state.reg.adl = true;
-                    self.state.set_pc(vector);
-                }
+    /// Accepts a maskable interrupt if enabled. Returns false if it was
+    /// not accepted, the device should keep requesting it.
+    pub fn interrupt(&mut self, number: u32) -> bool {
+        if !self.state.accepts_interrupt() {
+            return false;
+        }
+        let vector_address = ((self.state.reg.get_i16() as u32) << 8) + number;
+        let vector = self.peek16(vector_address) as u32;
+
+        self.state.reg.set_interrupts(false);
+        self.interrupt_call(vector);
+        true
+    }
+
+    /// Accepts a non maskable interrupt, jumping to $0066
+    pub(crate) fn nmi(&mut self) {
+        self.state.reg.start_nmi();
+        self.interrupt_call(NMI_ADDRESS);
+    }
+
+    // With MADL set, the handler runs in ADL mode and the ADL mode of
+    // the interrupted code is pushed, as in CALL.IL
+    fn interrupt_call(&mut self, vector: u32) {
+        self.state.halted = false;
+        // The acknowledge cycle refreshes R like an opcode fetch
+        self.state.reg.refresh(1);
+        if self.state.reg.madl {
+            let pc = self.state.pc();
+            if self.state.reg.adl {
+                self.push(pc);
+                self.push_byte_spl(3);
+                self.state.set_pc(vector);
             } else {
-                self.subroutine_call(vector);
+                self.push_byte_spl((pc >> 8) as u8);
+                self.push_byte_spl(pc as u8);
+                self.push_byte_spl(2);
+                self.state.reg.adl = true;
+                self.state.set_pc(vector);
             }
+        } else {
+            self.subroutine_call(vector);
         }
+        self.trace_interrupt(vector);
+        self.flush_cycles();
     }
 
     pub fn peek(&self, address: u32) -> u8 {
-        self.sys.peek(address)
+        self.read(address)
     }
 
     /// Sets the memory content to [value] in [address]
     pub fn poke(&mut self, address: u32, value: u8) {
-        self.sys.poke(address, value);
+        self.write(address, value);
     }
 
     /// Returns the memory contents in [address] as word
     pub fn peek16(&self, address: u32) -> u16 {
-        self.sys.peek(address) as u16
-        + ((self.sys.peek(self.wrap_address(address, 1)) as u16) << 8)
+        self.read(address) as u16
+        + ((self.read(self.wrap_address(address, 1)) as u16) << 8)
     }
 
     /// Sets the memory content to the word [value] in [address]
     pub fn poke16(&mut self, address: u32, value: u16) {
-        self.sys.poke(address, value as u8 );
-        self.sys.poke(self.wrap_address(address, 1), (value >> 8) as u8);
+        self.write(address, value as u8 );
+        self.write(self.wrap_address(address, 1), (value >> 8) as u8);
     }
 
     pub fn peek24(&self, address: u32) -> u32 {
-        self.sys.peek(address) as u32
-        + ((self.sys.peek(self.wrap_address(address, 1)) as u32) << 8)
-        + ((self.sys.peek(self.wrap_address(address, 2)) as u32) << 16)
+        self.read(address) as u32
+        + ((self.read(self.wrap_address(address, 1)) as u32) << 8)
+        + ((self.read(self.wrap_address(address, 2)) as u32) << 16)
     }
 
     pub fn poke24(&mut self, address: u32, value: u32) {
-        self.sys.poke(address, value as u8 );
-        self.sys.poke(self.wrap_address(address, 1), (value >> 8) as u8);
-        self.sys.poke(self.wrap_address(address, 2), (value >> 16) as u8);
+        self.write(address, value as u8 );
+        self.write(self.wrap_address(address, 1), (value >> 8) as u8);
+        self.write(self.wrap_address(address, 2), (value >> 16) as u8);
+    }
+
+    // The bytes from start to the PC, without using bus cycles
+    pub(crate) fn bytes_to_pc(&self, start: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut address = start;
+        while address != self.state.pc() && bytes.len() < 8 {
+            bytes.push(self.sys_peek(address));
+            address = self.wrap_address(address, 1);
+        }
+        bytes
     }
 
+    // Whether a repeated block instruction of instruction_len bytes can
+    // do another iteration in the same step. A watchpoint hit stops the
+    // burst. The fetch cycles, the refresh of R and the instruction
+    // count of the iteration are charged as if it was its own step.
+    pub(crate) fn continue_block(&mut self, iterations: u32, instruction_len: i32) -> bool {
+        if iterations >= self.state.block_burst || self.watch_hit.get().is_some() {
+            return false;
+        }
+        let pc = self.state.pc();
+        for i in -instruction_len..0 {
+            self.use_bus_cycle(self.wrap_address(pc, i));
+        }
+        self.state.reg.refresh(self.opcode_fetches);
+        self.state.instructions_executed += 1;
+        true
+    }
+
+    // The peek*_pc functions are used by the disassembler. They
+    // look ahead without using bus cycles.
     pub fn peek_pc(&self) -> u8 {
         let pc = self.state.pc();
-        self.sys.peek(pc)
+        self.sys_peek(pc)
+    }
+
+    /// Fetches a prefix or opcode byte, in an M1 cycle that refreshes R
+    pub(crate) fn advance_opcode(&mut self) -> u8 {
+        self.opcode_fetches += 1;
+        self.advance_pc()
     }
 
     pub fn advance_pc(&mut self) -> u8 {
         let pc = self.state.pc();
-        let value = self.sys.peek(pc);
+        let value = self.fetch(pc);
         if self.state.reg.adl {
             self.state.set_pc(self.wrap_address24(pc, 1));
         } else {
             self.state.set_pc(self.wrap_address16(pc, 1));
         }
+        self.next_pc = self.state.reg.pc;
         value
     }
 
     pub fn peek16_pc(&self) -> u16 {
         let pc = self.state.pc();
-        self.peek16(pc)
+        self.sys_peek(pc) as u16
+        + ((self.sys_peek(self.wrap_address(pc, 1)) as u16) << 8)
     }
 
     pub fn peek24_pc(&self) -> u32 {
         let pc = self.state.pc();
-        self.peek24(pc)
+        self.sys_peek(pc) as u32
+        + ((self.sys_peek(self.wrap_address(pc, 1)) as u32) << 8)
+        + ((self.sys_peek(self.wrap_address(pc, 2)) as u32) << 16)
     }
 
     pub fn advance_immediate16(&mut self) -> u16 {
@@ -153,26 +360,26 @@ impl <'a> Environment<'_> {
 
     pub fn push_byte_sps(&mut self, value: u8) {
         let sps = self.wrap_address16( self.state.reg.get16_mbase(Reg16::SP), -1);
-        self.sys.poke(sps, value);
+        self.write(sps, value);
         self.state.reg.set16(Reg16::SP, sps as u16);
     }
 
     pub fn pop_byte_sps(&mut self) -> u8 {
         let sps = self.state.reg.get16_mbase(Reg16::SP);
-        let l = self.sys.peek(sps);
+        let l = self.read(sps);
         self.state.reg.set16(Reg16::SP, self.wrap_address16(sps, 1) as u16);
         l
     }
 
     pub fn push_byte_spl(&mut self, value: u8) {
         let spl = self.wrap_address24( self.state.reg.get24(Reg16::SP), -1);
-        self.sys.poke(spl, value);
+        self.write(spl, value);
         self.state.reg.set24(Reg16::SP, spl);
     }
 
     pub fn pop_byte_spl(&mut self) -> u8 {
         let spl = self.state.reg.get24(Reg16::SP);
-        let l = self.sys.peek(spl);
+        let l = self.read(spl);
         self.state.reg.set24(Reg16::SP, self.wrap_address24(spl, 1));
         l
     }
@@ -216,6 +423,7 @@ impl <'a> Environment<'_> {
     }
 
     pub fn subroutine_return(&mut self) {
+        self.ret = true;
         if self.state.reg.adl {
             match self.state.sz_prefix {
                 SizePrefix::None => {
@@ -349,7 +557,7 @@ impl <'a> Environment<'_> {
 
     pub fn reg8_ext(& self, reg: Reg8) -> u8 {
         if reg == Reg8::_HL {
-            self.sys.peek(self.index_address())
+            self.read(self.index_address())
         } else {
             self.state.reg.get8(self.translate_reg(reg))
         }
@@ -383,7 +591,7 @@ impl <'a> Environment<'_> {
 
     pub fn set_reg(&mut self, reg: Reg8, value: u8) {
         if reg == Reg8::_HL {
-            self.sys.poke(self.index_address(), value);
+            self.write(self.index_address(), value);
         } else {
             self.state.reg.set8(self.translate_reg(reg), value);
         }
@@ -421,11 +629,89 @@ impl <'a> Environment<'_> {
         }
     }
 
+    // Offset of address in the emulator services ports, if it is there
+    fn services_offset(&self, address: u16) -> Option<u16> {
+        let base = self.state.services_port?;
+        let offset = address.wrapping_sub(base);
+        if offset < SERVICES_PORTS {
+            Some(offset)
+        } else {
+            None
+        }
+    }
+
+    fn services_in(&self, address: u16) -> Option<u8> {
+        match self.services_offset(address)? {
+            0 => Some(SERVICES_ID),
+            offset => Some((self.state.services_latch >> (8 * (offset - 1))) as u8),
+        }
+    }
+
+    fn services_out(&mut self, address: u16, value: u8) -> bool {
+        match self.services_offset(address) {
+            Some(0) => {
+                let cycles = self.state.cycles + self.cycles.get() as u64;
+                self.state.services_latch = if value != SERVICES_HOST_TIME {
+                    cycles
+                } else {
+                    self.state.host_clock.millis(cycles)
+                };
+                true
+            }
+            Some(1) => {
+                self.state.services_output.push(value);
+                true
+            }
+            Some(2) => {
+                self.watch(BreakReason::Exit(value));
+                true
+            }
+            Some(3) => {
+                self.watch(BreakReason::Snapshot);
+                true
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
     pub fn port_in(&mut self, address: u16) -> u8 {
-        self.sys.port_in(address)
+        self.cycles.set(self.cycles.get() + 1);
+        self.io = true;
+        let value = if let Some(value) = self.services_in(address) {
+            value
+        } else if let Some(slot) = find_slot(self.io_devices, address) {
+            // Extra cycles for the Machine, like the memory wait states
+            self.cycles.set(self.cycles.get() + slot.wait_states);
+            self.sys.use_cycles(slot.wait_states);
+            slot.device.port_in(address)
+        } else if address <= 0xff {
+            // The on-chip peripherals of the eZ80 are mapped on the ports
+            // with the upper byte of the address zero.
+            self.sys.internal_port_in(address as u8)
+        } else {
+            self.sys.port_in(address)
+        };
+        self.trace(|t| t.port_read(address, value));
+        value
     }
 
     pub fn port_out(&mut self, address: u16, value: u8) {
-        self.sys.port_out(address, value);
+        self.cycles.set(self.cycles.get() + 1);
+        self.io = true;
+        self.trace(|t| t.port_write(address, value));
+        if self.services_out(address, value) {
+            // Handled by the emulator services
+        } else if let Some(slot) = find_slot(self.io_devices, address) {
+            self.cycles.set(self.cycles.get() + slot.wait_states);
+            self.sys.use_cycles(slot.wait_states);
+            slot.device.port_out(address, value);
+        } else if address <= 0xff {
+            // The on-chip peripherals of the eZ80 are mapped on the ports
+            // with the upper byte of the address zero.
+            self.sys.internal_port_out(address as u8, value);
+        } else {
+            self.sys.port_out(address, value);
+        }
     }
 }
@@ -0,0 +1,50 @@
+//! Support for fuzzing the instruction decoders
+//!
+//! Runs arbitrary bytes as code on a RamMachine of 64 KiB, that has no
+//! host I/O, with each of the cpu models. A malformed instruction must
+//! never panic. The cargo-fuzz targets in fuzz/ call these functions.
+//!
+//! ```
+//! ez80::fuzz::execute(&[0xed, 0xff, 0xdd, 0xcb, 0x00, 0x00]);
+//! ez80::fuzz::disassemble(&[0xed, 0xff, 0xdd, 0xcb, 0x00, 0x00]);
+//! ```
+
+use crate::cpu::Cpu;
+use crate::disassembler;
+use crate::machine::RamMachine;
+
+/// Instructions executed on each cpu model
+pub const MAX_INSTRUCTIONS: u64 = 1000;
+
+const RAM_SIZE: usize = 0x10000;
+
+// The 8080, the Z80, and the eZ80 in Z80 and ADL modes
+fn cpus() -> [Cpu; 4] {
+    let mut adl = Cpu::new_ez80();
+    adl.state.reg.adl = true;
+    [Cpu::new_8080(), Cpu::new_z80(), Cpu::new_ez80(), adl]
+}
+
+fn machine(code: &[u8]) -> RamMachine {
+    let mut sys = RamMachine::new(RAM_SIZE);
+    sys.load(0, &code[..code.len().min(RAM_SIZE)]);
+    sys
+}
+
+/// Executes code, loaded at address 0, on each cpu model for up to
+/// MAX_INSTRUCTIONS instructions
+pub fn execute(code: &[u8]) {
+    for mut cpu in cpus() {
+        let mut sys = machine(code);
+        cpu.execute_with_fuel(&mut sys, MAX_INSTRUCTIONS);
+    }
+}
+
+/// Disassembles code, loaded at address 0, with each cpu model
+pub fn disassemble(code: &[u8]) {
+    let end = code.len().min(RAM_SIZE) as u32;
+    for mut cpu in cpus() {
+        let mut sys = machine(code);
+        disassembler::disassemble(&mut sys, &mut cpu, None, 0, end);
+    }
+}
@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+use super::state::State;
+
+/// A device on the IO bus, like an expansion card or an on-chip
+/// peripheral of the eZ80
+///
+/// Install it with Cpu::add_io_device. To inspect a device while it is
+/// installed, share it as an `Rc<RefCell<T>>`, that implements IoDevice
+/// as well.
+pub trait IoDevice {
+    /// Port in, from the device to the CPU
+    fn port_in(&mut self, address: u16) -> u8;
+    /// Port out, from the CPU to the device
+    fn port_out(&mut self, address: u16, value: u8);
+    /// Called after each instruction, and while the cpu is halted, with
+    /// the bus cycles used. To keep the timers and counters of the
+    /// device in step with the cpu, and drive its interrupt lines.
+    fn tick(&mut self, _cycles: u32, _lines: &mut CpuLines) {}
+}
+
+impl<T: IoDevice> IoDevice for Rc<RefCell<T>> {
+    fn port_in(&mut self, address: u16) -> u8 {
+        self.borrow_mut().port_in(address)
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        self.borrow_mut().port_out(address, value);
+    }
+    fn tick(&mut self, cycles: u32, lines: &mut CpuLines) {
+        self.borrow_mut().tick(cycles, lines);
+    }
+}
+
+/// The interrupt and reset lines of the cpu, driven by the devices in
+/// IoDevice::tick. They act like the Cpu functions of the same names.
+pub struct CpuLines<'a> {
+    pub(crate) state: &'a mut State,
+}
+
+impl CpuLines<'_> {
+    /// See Cpu::assert_irq
+    pub fn assert_irq(&mut self, number: u32) {
+        self.state.irq_lines.insert(number);
+    }
+
+    /// See Cpu::deassert_irq
+    pub fn deassert_irq(&mut self, number: u32) {
+        self.state.irq_lines.remove(&number);
+    }
+
+    /// See Cpu::signal_nmi
+    pub fn signal_nmi(&mut self) {
+        self.state.nmi_pending = true;
+    }
+
+    /// See Cpu::signal_reset
+    pub fn signal_reset(&mut self) {
+        self.state.reset_pending = true;
+    }
+}
+
+pub(crate) struct IoSlot {
+    pub(crate) ports: Range<u16>,
+    // Extra cycles used on each access
+    pub(crate) wait_states: u32,
+    pub(crate) device: Box<dyn IoDevice>,
+}
+
+pub(crate) fn find_slot(slots: &mut [IoSlot], address: u16) -> Option<&mut IoSlot> {
+    slots.iter_mut().find(|s| s.ports.contains(&address))
+}
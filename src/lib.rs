@@ -38,16 +38,31 @@
 //! ```
 
 
+mod chipselect;
+mod coverage;
 mod cpu;
+mod debugger;
+mod loader;
+mod lockstep;
 mod machine;
+mod memtiming;
+mod prt;
 mod registers;
+mod rtc;
+mod scheduler;
 mod state;
+mod symbols;
+mod tracer;
+mod translation;
+mod uart;
+mod watchdog;
 
 
 mod decoder_ez80;
 mod decoder_z80;
 mod decoder_8080;
 mod environment;
+mod iodevice;
 mod opcode;
 mod opcode_alu;
 mod opcode_arith;
@@ -58,10 +73,32 @@ mod opcode_ld;
 mod operators;
 
 pub mod disassembler;
-pub mod z80_mem_tools;
+pub mod mem;
+/// Former name of the mem module
+pub use mem as z80_mem_tools;
+#[cfg(feature = "selftest")]
+pub mod selftest;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
 
-pub use cpu::Cpu;
+pub use chipselect::{ChipSelect, ChipSelects};
+pub use coverage::{ez80_coverage, write_coverage_csv, OpcodeCoverage, OpcodeStatus};
+pub use cpu::{Cpu, IllegalHandler, PcHook};
+pub use debugger::{BreakReason, IllegalInstruction, IllegalPolicy, InstructionResult, RunResult, StackFrame, StepResult, WatchKind};
+pub use loader::{crc32, elf_symbols, load_program, load_program_checked, load_program_file, Program, ProgramFormat};
+pub use lockstep::{Divergence, ReferenceCore};
 pub use machine::Machine;
 pub use machine::PlainMachine;
+pub use machine::{MemoryView, RamMachine, SharedRamMachine, UnmappedMemory};
+pub use prt::Prt;
 pub use registers::*;
+pub use rtc::{Rtc, RtcTime};
 pub use environment::Environment;
+pub use iodevice::{CpuLines, IoDevice};
+pub use translation::{AddressTranslation, PageMap};
+pub use uart::Uart;
+pub use watchdog::{Watchdog, WatchdogAction, WatchdogCallback};
+pub use scheduler::Scheduler;
+pub use state::HostClock;
+pub use symbols::Symbols;
+pub use tracer::{CodeCoverage, CoverageRange, InstructionTrace, IoAccess, IoCallback, IoLog, JsonTracer, LogTracer, MemoryProfiler, OverflowPolicy, PageCounts, PortCounts, RingTracer, ThreadedTracer, Tracer};
@@ -0,0 +1,332 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::cpu::Cpu;
+use super::machine::Machine;
+use super::symbols::Symbols;
+
+// Agon MOS executables are loaded and started at $040000
+const MOS_LOAD_ADDRESS: u32 = 0x040000;
+// "MOS", version and ADL flag
+const MOS_HEADER_OFFSET: usize = 0x40;
+// Size of the 24 bit address space
+const ADDRESS_SPACE: u32 = 0x1000000;
+
+/// Format of the programs read by load_program
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramFormat {
+    /// Intel HEX records, with the 24 bit addresses of the extended
+    /// linear address records. The entry point is the start address
+    /// record, or the lowest address.
+    IntelHex,
+    /// Raw binary, loaded and started at the address. It runs in ADL
+    /// mode if the address is over $ffff.
+    Binary(u32),
+    /// Agon MOS executable, a binary with a header at offset $40
+    /// telling if it runs in ADL mode
+    MosBin,
+    /// 32 bit little endian ELF executable, like the output of the
+    /// LLVM-ez80 toolchain. The loadable segments are copied to their
+    /// physical address.
+    Elf,
+}
+
+/// A program loaded in memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Program {
+    /// Lowest address written
+    pub start: u32,
+    /// Address after the highest one written
+    pub end: u32,
+    /// Where the execution starts
+    pub entry: u32,
+    /// Whether the program starts in ADL mode
+    pub adl: bool,
+    /// CRC-32 of the file, to identify the build in bug reports
+    pub checksum: u32,
+}
+
+/// Copies a program to the memory of the machine. Returns where it
+/// has been loaded or the reason the data is not valid.
+pub fn load_program(machine: &mut dyn Machine, data: &[u8], format: ProgramFormat) -> Result<Program, String> {
+    let mut program = match format {
+        ProgramFormat::IntelHex => load_intel_hex(machine, data),
+        ProgramFormat::Binary(address) => load_binary(machine, data, address, address > 0xffff),
+        ProgramFormat::MosBin => {
+            let header = data.get(MOS_HEADER_OFFSET..MOS_HEADER_OFFSET + 5)
+                .filter(|h| &h[0..3] == b"MOS")
+                .ok_or_else(|| "no MOS header at offset $40".to_string())?;
+            let adl = header[4] != 0;
+            load_binary(machine, data, MOS_LOAD_ADDRESS, adl)
+        }
+        ProgramFormat::Elf => load_elf(machine, data),
+    }?;
+    program.checksum = crc32(data);
+    Ok(program)
+}
+
+/// Like load_program, but the file must have the expected CRC-32, as
+/// published with the build. Nothing is loaded when it does not match.
+pub fn load_program_checked(machine: &mut dyn Machine, data: &[u8], format: ProgramFormat, checksum: u32) -> Result<Program, String> {
+    let actual = crc32(data);
+    if actual != checksum {
+        return Err(format!("checksum mismatch: expected {:08x}, found {:08x}", checksum, actual));
+    }
+    load_program(machine, data, format)
+}
+
+/// Returns the CRC-32, as in zip and PNG, of a program file
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 }
+        })
+    })
+}
+
+/// Returns the symbols of the symbol table of an ELF file: functions,
+/// objects and labels
+pub fn elf_symbols(data: &[u8]) -> Result<Symbols, String> {
+    let elf = Elf::parse(data)?;
+    let mut symbols = Symbols::new();
+    for i in 0..elf.shnum {
+        let section = elf.shoff + i * elf.shentsize;
+        if elf.u32(section + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let offset = elf.u32(section + 16)? as usize;
+        let size = elf.u32(section + 20)? as usize;
+        let entsize = (elf.u32(section + 36)? as usize).max(ELF_SYM_SIZE);
+        let strtab = elf.shoff + elf.u32(section + 24)? as usize * elf.shentsize;
+        let strings = elf.u32(strtab + 16)? as usize;
+
+        for sym in (offset..offset + size).step_by(entsize) {
+            let name = elf.u32(sym)? as usize;
+            let value = elf.u32(sym + 4)?;
+            let kind = elf.u8(sym + 12)? & 0x0f;
+            let shndx = elf.u16(sym + 14)?;
+            if name == 0 || shndx == 0 || kind > 2 {
+                // unnamed, undefined, sections and files
+                continue;
+            }
+            symbols.add(elf.string(strings + name)?, value);
+        }
+    }
+    Ok(symbols)
+}
+
+/// Reads a program file and copies it to the memory of the machine
+pub fn load_program_file<P: AsRef<Path>>(machine: &mut dyn Machine, path: P, format: ProgramFormat) -> io::Result<Program> {
+    let data = fs::read(path)?;
+    load_program(machine, &data, format).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+impl Cpu {
+    /// Prepares the cpu to run a program: PC on the entry point, with
+    /// its ADL mode
+    pub fn start_program(&mut self, program: &Program) {
+        self.state.reg.adl = program.adl;
+        self.state.reg.mbase = (program.entry >> 16) as u8;
+        self.state.set_pc(if program.adl { program.entry } else { program.entry & 0xffff });
+    }
+}
+
+// Returns the address after a block of len bytes at address, if the
+// block fits in the 24 bit address space
+fn block_end(address: u32, len: usize) -> Option<u32> {
+    u32::try_from(len).ok()
+        .and_then(|len| address.checked_add(len))
+        .filter(|&end| end <= ADDRESS_SPACE)
+}
+
+fn load_binary(machine: &mut dyn Machine, data: &[u8], address: u32, adl: bool) -> Result<Program, String> {
+    let end = block_end(address, data.len())
+        .ok_or_else(|| format!("the program at ${:x} does not fit below $1000000", address))?;
+    for (a, &value) in (address..end).zip(data) {
+        machine.poke(a, value);
+    }
+    Ok(Program {
+        start: address,
+        end,
+        entry: address,
+        adl,
+        checksum: 0, // set by load_program
+    })
+}
+
+fn load_intel_hex(machine: &mut dyn Machine, data: &[u8]) -> Result<Program, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "Intel HEX is not text".to_string())?;
+    let mut base = 0u32;
+    let mut start = u32::MAX;
+    let mut end = 0u32;
+    let mut entry = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", number + 1, message);
+        let record = line.strip_prefix(':')
+            .and_then(parse_hex_bytes)
+            .ok_or_else(|| error("not an Intel HEX record"))?;
+        if record.len() < 5 || record.len() != record[0] as usize + 5 {
+            return Err(error("bad record length"));
+        }
+        if record.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(error("bad checksum"));
+        }
+        let offset = ((record[1] as u32) << 8) + record[2] as u32;
+        let payload = &record[4..record.len() - 1];
+        match record[3] {
+            0x00 => {
+                let address = base + offset;
+                let record_end = block_end(address, payload.len())
+                    .ok_or_else(|| error("data beyond $ffffff"))?;
+                for (a, &value) in (address..record_end).zip(payload) {
+                    machine.poke(a, value);
+                }
+                start = start.min(address);
+                end = end.max(record_end);
+            }
+            0x01 => break,
+            0x02 if payload.len() == 2 => base = (((payload[0] as u32) << 8) + payload[1] as u32) << 4,
+            0x04 if payload.len() == 2 => base = (((payload[0] as u32) << 8) + payload[1] as u32) << 16,
+            0x03 if payload.len() == 4 => {
+                // CS:IP, the only sensible use here is CS zero
+                entry = Some(((payload[2] as u32) << 8) + payload[3] as u32);
+            }
+            0x05 if payload.len() == 4 => {
+                entry = Some(payload.iter().fold(0, |a, b| (a << 8) + *b as u32));
+            }
+            _ => return Err(error("unsupported record")),
+        }
+    }
+
+    if start == u32::MAX {
+        return Err("no data in the Intel HEX file".to_string());
+    }
+    let entry = entry.unwrap_or(start);
+    Ok(Program {
+        start,
+        end,
+        entry,
+        adl: entry > 0xffff,
+        checksum: 0, // set by load_program
+    })
+}
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const ELF_SYM_SIZE: usize = 16;
+
+// Bounds checked reads of a 32 bit little endian ELF file
+struct Elf<'a> {
+    data: &'a [u8],
+    entry: u32,
+    phoff: usize,
+    phentsize: usize,
+    phnum: usize,
+    shoff: usize,
+    shentsize: usize,
+    shnum: usize,
+}
+
+impl<'a> Elf<'a> {
+    fn parse(data: &'a [u8]) -> Result<Elf<'a>, String> {
+        if data.len() < 52 || &data[0..4] != b"\x7fELF" {
+            return Err("not an ELF file".to_string());
+        }
+        if data[4] != 1 || data[5] != 1 {
+            return Err("only 32 bit little endian ELF files are supported".to_string());
+        }
+        let mut elf = Elf { data, entry: 0, phoff: 0, phentsize: 0, phnum: 0, shoff: 0, shentsize: 0, shnum: 0 };
+        elf.entry = elf.u32(24)?;
+        elf.phoff = elf.u32(28)? as usize;
+        elf.shoff = elf.u32(32)? as usize;
+        elf.phentsize = elf.u16(42)? as usize;
+        elf.phnum = elf.u16(44)? as usize;
+        elf.shentsize = elf.u16(46)? as usize;
+        elf.shnum = elf.u16(48)? as usize;
+        Ok(elf)
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8], String> {
+        offset.checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| format!("ELF file truncated at offset ${:x}", offset))
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8, String> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, String> {
+        let b = self.bytes(offset, 2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, String> {
+        let b = self.bytes(offset, 4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn string(&self, offset: usize) -> Result<&'a str, String> {
+        let rest = self.data.get(offset..).ok_or("ELF string out of the file")?;
+        let len = rest.iter().position(|&b| b == 0).ok_or("unterminated ELF string")?;
+        std::str::from_utf8(&rest[..len]).map_err(|_| "ELF string is not UTF-8".to_string())
+    }
+}
+
+fn load_elf(machine: &mut dyn Machine, data: &[u8]) -> Result<Program, String> {
+    let elf = Elf::parse(data)?;
+    let mut start = u32::MAX;
+    let mut end = 0u32;
+    for i in 0..elf.phnum {
+        let header = elf.phoff + i * elf.phentsize;
+        if elf.u32(header)? != PT_LOAD {
+            continue;
+        }
+        let offset = elf.u32(header + 4)? as usize;
+        let address = elf.u32(header + 12)?;
+        let filesz = elf.u32(header + 16)? as usize;
+        let memsz = elf.u32(header + 20)? as usize;
+        let bytes = elf.bytes(offset, filesz)?;
+        if memsz < filesz {
+            return Err(format!("ELF segment at ${:x} is smaller in memory than in the file", address));
+        }
+        let segment_end = block_end(address, memsz)
+            .ok_or_else(|| format!("ELF segment at ${:x} does not fit below $1000000", address))?;
+        // the memory not in the file, like .bss, is cleared
+        let contents = bytes.iter().copied().chain(std::iter::repeat(0));
+        for (a, value) in (address..segment_end).zip(contents) {
+            machine.poke(a, value);
+        }
+        if memsz > 0 {
+            start = start.min(address);
+            end = end.max(segment_end);
+        }
+    }
+
+    if start == u32::MAX {
+        return Err("no loadable segment in the ELF file".to_string());
+    }
+    Ok(Program {
+        start,
+        end,
+        entry: elf.entry,
+        adl: elf.entry > 0xffff,
+        checksum: 0, // set by load_program
+    })
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+
+use super::registers::*;
+
+/// Another cpu core to run in lockstep with Cpu::run_lockstep, like a
+/// reference implementation in C driven over FFI
+///
+/// The core runs on its own copy of the memory and ports.
+pub trait ReferenceCore {
+    /// Executes one instruction
+    fn step(&mut self);
+    /// Returns the registers of the core
+    fn registers(&self) -> Registers;
+    /// Returns the bus cycles elapsed, if the core counts them. The
+    /// cycles used by the two cores are compared then.
+    fn cycles(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// First difference found by Cpu::run_lockstep
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// Instructions executed in the run. The difference appeared in the
+    /// last interval instructions.
+    pub instructions: u64,
+    /// Address of the last instruction executed
+    pub pc: u32,
+    /// Registers of the reference core
+    pub expected: Registers,
+    /// Registers of the Cpu
+    pub actual: Registers,
+    /// Bus cycles used in the run by the reference core, if counted
+    pub expected_cycles: Option<u64>,
+    /// Bus cycles used in the run by the Cpu
+    pub actual_cycles: u64,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Divergence after {} instructions, at ${:06x}", self.instructions, self.pc)?;
+        writeln!(f, "Expected:\n{}", self.expected)?;
+        writeln!(f, "Actual:\n{}", self.actual)?;
+        if let Some(expected_cycles) = self.expected_cycles {
+            writeln!(f, "Cycles: expected {}, actual {}", expected_cycles, self.actual_cycles)?;
+        }
+        Ok(())
+    }
+}
@@ -1,3 +1,7 @@
+use std::cell::Cell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
 /// Abstraction of the device hosting the Z80 CPU
 /// 
 /// The device hosting the CPU has to provide implementations
@@ -46,12 +50,26 @@ pub trait Machine {
     /// Port out, from the CPU to the device. Sets a port value on
     /// the hosting device.
     fn port_out(&mut self, address: u16, value: u8);
+
+    /// Port in from the eZ80 on-chip peripherals. Used for the port
+    /// addresses $0000 to $00ff, that is IN0, TSTIO, the block
+    /// instructions OTIM/INIM... and IN with the upper byte of the
+    /// address zero. The other addresses are external IO cycles that
+    /// go to port_in. By default, both spaces are the same.
+    fn internal_port_in(&mut self, address: u8) -> u8 {
+        self.port_in(address as u16)
+    }
+
+    /// Port out to the eZ80 on-chip peripherals. See internal_port_in.
+    fn internal_port_out(&mut self, address: u8, value: u8) {
+        self.port_out(address as u16, value);
+    }
 }
 
 /// A simple Machine implementation
 /// 
-/// A minimum implementation of Machine. It uses two arrays of 65536 bytes to back the peeks and
-/// pokes to memory and the ins and outs of ports.
+/// A minimum implementation of Machine. It uses two arrays of 256 KiB to back the peeks and
+/// pokes to memory and the ins and outs of ports. Addresses over $3ffff panic.
 pub struct PlainMachine {
     mem: [u8; 4*65536],
     io: [u8; 4*65536]
@@ -92,7 +110,204 @@ impl Machine for PlainMachine {
     }
 }
 
+/// What RamMachine returns for the addresses over its RAM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnmappedMemory {
+    /// The RAM is repeated over the whole address space
+    Mirror,
+    /// Reads return the value, writes are ignored
+    Value(u8),
+    /// Reads return the last value seen on the data bus, writes are
+    /// ignored
+    OpenBus,
+}
+
+/// A Machine with only RAM, of any size
+///
+/// By default the memory is mirrored over the whole address space, so
+/// the default of 64 KiB behaves like a plain Z80 system. Ports are
+/// stored like on PlainMachine.
+pub struct RamMachine {
+    mem: Vec<u8>,
+    io: Vec<u8>,
+    unmapped: UnmappedMemory,
+    bus: Cell<u8>,
+}
+
+impl RamMachine {
+    /// Returns a RamMachine with size bytes of RAM, a power of two
+    pub fn new(size: usize) -> RamMachine {
+        assert!(size.is_power_of_two(), "the RAM size must be a power of two");
+        RamMachine {
+            mem: vec![0; size],
+            io: vec![0; 65536],
+            unmapped: UnmappedMemory::Mirror,
+            bus: Cell::new(0xff),
+        }
+    }
+
+    /// Sets the behaviour of the addresses over the RAM, up to $ffffff
+    pub fn set_unmapped(&mut self, unmapped: UnmappedMemory) {
+        self.unmapped = unmapped;
+    }
+
+    fn ram_index(&self, address: u32) -> Option<usize> {
+        let address = (address & 0xffffff) as usize;
+        if address < self.mem.len() {
+            Some(address)
+        } else if self.unmapped == UnmappedMemory::Mirror {
+            Some(address & (self.mem.len() - 1))
+        } else {
+            None
+        }
+    }
+
+    /// Copies data to the RAM at address
+    pub fn load(&mut self, address: u32, data: &[u8]) {
+        for (i, &value) in data.iter().enumerate() {
+            self.poke(address.wrapping_add(i as u32), value);
+        }
+    }
+}
+
+impl Default for RamMachine {
+    fn default() -> Self {
+        Self::new(65536)
+    }
+}
+
+impl Machine for RamMachine {
+    fn peek(&self, address: u32) -> u8 {
+        let value = match (self.ram_index(address), self.unmapped) {
+            (Some(index), _) => self.mem[index],
+            (None, UnmappedMemory::Value(value)) => value,
+            (None, _) => self.bus.get(),
+        };
+        self.bus.set(value);
+        value
+    }
+    fn poke(&mut self, address: u32, value: u8) {
+        if let Some(index) = self.ram_index(address) {
+            self.mem[index] = value;
+        }
+        self.bus.set(value);
+    }
+
+    fn port_in(&mut self, address: u16) -> u8 {
+        self.io[address as usize]
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        self.io[address as usize] = value;
+    }
+
+    fn use_cycles(&self, _cycles: u32) {
+    }
+}
+
+/// The memory of a SharedRamMachine, that other threads can read and
+/// write while the cpu runs
+///
+/// The accesses are atomic by byte only: a range read while the cpu
+/// runs may mix values from before and after an instruction.
+#[derive(Clone)]
+pub struct MemoryView {
+    mem: Arc<[AtomicU8]>,
+}
+
+impl MemoryView {
+    fn new(size: usize) -> MemoryView {
+        MemoryView {
+            mem: (0..size).map(|_| AtomicU8::new(0)).collect(),
+        }
+    }
+
+    /// Returns the size of the memory in bytes
+    pub fn len(&self) -> usize {
+        self.mem.len()
+    }
 
+    pub fn is_empty(&self) -> bool {
+        self.mem.is_empty()
+    }
+
+    pub fn peek(&self, address: u32) -> u8 {
+        self.mem[address as usize & (self.mem.len() - 1)].load(Ordering::Relaxed)
+    }
+
+    pub fn poke(&self, address: u32, value: u8) {
+        self.mem[address as usize & (self.mem.len() - 1)].store(value, Ordering::Relaxed);
+    }
+}
+
+/// The view as a Machine without ports, to use the helpers of the mem
+/// module on it while the cpu runs
+impl Machine for MemoryView {
+    fn peek(&self, address: u32) -> u8 {
+        MemoryView::peek(self, address)
+    }
+    fn poke(&mut self, address: u32, value: u8) {
+        MemoryView::poke(self, address, value);
+    }
+
+    fn port_in(&mut self, _address: u16) -> u8 {
+        0xff
+    }
+    fn port_out(&mut self, _address: u16, _value: u8) {
+    }
+
+    fn use_cycles(&self, _cycles: u32) {
+    }
+}
+
+/// A Machine with only RAM, like RamMachine, whose memory can be
+/// shared with other threads, for example to show it live in a
+/// frontend
+pub struct SharedRamMachine {
+    mem: MemoryView,
+    io: Vec<u8>,
+}
+
+impl SharedRamMachine {
+    /// Returns a SharedRamMachine with size bytes of RAM, a power of
+    /// two, mirrored over the address space
+    pub fn new(size: usize) -> SharedRamMachine {
+        assert!(size.is_power_of_two(), "the RAM size must be a power of two");
+        SharedRamMachine {
+            mem: MemoryView::new(size),
+            io: vec![0; 65536],
+        }
+    }
+
+    /// Returns a view of the memory, to send to other threads
+    pub fn view(&self) -> MemoryView {
+        self.mem.clone()
+    }
+}
+
+impl Default for SharedRamMachine {
+    fn default() -> Self {
+        Self::new(65536)
+    }
+}
+
+impl Machine for SharedRamMachine {
+    fn peek(&self, address: u32) -> u8 {
+        self.mem.peek(address)
+    }
+    fn poke(&mut self, address: u32, value: u8) {
+        self.mem.poke(address, value);
+    }
+
+    fn port_in(&mut self, address: u16) -> u8 {
+        self.io[address as usize]
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        self.io[address as usize] = value;
+    }
+
+    fn use_cycles(&self, _cycles: u32) {
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -107,4 +322,45 @@ mod tests {
         m.poke(A, V);
         assert_eq!(V, m.peek(A));
     }
+
+    #[test]
+    fn ram_machine_mirrors() {
+        let mut m = RamMachine::default();
+
+        m.load(0xffff, &[0x12, 0x34]);
+        assert_eq!(0x12, m.peek(0x00ffff));
+        assert_eq!(0x34, m.peek(0x000000));
+        assert_eq!(0x34, m.peek(0x050000));
+    }
+
+    #[test]
+    fn ram_machine_unmapped() {
+        let mut m = RamMachine::new(0x1000);
+        m.poke(0x0010, 0x5a);
+
+        m.set_unmapped(UnmappedMemory::Value(0xff));
+        m.poke(0x1010, 0x12);
+        assert_eq!(0xff, m.peek(0x1010));
+        assert_eq!(0x5a, m.peek(0x0010));
+
+        m.set_unmapped(UnmappedMemory::OpenBus);
+        assert_eq!(0x5a, m.peek(0x0010));
+        assert_eq!(0x5a, m.peek(0x2000));
+        m.poke(0x3000, 0x77);
+        assert_eq!(0x77, m.peek(0x2000));
+    }
+
+    #[test]
+    fn shared_ram_machine_view() {
+        let mut m = SharedRamMachine::new(0x1000);
+        let view = m.view();
+
+        m.poke(0x0123, 0x45);
+        let value = std::thread::spawn(move || {
+            view.poke(0x0124, 0x67);
+            view.peek(0x1123)
+        }).join().unwrap();
+        assert_eq!(0x45, value);
+        assert_eq!(0x67, m.peek(0x0124));
+    }
 }
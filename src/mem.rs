@@ -0,0 +1,120 @@
+//! Helpers to access the memory of a Machine
+//!
+//! Useful to embedders writing traps, like PC hooks that read the
+//! arguments of a guest routine. The values are little endian, as on
+//! the Z80. The addresses are the ones of the Machine, there is no
+//! wrapping in Z80 mode.
+
+use std::ops::Range;
+
+use crate::Machine;
+
+pub fn memset<M: Machine + ?Sized>(machine: &mut M, address: u32, fill: u8, count: u32) {
+    for loc in address..(address + count) {
+        machine.poke(loc, fill);
+    }
+}
+
+pub fn memcpy_to_z80<M: Machine + ?Sized>(machine: &mut M, start: u32, data: &[u8]) {
+    for (loc, byte) in (start..).zip(data.iter()) {
+        machine.poke(loc, *byte);
+    }
+}
+
+/// Returns the bytes of the zero terminated string at address, without
+/// the terminator
+pub fn get_cstring<M: Machine + ?Sized>(machine: &M, address: u32) -> Vec<u8> {
+    let mut s: Vec<u8> = vec![];
+    let mut ptr = address;
+
+    loop {
+        match machine.peek(ptr) {
+            0 => break,
+            b => s.push(b)
+        }
+        ptr += 1;
+    }
+    s
+}
+
+/// Writes data at address followed by a zero
+pub fn put_cstring<M: Machine + ?Sized>(machine: &mut M, address: u32, data: &[u8]) {
+    write_bytes(machine, address, data);
+    machine.poke(address + data.len() as u32, 0);
+}
+
+pub fn checksum<M: Machine + ?Sized>(machine: &M, start: u32, len: u32) -> u32 {
+    let mut checksum = 0u32;
+    for i in (start..(start+len)).step_by(3) {
+        checksum ^= machine._peek24(i);
+    }
+    checksum
+}
+
+/// Returns the addresses in range where the bytes are found, like a
+/// string. The matches can overlap.
+pub fn find<M: Machine + ?Sized>(machine: &M, range: Range<u32>, bytes: &[u8]) -> Vec<u32> {
+    let pattern: Vec<Option<u8>> = bytes.iter().copied().map(Some).collect();
+    search(machine, range, &pattern)
+}
+
+/// Returns the addresses in range where the pattern is found. None in
+/// the pattern matches any byte. The matches can overlap.
+pub fn search<M: Machine + ?Sized>(machine: &M, range: Range<u32>, pattern: &[Option<u8>]) -> Vec<u32> {
+    if pattern.is_empty() {
+        return Vec::new();
+    }
+    let last = range.end.saturating_sub(pattern.len() as u32 - 1);
+    (range.start..last)
+        .filter(|&address| pattern.iter().enumerate().all(|(i, byte)| {
+            byte.is_none_or(|byte| machine.peek(address + i as u32) == byte)
+        }))
+        .collect()
+}
+
+pub fn read_bytes<M: Machine + ?Sized>(machine: &M, address: u32, len: u32) -> Vec<u8> {
+    (address..address + len).map(|loc| machine.peek(loc)).collect()
+}
+
+pub fn write_bytes<M: Machine + ?Sized>(machine: &mut M, address: u32, data: &[u8]) {
+    memcpy_to_z80(machine, address, data);
+}
+
+/// Reads a field of size bytes, 1 to 4, at the offset of a structure
+pub fn read_field<M: Machine + ?Sized>(machine: &M, address: u32, offset: u32, size: u32) -> u32 {
+    assert!((1..=4).contains(&size), "fields are 1 to 4 bytes");
+    (0..size).fold(0, |value, i| value | ((machine.peek(address + offset + i) as u32) << (8 * i)))
+}
+
+/// Writes a field of size bytes, 1 to 4, at the offset of a structure
+pub fn write_field<M: Machine + ?Sized>(machine: &mut M, address: u32, offset: u32, size: u32, value: u32) {
+    assert!((1..=4).contains(&size), "fields are 1 to 4 bytes");
+    for i in 0..size {
+        machine.poke(address + offset + i, (value >> (8 * i)) as u8);
+    }
+}
+
+pub fn read_u16<M: Machine + ?Sized>(machine: &M, address: u32) -> u16 {
+    read_field(machine, address, 0, 2) as u16
+}
+
+pub fn read_u24<M: Machine + ?Sized>(machine: &M, address: u32) -> u32 {
+    read_field(machine, address, 0, 3)
+}
+
+pub fn read_u32<M: Machine + ?Sized>(machine: &M, address: u32) -> u32 {
+    read_field(machine, address, 0, 4)
+}
+
+pub fn write_u16<M: Machine + ?Sized>(machine: &mut M, address: u32, value: u16) {
+    write_field(machine, address, 0, 2, value as u32);
+}
+
+/// Writes the low 24 bits of value
+pub fn write_u24<M: Machine + ?Sized>(machine: &mut M, address: u32, value: u32) {
+    write_field(machine, address, 0, 3, value);
+}
+
+pub fn write_u32<M: Machine + ?Sized>(machine: &mut M, address: u32, value: u32) {
+    write_field(machine, address, 0, 4, value);
+}
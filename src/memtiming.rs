@@ -0,0 +1,14 @@
+use std::ops::Range;
+
+/// Extra cycles on each access to a range of memory
+pub(crate) struct MemoryRegion {
+    pub(crate) range: Range<u32>,
+    pub(crate) wait_states: u32,
+}
+
+// The first region containing the address applies
+pub(crate) fn wait_states(regions: &[MemoryRegion], address: u32) -> u32 {
+    regions.iter()
+        .find(|r| r.range.contains(&address))
+        .map_or(0, |r| r.wait_states)
+}
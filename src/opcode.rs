@@ -14,6 +14,11 @@ impl Opcode {
         (self.action)(env);
     }
 
+    /// Invalid opcodes are decoded as NONINOP
+    pub(crate) fn is_invalid(&self) -> bool {
+        self.name == "NONINOP"
+    }
+
     /// returns String, and u32 PC increment due to immediates
     /// (the PC increment due to the opcode itself, (and due 
     /// to the state.index hack), have already been applied by
@@ -109,6 +114,17 @@ pub fn build_halt() -> Opcode {
     }
 }
 
+pub fn build_slp() -> Opcode {
+    Opcode {
+        name: "SLP".to_string(),
+        action: Box::new(move |env: &mut Environment| {
+            // Sleep mode stops the cpu like HALT. The peripherals that
+            // keep running and wake it up are in the hosting machine.
+            env.state.halted = true;
+        })
+    }
+}
+
 pub fn build_pop_rr(rr: Reg16) -> Opcode {
     Opcode {
         name: format!("POP {:?}", rr),
@@ -139,6 +155,7 @@ pub fn build_conf_interrupts(enable: bool) -> Opcode {
         name: name.to_string(),
         action: Box::new(move |env: &mut Environment| {
             env.state.reg.set_interrupts(enable);
+            env.state.ei_delay = enable;
         })
     }
 }
@@ -48,7 +48,7 @@ pub fn build_tst_a_r(reg: Reg8) -> Opcode {
 
 pub fn build_tst_a_n() -> Opcode {
     Opcode {
-        name: format!("TST A, n"),
+        name: "TST A, n".to_string(),
         action: Box::new(move |env: &mut Environment| {
             let a = env.state.reg.a();
             let b = env.advance_pc();
@@ -158,7 +158,7 @@ pub fn build_mlt_rr(reg: Reg16) -> Opcode {
             let a = r & 0xff;
             let b = (r >> 8) & 0xff;
             env.state.reg.set16(reg, a * b);
-            env.sys.use_cycles(4);
+            env.use_cycles(4);
         })
     }
 }
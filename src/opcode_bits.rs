@@ -33,8 +33,7 @@ pub fn build_rot_r(r: Reg8, (dir, mode, name): (ShiftDir, ShiftMode, &str), fast
                 env.reg8_ext(r)
             };
 
-            let carry: bool;
-            match dir {
+            let carry = match dir {
                 ShiftDir::Left => {
                     let upper_bit = v >= 0x80;
                     v <<= 1;
@@ -47,7 +46,7 @@ pub fn build_rot_r(r: Reg8, (dir, mode, name): (ShiftDir, ShiftMode, &str), fast
                     if set_lower_bit { // bit 0 is 0 already
                         v |= 1;
                     }
-                    carry = upper_bit;
+                    upper_bit
                 },
                 ShiftDir::Right => {
                     let upper_bit = v >= 0x80;
@@ -62,7 +61,7 @@ pub fn build_rot_r(r: Reg8, (dir, mode, name): (ShiftDir, ShiftMode, &str), fast
                     if set_upper_bit { // bit 7 is 0 already
                         v |= 0x80;
                     }
-                    carry = lower_bit;
+                    lower_bit
                 }
             };
             if indexed && r != Reg8::_HL {
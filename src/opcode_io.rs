@@ -1,6 +1,8 @@
 use super::opcode::*;
 use super::environment::*;
+use super::operators::*;
 use super::registers::*;
+use super::state::SizePrefix;
 
 /*
     From "The undocumented Z80 documented" TUZD-4.4:
@@ -116,61 +118,249 @@ pub fn build_in_block((inc, repeat, postfix) : (bool, bool, &'static str)) -> Op
     Opcode {
         name: format!("IN{}", postfix),
         action: Box::new(move |env: &mut Environment| {
-            // The INI/INIR/IND/INDR instructions use BC after decrementing B
-            let b = env.state.reg.inc_dec8(Reg8::B, false /* decrement */);
-            let address = env.state.reg.get16(Reg16::BC);
+            let mut iterations = 0;
+            loop {
+                // The INI/INIR/IND/INDR instructions use BC after decrementing B
+                let b = env.state.reg.inc_dec8(Reg8::B, false /* decrement */);
+                let address = env.state.reg.get16(Reg16::BC);
+
+                let value = env.port_in(address);
+                // We won't have IX and IY cases to consider
+                env.set_reg(Reg8::_HL, value);
+                if env.state.is_op_long() {
+                    env.state.reg.inc_dec24(Reg16::HL, inc);
+                } else {
+                    env.state.reg.inc_dec16(Reg16::HL, inc);
+                }
+
+                // TUZD-4.3
+                let mut j = env.state.reg.get8(Reg8::C) as u16;
+                j = if inc {j.wrapping_add(1)} else {j.wrapping_sub(1)};
+                let k = value as u16 + (j & 0xff);
+                env.state.reg.update_block_flags(value, k, b);
+
+                iterations += 1;
+                if !repeat || b == 0 {
+                    break;
+                }
+                if !env.continue_block(iterations, instruction_len(env)) {
+                    repeat_instruction(env);
+                    break;
+                }
+            }
+        })
+    }
+}
+
+pub fn build_out_block((inc, repeat, postfix) : (bool, bool, &'static str)) -> Opcode {
+    let n0 = if repeat {"OT"} else {"OUT"};
+    Opcode {
+        name: format!("{}{}", n0, postfix),
+        action: Box::new(move |env: &mut Environment| {
+            let mut iterations = 0;
+            loop {
+                // the OUTI/OTIR/OUTD/OTDR instructions use BC before decrementing B
+                let address = env.state.reg.get16(Reg16::BC);
+                let b = env.state.reg.inc_dec8(Reg8::B, false /* decrement */);
+
+                // We won't have IX and IY cases to consider
+                let value = env.reg8_ext(Reg8::_HL);
+                env.port_out(address, value);
+                if env.state.is_op_long() {
+                    env.state.reg.inc_dec24(Reg16::HL, inc);
+                } else {
+                    env.state.reg.inc_dec16(Reg16::HL, inc);
+                }
+
+                // TUZD-4.3
+                let k = value as u16 + env.state.reg.get8(Reg8::L) as u16;
+                env.state.reg.update_block_flags(value, k, b);
+
+                iterations += 1;
+                if !repeat || b == 0 {
+                    break;
+                }
+                if !env.continue_block(iterations, instruction_len(env)) {
+                    repeat_instruction(env);
+                    break;
+                }
+            }
+        })
+    }
+}
+
+/*
+    eZ80 block IO. From the eZ80 CPU user manual UM0077:
+
+The xxM instructions access the on-chip peripherals, the port address
+is {00h, C} and C moves with HL. The xx2 instructions use BC as the
+port address and also move C. The xx2R instructions use DE as the port
+address, moving with HL, and BC as counter. The xxRX instructions use
+DE as a fixed port address and BC as counter.
+*/
+
+fn step_hl(env: &mut Environment, inc: bool) {
+    if env.state.is_op_long() {
+        env.state.reg.inc_dec24(Reg16::HL, inc);
+    } else {
+        env.state.reg.inc_dec16(Reg16::HL, inc);
+    }
+}
+
+fn step_de(env: &mut Environment, inc: bool) {
+    if env.state.is_op_long() {
+        env.state.reg.inc_dec24(Reg16::DE, inc);
+    } else {
+        env.state.reg.inc_dec16(Reg16::DE, inc);
+    }
+}
+
+fn decrement_bc(env: &mut Environment) -> u32 {
+    if env.state.is_op_long() {
+        env.state.reg.inc_dec24(Reg16::BC, false /* decrement */)
+    } else {
+        env.state.reg.inc_dec16(Reg16::BC, false /* decrement */)
+    }
+}
 
+// Length of the block instruction, with its suffix
+fn instruction_len(env: &Environment) -> i32 {
+    match env.state.sz_prefix {
+        SizePrefix::None => 2,
+        _ => 3
+    }
+}
+
+fn repeat_instruction(env: &mut Environment) {
+    // Back to redo the instruction
+    let pc = env.wrap_address(env.state.pc(), -instruction_len(env));
+    env.state.set_pc(pc);
+}
+
+pub fn build_in_block_m((inc, repeat, name) : (bool, bool, &'static str)) -> Opcode {
+    Opcode {
+        name: name.to_string(),
+        action: Box::new(move |env: &mut Environment| {
+            let address = env.state.reg.get8(Reg8::C) as u16;
             let value = env.port_in(address);
-            // We won't have IX and IY cases to consider
             env.set_reg(Reg8::_HL, value);
-            if env.state.is_op_long() {
-                env.state.reg.inc_dec24(Reg16::HL, inc);
-            } else {
-                env.state.reg.inc_dec16(Reg16::HL, inc);
+            env.state.reg.inc_dec8(Reg8::C, inc);
+            step_hl(env, inc);
+            let b = env.state.reg.inc_dec8(Reg8::B, false /* decrement */);
+            env.state.reg.update_block_m_flags(value, b);
+
+            if repeat && b != 0 {
+                repeat_instruction(env);
             }
+        })
+    }
+}
 
-            // TUZD-4.3
-            let mut j = env.state.reg.get8(Reg8::C) as u16;
-            j = if inc {j+1} else {j-1};
-            let k = value as u16 + (j & 0xff);
-            env.state.reg.update_block_flags(value, k, b);
+pub fn build_out_block_m((inc, repeat, name) : (bool, bool, &'static str)) -> Opcode {
+    Opcode {
+        name: name.to_string(),
+        action: Box::new(move |env: &mut Environment| {
+            let address = env.state.reg.get8(Reg8::C) as u16;
+            let value = env.reg8_ext(Reg8::_HL);
+            env.port_out(address, value);
+            env.state.reg.inc_dec8(Reg8::C, inc);
+            step_hl(env, inc);
+            let b = env.state.reg.inc_dec8(Reg8::B, false /* decrement */);
+            env.state.reg.update_block_m_flags(value, b);
 
             if repeat && b != 0 {
-                // Back to redo the instruction
-                let pc = env.wrap_address(env.state.pc(), -2);
-                env.state.set_pc(pc);
+                repeat_instruction(env);
             }
-                })
+        })
     }
 }
 
-pub fn build_out_block((inc, repeat, postfix) : (bool, bool, &'static str)) -> Opcode {
-    let n0 = if repeat {"OT"} else {"OUT"};
+pub fn build_in_block_2((inc, name) : (bool, &'static str)) -> Opcode {
     Opcode {
-        name: format!("{}{}", n0, postfix),
+        name: name.to_string(),
         action: Box::new(move |env: &mut Environment| {
-            // the OUTI/OTIR/OUTD/OTDR instructions use BC before decrementing B
             let address = env.state.reg.get16(Reg16::BC);
+            let value = env.port_in(address);
+            env.set_reg(Reg8::_HL, value);
+            env.state.reg.inc_dec8(Reg8::C, inc);
+            step_hl(env, inc);
             let b = env.state.reg.inc_dec8(Reg8::B, false /* decrement */);
+            env.state.reg.put_flag(Flag::Z, b == 0);
+            env.state.reg.put_flag(Flag::N, value & 0x80 != 0);
+        })
+    }
+}
 
-            // We won't have IX and IY cases to consider
+pub fn build_out_block_2((inc, name) : (bool, &'static str)) -> Opcode {
+    Opcode {
+        name: name.to_string(),
+        action: Box::new(move |env: &mut Environment| {
+            let address = env.state.reg.get16(Reg16::BC);
             let value = env.reg8_ext(Reg8::_HL);
             env.port_out(address, value);
-            if env.state.is_op_long() {
-                env.state.reg.inc_dec24(Reg16::HL, inc);
-            } else {
-                env.state.reg.inc_dec16(Reg16::HL, inc);
+            env.state.reg.inc_dec8(Reg8::C, inc);
+            step_hl(env, inc);
+            let b = env.state.reg.inc_dec8(Reg8::B, false /* decrement */);
+            env.state.reg.put_flag(Flag::Z, b == 0);
+            env.state.reg.put_flag(Flag::N, value & 0x80 != 0);
+        })
+    }
+}
+
+/// INI2R, IND2R, INIRX and INDRX. The port in DE moves only for the 2R
+pub fn build_in_block_de((inc, step_port, name) : (bool, bool, &'static str)) -> Opcode {
+    Opcode {
+        name: name.to_string(),
+        action: Box::new(move |env: &mut Environment| {
+            let address = env.state.reg.get16(Reg16::DE);
+            let value = env.port_in(address);
+            env.set_reg(Reg8::_HL, value);
+            if step_port {
+                step_de(env, inc);
             }
+            step_hl(env, inc);
+            let bc = decrement_bc(env);
+            env.state.reg.put_flag(Flag::Z, bc == 0);
+            env.state.reg.put_flag(Flag::N, value & 0x80 != 0);
 
-            // TUZD-4.3
-            let k = value as u16 + env.state.reg.get8(Reg8::L) as u16;
-            env.state.reg.update_block_flags(value, k, b);
+            if bc != 0 {
+                repeat_instruction(env);
+            }
+        })
+    }
+}
 
-            if repeat && b != 0 {
-                // Back to redo the instruction
-                let pc = env.wrap_address(env.state.pc(), -2);
-                env.state.set_pc(pc);
+/// OTI2R, OTD2R, OTIRX and OTDRX. The port in DE moves only for the 2R
+pub fn build_out_block_de((inc, step_port, name) : (bool, bool, &'static str)) -> Opcode {
+    Opcode {
+        name: name.to_string(),
+        action: Box::new(move |env: &mut Environment| {
+            let address = env.state.reg.get16(Reg16::DE);
+            let value = env.reg8_ext(Reg8::_HL);
+            env.port_out(address, value);
+            if step_port {
+                step_de(env, inc);
+            }
+            step_hl(env, inc);
+            let bc = decrement_bc(env);
+            env.state.reg.put_flag(Flag::Z, bc == 0);
+            env.state.reg.put_flag(Flag::N, value & 0x80 != 0);
+
+            if bc != 0 {
+                repeat_instruction(env);
             }
         })
     }
 }
+
+pub fn build_tstio_n() -> Opcode {
+    Opcode {
+        name: "TSTIO n".to_string(),
+        action: Box::new(move |env: &mut Environment| {
+            let n = env.advance_pc();
+            let address = env.state.reg.get8(Reg8::C) as u16;
+            let value = env.port_in(address);
+            operator_tst(env, value, n);
+        })
+    }
+}
@@ -13,7 +13,7 @@ pub fn build_djnz() -> Opcode {
             env.state.reg.set8(Reg8::B, b);
             if b != 0 {
                 // Condition not met
-                env.sys.use_cycles(1);
+                env.use_cycles(1);
                 relative_jump(env, offset);
             }
         })
@@ -25,7 +25,7 @@ pub fn build_jr_unconditional() -> Opcode {
         name: "JR l".to_string(),
         action: Box::new(move |env: &mut Environment| {
             let offset = env.advance_pc();
-            env.sys.use_cycles(1);
+            env.use_cycles(1);
             relative_jump(env, offset);
         })
     }
@@ -37,7 +37,7 @@ pub fn build_jr_eq((flag, value, name): (Flag, bool, &str)) -> Opcode {
         action: Box::new(move |env: &mut Environment| {
             let offset = env.advance_pc();
             if env.state.reg.get_flag(flag) == value {
-                env.sys.use_cycles(2);
+                env.use_cycles(2);
                 relative_jump(env, offset);
             }
         })
@@ -79,7 +79,7 @@ pub fn build_jp_unconditional() -> Opcode {
         action: Box::new(move |env: &mut Environment| {
             let address = env.advance_immediate_16mbase_or_24();
             handle_jump_adl_state(env);
-            env.sys.use_cycles(1);
+            env.use_cycles(1);
             env.state.set_pc(address);
         })
     }
@@ -91,7 +91,8 @@ pub fn build_jp_eq((flag, value, name): (Flag, bool, &str)) -> Opcode {
         action: Box::new(move |env: &mut Environment| {
             let address = env.advance_immediate_16mbase_or_24();
             if env.state.reg.get_flag(flag) == value {
-                env.sys.use_cycles(1);
+                handle_jump_adl_state(env);
+                env.use_cycles(1);
                 env.state.set_pc(address);
             }
         })
@@ -104,7 +105,8 @@ pub fn build_jp_hl() -> Opcode {
         action: Box::new(move |env: &mut Environment| {
             // Note: no displacement added to the index
             let address = env.index_value();
-            env.sys.use_cycles(1);
+            handle_jump_adl_state(env);
+            env.use_cycles(1);
             env.state.set_pc(address);
         })
     }
@@ -112,6 +114,7 @@ pub fn build_jp_hl() -> Opcode {
 
 fn handle_call_size_prefix(env: &mut Environment) {
     let pc = env.state.pc();
+    env.call = true;
 
     if env.state.reg.adl {
         match env.state.sz_prefix {
@@ -189,6 +192,7 @@ pub fn build_call_eq((flag, value, name): (Flag, bool, &str)) -> Opcode {
 
 fn handle_rst_size_prefix(env: &mut Environment, vec: u32) {
     let pc = env.state.pc();
+    env.call = true;
 
     if env.state.reg.adl {
         match env.state.sz_prefix {
@@ -196,6 +200,7 @@ fn handle_rst_size_prefix(env: &mut Environment, vec: u32) {
                 env.push(pc);
                 env.state.set_pc(vec);
             },
+            SizePrefix::SIS | // RST has no immediate, .SIS is the same as .SIL
             SizePrefix::SIL => {
                 env.push_byte_sps((pc >> 8) as u8);
                 env.push_byte_sps(pc as u8);
@@ -210,9 +215,6 @@ fn handle_rst_size_prefix(env: &mut Environment, vec: u32) {
                 env.push_byte_spl(3);
                 env.state.reg.pc = vec;
             }
-            SizePrefix::SIS => {
-                eprintln!("invalid rst size prefix");
-            }
         }
     } else {
         match env.state.sz_prefix {
@@ -253,7 +255,7 @@ pub fn build_ret() -> Opcode {
     Opcode {
         name: "RET".to_string(),
         action: Box::new(move |env: &mut Environment| {
-            env.sys.use_cycles(2);
+            env.use_cycles(2);
             env.subroutine_return();
         })
     }
@@ -269,41 +269,75 @@ pub fn build_ld_block((inc, repeat, postfix) : (bool, bool, &'static str)) -> Op
     Opcode {
         name: format!("LD{}", postfix),
         action: Box::new(move |env: &mut Environment| {
-            let value = env.reg8_ext(Reg8::_HL);
-            let address = env.reg16mbase_or_24(Reg16::DE);
-            env.poke(address, value);
+            let mut iterations = 0;
+            loop {
+                let value = env.reg8_ext(Reg8::_HL);
+                let address = env.reg16mbase_or_24(Reg16::DE);
+                env.poke(address, value);
+
+                let bc = if env.state.is_op_long() {
+                    env.state.reg.inc_dec24(Reg16::DE, inc);
+                    env.state.reg.inc_dec24(Reg16::HL, inc);
+                    env.state.reg.inc_dec24(Reg16::BC, false /*decrement*/)
+                } else {
+                    env.state.reg.inc_dec16(Reg16::DE, inc);
+                    env.state.reg.inc_dec16(Reg16::HL, inc);
+                    env.state.reg.inc_dec16(Reg16::BC, false /*decrement*/)
+                };
 
-            let bc = if env.state.is_op_long() {
-                env.state.reg.inc_dec24(Reg16::DE, inc);
-                env.state.reg.inc_dec24(Reg16::HL, inc);
-                env.state.reg.inc_dec24(Reg16::BC, false /*decrement*/)
-            } else {
-                env.state.reg.inc_dec16(Reg16::DE, inc);
-                env.state.reg.inc_dec16(Reg16::HL, inc);
-                env.state.reg.inc_dec16(Reg16::BC, false /*decrement*/)
-            };
-
-            // TUZD-4.2
-            let n = value.wrapping_add(env.state.reg.a());
-            env.state.reg.update_undocumented_flags_block(n);
-            env.state.reg.clear_flag(Flag::N);
-            env.state.reg.clear_flag(Flag::H);
-            env.state.reg.put_flag(Flag::P, bc != 0);
-            // S, Z and C unchanged. What about N?
-
-            if repeat && bc != 0 {
-                // Back to redo the instruction
+                // TUZD-4.2
+                let n = value.wrapping_add(env.state.reg.a());
+                env.state.reg.update_undocumented_flags_block(n);
+                env.state.reg.clear_flag(Flag::N);
+                env.state.reg.clear_flag(Flag::H);
+                env.state.reg.put_flag(Flag::P, bc != 0);
+                // S, Z and C unchanged. What about N?
+
+                iterations += 1;
+                if !repeat || bc == 0 {
+                    break;
+                }
                 let instruction_len = match env.state.sz_prefix {
                         crate::state::SizePrefix::None => 2,
                         _ => 3
                 };
-                let pc = env.wrap_address(env.state.pc(), -instruction_len);
-                env.state.set_pc(pc);
+                if !env.continue_block(iterations, instruction_len) {
+                    // Back to redo the instruction
+                    let pc = env.wrap_address(env.state.pc(), -instruction_len);
+                    env.state.set_pc(pc);
+                    break;
+                }
             }
         })         
     }
 }
 
+pub fn build_ld_i_hl() -> Opcode {
+    Opcode {
+        name: "LD I, HL".to_string(),
+        action: Box::new(|env: &mut Environment| {
+            let hl = env.state.reg.get16(Reg16::HL);
+            env.state.reg.set_i16(hl);
+        })
+    }
+}
+
+pub fn build_ld_hl_i() -> Opcode {
+    Opcode {
+        name: "LD HL, I".to_string(),
+        action: Box::new(|env: &mut Environment| {
+            // The upper byte of HL is MBASE
+            let i = env.state.reg.get_i16();
+            if env.state.is_op_long() {
+                let value = ((env.state.reg.mbase as u32) << 16) + i as u32;
+                env.state.reg.set24(Reg16::HL, value);
+            } else {
+                env.state.reg.set16(Reg16::HL, i);
+            }
+        })
+    }
+}
+
 pub fn build_ld_a_mb() -> Opcode {
     Opcode {
         name: "LD A, MB".to_string(),
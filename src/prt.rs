@@ -0,0 +1,199 @@
+use super::iodevice::{CpuLines, IoDevice};
+
+// TMR0_CTL, each timer uses 3 ports from there
+const PRT_BASE: u8 = 0x80;
+const PRT_TIMERS: usize = 6;
+
+// Offsets of the registers of a timer
+const TMR_CTL: u8 = 0;
+const TMR_DR_L: u8 = 1; // TMR_RR_L on writes
+const TMR_DR_H: u8 = 2; // TMR_RR_H on writes
+
+// TMR_CTL bits
+const PRT_IRQ: u8 = 0x80;
+const IRQ_EN: u8 = 0x40;
+const PRT_MODE: u8 = 0x10;
+const CLK_DIV: u8 = 0x0c;
+const RST_EN: u8 = 0x02;
+const PRT_EN: u8 = 0x01;
+
+#[derive(Clone, Debug)]
+struct Timer {
+    ctl: u8,
+    reload: u16,
+    // Down counter, from 1 to 65536
+    count: u32,
+    // Cycles not yet divided by CLK_DIV
+    prescale: u32,
+    // TMR_DR_H, latched when reading TMR_DR_L
+    latch: u8,
+    irq: Option<u32>,
+}
+
+impl Timer {
+    fn new() -> Timer {
+        Timer {
+            ctl: 0x00,
+            reload: 0x0000,
+            count: 0x10000,
+            prescale: 0,
+            latch: 0x00,
+            irq: None,
+        }
+    }
+
+    // A reload of 0 counts 65536 clocks
+    fn period(&self) -> u32 {
+        if self.reload == 0 { 0x10000 } else { self.reload as u32 }
+    }
+
+    fn divider(&self) -> u32 {
+        4 << (2 * ((self.ctl & CLK_DIV) >> 2))
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if self.ctl & PRT_EN == 0 {
+            return;
+        }
+        let divider = self.divider();
+        self.prescale += cycles % divider;
+        let mut steps = cycles / divider + self.prescale / divider;
+        self.prescale %= divider;
+        while steps > 0 {
+            if steps < self.count {
+                self.count -= steps;
+                return;
+            }
+            steps -= self.count;
+            self.ctl |= PRT_IRQ;
+            if self.ctl & PRT_MODE == 0 {
+                // Single pass, stopped at the end of count
+                self.count = 0x10000;
+                self.ctl &= !PRT_EN;
+                return;
+            }
+            self.count = self.period();
+            steps %= self.period();
+        }
+    }
+}
+
+/// The programmable reload timers PRT0 to PRT5 of the eZ80F92, on the
+/// on-chip ports $80-$91
+///
+/// Attach it with Cpu::add_io_device. The timers count down the bus
+/// cycles of the cpu, divided by CLK_DIV, and drive the interrupt lines
+/// set with set_irq. Writing TMR_CTL with RST_EN, or enabling the
+/// timer, loads the counter from TMR_RR. With fast boot, the counter is
+/// loaded with 1 instead, so that delay loops on a timer, like the ones
+/// of a boot, end at once. Only the system clock source is modelled:
+/// TMR_ISS is not on the ports of the Prt.
+#[derive(Clone, Debug)]
+pub struct Prt {
+    timers: [Timer; PRT_TIMERS],
+    fast_boot: bool,
+}
+
+impl Prt {
+    /// Returns the timers disabled, as after reset
+    pub fn new() -> Prt {
+        Prt {
+            timers: std::array::from_fn(|_| Timer::new()),
+            fast_boot: false,
+        }
+    }
+
+    /// Sets the interrupt line asserted while PRT_IRQ of the timer is
+    /// set with IRQ_EN
+    pub fn set_irq(&mut self, timer: usize, number: u32) {
+        self.timers[timer].irq = Some(number);
+    }
+
+    /// Loads the counters with 1 when the timers start, see Prt
+    pub fn set_fast_boot(&mut self, fast_boot: bool) {
+        self.fast_boot = fast_boot;
+    }
+
+    /// Reads a timer register. Returns None for other ports. Reading
+    /// TMR_CTL clears PRT_IRQ.
+    pub fn port_in(&mut self, port: u8) -> Option<u8> {
+        let (timer, register) = Self::register(port)?;
+        let timer = &mut self.timers[timer];
+        let value = match register {
+            TMR_CTL => {
+                let value = timer.ctl;
+                timer.ctl &= !PRT_IRQ;
+                value
+            }
+            TMR_DR_L => {
+                timer.latch = (timer.count >> 8) as u8;
+                timer.count as u8
+            }
+            TMR_DR_H => timer.latch,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Writes a timer register. Returns false for other ports.
+    pub fn port_out(&mut self, port: u8, value: u8) -> bool {
+        let (timer, register) = match Self::register(port) {
+            Some(register) => register,
+            None => return false,
+        };
+        let fast_boot = self.fast_boot;
+        let timer = &mut self.timers[timer];
+        match register {
+            TMR_CTL => {
+                let start = value & PRT_EN != 0
+                    && (value & RST_EN != 0 || timer.ctl & PRT_EN == 0);
+                timer.ctl = (timer.ctl & PRT_IRQ) | (value & !(PRT_IRQ | RST_EN));
+                if start {
+                    timer.count = if fast_boot { 1 } else { timer.period() };
+                    timer.prescale = 0;
+                }
+            }
+            TMR_DR_L => timer.reload = (timer.reload & 0xff00) | value as u16,
+            TMR_DR_H => timer.reload = (timer.reload & 0x00ff) | ((value as u16) << 8),
+            _ => return false,
+        }
+        true
+    }
+
+    // The timer and register offset of a port
+    fn register(port: u8) -> Option<(usize, u8)> {
+        let offset = port.wrapping_sub(PRT_BASE);
+        if (offset as usize) < 3 * PRT_TIMERS {
+            Some(((offset / 3) as usize, offset % 3))
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Prt {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoDevice for Prt {
+    fn port_in(&mut self, address: u16) -> u8 {
+        Prt::port_in(self, address as u8).unwrap_or(0x00)
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        Prt::port_out(self, address as u8, value);
+    }
+    fn tick(&mut self, cycles: u32, lines: &mut CpuLines) {
+        for timer in self.timers.iter_mut() {
+            timer.tick(cycles);
+            if let Some(number) = timer.irq {
+                if timer.ctl & PRT_IRQ != 0 && timer.ctl & IRQ_EN != 0 {
+                    lines.assert_irq(number);
+                } else {
+                    lines.deassert_irq(number);
+                }
+            }
+        }
+    }
+}
@@ -121,7 +121,7 @@ impl fmt::Display for Reg8 {
 }
 
 /// Z80 internal register values
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Registers {
     data: [u8; REG_COUNT8],
     shadow: [u8; REG_COUNT8],
@@ -133,6 +133,7 @@ pub struct Registers {
     pub adl: bool,  // ez80 24-bit flat addressing mode
     pub madl: bool,  // ez80
     pub mbase: u8,  // provides the top 8-bits of a 24-bit address when ez80 is in z80 mode
+    i_upper: u8, // ez80 I register is 16 bits, Reg8::I is the low byte
 }
 
 impl Registers {
@@ -149,6 +150,7 @@ impl Registers {
             adl: false,
             madl: false,
             mbase: 0,
+            i_upper: 0,
         };
 
         reg.set16(Reg16::AF, 0xffff);
@@ -323,6 +325,35 @@ impl Registers {
         self.data[r8 as usize] = (value >> 16) as u8;
     }
 
+    /// Returns the value of a 16 bit register of the shadow set, AF'
+    /// or the lower 16 bits of BC', DE' and HL'
+    pub fn get_shadow16(&self, rr: Reg16) -> u16 {
+        let ih = self.map_reg16_to_reg8(rr) as usize;
+        ((self.shadow[ih] as u16) << 8) + self.shadow[ih + 1] as u16
+    }
+
+    pub fn set_shadow16(&mut self, rr: Reg16, value: u16) {
+        let ih = self.map_reg16_to_reg8(rr) as usize;
+        self.shadow[ih] = (value >> 8) as u8;
+        self.shadow[ih + 1] = value as u8;
+    }
+
+    /// Returns the value of a 24 bit register of the shadow set, BC',
+    /// DE' or HL'
+    pub fn get_shadow24(&self, rr: Reg16) -> u32 {
+        let iu = self.map_reg24_to_reg8(rr) as usize;
+        self.shadow[iu + 2] as u32
+        + ((self.shadow[iu + 1] as u32) << 8)
+        + ((self.shadow[iu] as u32) << 16)
+    }
+
+    pub fn set_shadow24(&mut self, rr: Reg16, value: u32) {
+        let iu = self.map_reg24_to_reg8(rr) as usize;
+        self.shadow[iu + 2] = value as u8;
+        self.shadow[iu + 1] = (value >> 8) as u8;
+        self.shadow[iu] = (value >> 16) as u8;
+    }
+
     pub(crate) fn swap16(&mut self, rr: Reg16) {
         let ih = self.map_reg16_to_reg8(rr) as usize;
         mem::swap(&mut self.data[ih], &mut self.shadow[ih]);
@@ -374,7 +405,7 @@ impl Registers {
 
     pub(crate) fn update_p_flag(&mut self, reference: u8) {
         let bits = reference.count_ones();
-        self.put_flag(Flag::P, bits % 2 == 0);
+        self.put_flag(Flag::P, bits.is_multiple_of(2));
     }
 
     pub(crate) fn update_sz53_flags(&mut self, reference: u8) {
@@ -452,6 +483,7 @@ impl Registers {
                 let a_b3 = (a & 0x08) != 0;
                 let b_b3 = (b & 0x08) != 0;
                 let r_b3 = (reference & 0x08) != 0;
+                #[allow(clippy::nonminimal_bool)]
                 let neg_half_bit = (!a_b3 && !b_b3 && !r_b3) || (a_b3 && !(b_b3 && r_b3)); 
                 self.put_flag(Flag::H, neg_half_bit);    
             }
@@ -487,6 +519,14 @@ impl Registers {
         self.put_flag(Flag::C, k>255);
     }
 
+    pub(crate) fn update_block_m_flags(&mut self, reference: u8, counter: u8) {
+        // eZ80 OTIM, INIM and variants, flags from B-1 and the data
+        self.put_flag(Flag::S, counter & 0x80 != 0);
+        self.put_flag(Flag::Z, counter == 0);
+        self.put_flag(Flag::H, counter & 0x0f == 0x0f);
+        self.put_flag(Flag::N, reference & 0x80 != 0);
+    }
+
     pub(crate) fn update_bits_in_flags(&mut self, reference: u8) {
         self.update_sz53_flags(reference);
         self.clear_flag(Flag::H);
@@ -496,16 +536,38 @@ impl Registers {
         }
     }
 
+    /// Returns the 16 bit I register of the eZ80. LD A, I and
+    /// LD I, A only use the low byte.
+    pub fn get_i16(&self) -> u16 {
+        ((self.i_upper as u16) << 8) + self.get8(Reg8::I) as u16
+    }
+
+    /// Sets the 16 bit I register of the eZ80
+    pub fn set_i16(&mut self, value: u16) {
+        self.i_upper = (value >> 8) as u8;
+        self.set8(Reg8::I, value as u8);
+    }
+
     pub fn get_iff1(&self) -> bool {
         self.iff1
     }
 
-    pub(crate) fn set_interrupts(&mut self, v: bool) {
+    pub fn get_iff2(&self) -> bool {
+        self.iff2
+    }
+
+    /// Sets IFF1 and IFF2, like EI and DI
+    pub fn set_interrupts(&mut self, v: bool) {
         self.iff1 = v;
         self.iff2 = v;
     }
 
-    pub(crate) fn set_interrupt_mode(&mut self, im: u8) {
+    /// Returns the interrupt mode, 0, 1 or 2
+    pub fn get_interrupt_mode(&self) -> u8 {
+        self.im
+    }
+
+    pub fn set_interrupt_mode(&mut self, im: u8) {
         self.im = im;
     }
 
@@ -520,6 +582,50 @@ impl Registers {
 
 }
 
+impl Registers {
+    /// Increments the 7 low bits of R once per opcode fetch. Bit 7 is
+    /// kept, it only changes with LD R, A.
+    pub(crate) fn refresh(&mut self, fetches: u32) {
+        let r = self.get8(Reg8::R);
+        self.set8(Reg8::R, (r & 0x80) | (r.wrapping_add(fetches as u8) & 0x7f));
+    }
+}
+
+impl Default for Registers {
+    /// The registers after reset, for the Z80 and the eZ80
+    fn default() -> Registers {
+        Registers::new()
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "PC:{:06x} AF:{:04x} BC:{:06x} DE:{:06x} HL:{:06x} IX:{:06x} IY:{:06x} SPS:{:04x} SPL:{:06x}",
+            self.pc,
+            self.get16(Reg16::AF),
+            self.get24(Reg16::BC),
+            self.get24(Reg16::DE),
+            self.get24(Reg16::HL),
+            self.get24(Reg16::IX),
+            self.get24(Reg16::IY),
+            self.get16(Reg16::SP),
+            self.get24(Reg16::SP))?;
+        write!(f, "AF':{:04x} BC':{:06x} DE':{:06x} HL':{:06x} I:{:04x} R:{:02x} MB:{:02x} ADL:{} MADL:{} IFF1:{} IFF2:{} IM:{}",
+            self.get_shadow16(Reg16::AF),
+            self.get_shadow24(Reg16::BC),
+            self.get_shadow24(Reg16::DE),
+            self.get_shadow24(Reg16::HL),
+            self.get_i16(),
+            self.get8(Reg8::R),
+            self.mbase,
+            self.adl as u8,
+            self.madl as u8,
+            self.iff1 as u8,
+            self.iff2 as u8,
+            self.im)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -548,12 +654,37 @@ mod tests {
         let mut r = Registers::new();
  
         r.set_flag(Flag::P);
-        assert_eq!(true, r.get_flag(Flag::P));
+        assert!(r.get_flag(Flag::P));
         r.clear_flag(Flag::P);
-        assert_eq!(false, r.get_flag(Flag::P));
+        assert!(!r.get_flag(Flag::P));
         r.put_flag(Flag::P, true);
-        assert_eq!(true, r.get_flag(Flag::P));
+        assert!(r.get_flag(Flag::P));
         r.put_flag(Flag::P, false);
-        assert_eq!(false, r.get_flag(Flag::P));
+        assert!(!r.get_flag(Flag::P));
+    }
+
+    #[test]
+    fn set_get_shadow_register() {
+        let mut r = Registers::new();
+
+        r.set24(Reg16::HL, 0x123456);
+        r.set_shadow24(Reg16::HL, 0xabcdef);
+        r.set_shadow16(Reg16::AF, 0x1234);
+        r.swap24(Reg16::HL);
+        assert_eq!(0xabcdef, r.get24(Reg16::HL));
+        assert_eq!(0x123456, r.get_shadow24(Reg16::HL));
+        assert_eq!(0x1234, r.get_shadow16(Reg16::AF));
+    }
+
+    #[test]
+    fn display_registers() {
+        let mut r = Registers::new();
+        r.set24(Reg16::HL, 0x123456);
+        r.set_interrupt_mode(2);
+
+        let text = r.to_string();
+        assert!(text.contains("HL:123456"));
+        assert!(text.contains("AF:ffff"));
+        assert!(text.ends_with("IM:2"));
     }
 }
@@ -0,0 +1,265 @@
+use super::iodevice::{CpuLines, IoDevice};
+use super::state::HostClock;
+
+const RTC_SEC: u8 = 0xe0;
+const RTC_CEN: u8 = 0xe7;
+const RTC_ASEC: u8 = 0xe8;
+const RTC_ADOW: u8 = 0xeb;
+const RTC_ACTRL: u8 = 0xec;
+const RTC_CTRL: u8 = 0xed;
+
+// RTC_CTRL bits
+const RTC_ALARM: u8 = 0x80;
+const RTC_INT_EN: u8 = 0x40;
+const RTC_BCD_EN: u8 = 0x20;
+const RTC_UNLOCK: u8 = 0x01;
+
+const SECONDS_PER_DAY: i64 = 86400;
+// Every alarm setting matches once in a week
+const SECONDS_PER_WEEK: i64 = 7 * SECONDS_PER_DAY;
+
+/// Where the time of the Rtc comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RtcTime {
+    /// The host clock plus an offset in seconds
+    Offset(i64),
+    /// A fixed time in seconds since the Unix epoch, for deterministic
+    /// tests. Only the guest changes it, writing the time registers.
+    Frozen(i64),
+}
+
+/// The real time clock of the eZ80F92, on the on-chip ports $e0-$ed
+///
+/// Attach it with Cpu::add_io_device on the ports $e0..$ee. It is then
+/// updated with the cycles of the cpu, so the registers follow the host
+/// clock, and drives the interrupt line set with set_irq. A Machine can
+/// also route the ports to it and call update itself. The time is the 24 hour format, in binary or BCD as set
+/// in RTC_CTRL. The day of the week runs from 1, Sunday, to 7 and is
+/// derived from the date: writes to RTC_DOW are ignored.
+#[derive(Clone, Debug)]
+pub struct Rtc {
+    clock: HostClock,
+    time: RtcTime,
+    cycles: u64,
+    now: i64,
+    alarm: [u8; 4],
+    actrl: u8,
+    ctrl: u8,
+    irq: Option<u32>,
+}
+
+impl Rtc {
+    /// Returns an rtc on the host clock, without offset
+    pub fn new(clock: HostClock) -> Rtc {
+        let mut rtc = Rtc {
+            clock,
+            time: RtcTime::Offset(0),
+            cycles: 0,
+            now: 0,
+            alarm: [0; 4],
+            actrl: 0x00,
+            ctrl: 0x00,
+            irq: None,
+        };
+        rtc.now = rtc.seconds();
+        rtc
+    }
+
+    /// Returns an rtc stopped at the given seconds since the Unix epoch
+    pub fn frozen(seconds: i64) -> Rtc {
+        let mut rtc = Rtc::new(HostClock::System);
+        rtc.set_time(RtcTime::Frozen(seconds));
+        rtc
+    }
+
+    pub fn time(&self) -> RtcTime {
+        self.time
+    }
+
+    pub fn set_time(&mut self, time: RtcTime) {
+        self.time = time;
+        self.now = self.seconds();
+    }
+
+    /// Sets the interrupt line asserted while the alarm flag is set with
+    /// its interrupt enabled, when attached as an IoDevice
+    pub fn set_irq(&mut self, number: u32) {
+        self.irq = Some(number);
+    }
+
+    /// Returns the time shown, in seconds since the Unix epoch
+    pub fn now(&self) -> i64 {
+        self.now
+    }
+
+    /// Advances the time to the given cycles of the cpu. Returns true
+    /// when the alarm goes off with its interrupt enabled, for the
+    /// Machine to request the interrupt. The alarm goes off if it
+    /// matches any of the seconds passed, even when the cycles jump
+    /// over several seconds.
+    pub fn update(&mut self, cycles: u64) -> bool {
+        self.cycles = cycles;
+        let now = self.seconds();
+        if now == self.now {
+            return false;
+        }
+        let first = (self.now + 1).max(now - SECONDS_PER_WEEK + 1);
+        self.now = now;
+        if self.actrl & 0x0f == 0 || !(first..=now).any(|seconds| self.alarm_matches(seconds)) {
+            return false;
+        }
+        self.ctrl |= RTC_ALARM;
+        self.ctrl & RTC_INT_EN != 0
+    }
+
+    /// Reads an rtc register. Returns None for other ports. Reading
+    /// RTC_CTRL clears the alarm flag.
+    pub fn port_in(&mut self, port: u8) -> Option<u8> {
+        match port {
+            RTC_SEC..=RTC_CEN => Some(self.encode(self.fields()[(port - RTC_SEC) as usize])),
+            RTC_ASEC..=RTC_ADOW => Some(self.alarm[(port - RTC_ASEC) as usize]),
+            RTC_ACTRL => Some(self.actrl),
+            RTC_CTRL => {
+                let value = self.ctrl;
+                self.ctrl &= !RTC_ALARM;
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes an rtc register. Returns false for other ports. The time
+    /// registers only change while RTC_CTRL is unlocked.
+    pub fn port_out(&mut self, port: u8, value: u8) -> bool {
+        match port {
+            RTC_SEC..=RTC_CEN => {
+                let index = (port - RTC_SEC) as usize;
+                if self.ctrl & RTC_UNLOCK != 0 && index != 3 {
+                    let mut fields = self.fields();
+                    fields[index] = self.decode(value);
+                    let seconds = seconds_from_fields(&fields);
+                    let time = match self.time {
+                        RtcTime::Offset(_) => RtcTime::Offset(seconds - self.host_seconds()),
+                        RtcTime::Frozen(_) => RtcTime::Frozen(seconds),
+                    };
+                    self.set_time(time);
+                }
+                true
+            }
+            RTC_ASEC..=RTC_ADOW => {
+                self.alarm[(port - RTC_ASEC) as usize] = value;
+                true
+            }
+            RTC_ACTRL => {
+                self.actrl = value & 0x0f;
+                true
+            }
+            RTC_CTRL => {
+                self.ctrl = (self.ctrl & RTC_ALARM) | (value & !RTC_ALARM);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn host_seconds(&self) -> i64 {
+        (self.clock.millis(self.cycles) / 1000) as i64
+    }
+
+    fn seconds(&self) -> i64 {
+        match self.time {
+            RtcTime::Offset(offset) => self.host_seconds() + offset,
+            RtcTime::Frozen(seconds) => seconds,
+        }
+    }
+
+    fn fields(&self) -> [i64; 8] {
+        fields_from_seconds(self.now)
+    }
+
+    fn alarm_matches(&self, seconds: i64) -> bool {
+        let fields = fields_from_seconds(seconds);
+        // RTC_ASEC, RTC_AMIN, RTC_AHRS and RTC_ADOW against sec, min,
+        // hrs and dow
+        (0..4).filter(|i| self.actrl & (1 << i) != 0)
+            .all(|i| self.alarm[i] == self.encode(fields[i]))
+    }
+
+    fn encode(&self, value: i64) -> u8 {
+        let value = value as u8;
+        if self.ctrl & RTC_BCD_EN != 0 {
+            ((value / 10) << 4) | (value % 10)
+        } else {
+            value
+        }
+    }
+
+    fn decode(&self, value: u8) -> i64 {
+        if self.ctrl & RTC_BCD_EN != 0 {
+            ((value >> 4) * 10 + (value & 0x0f)) as i64
+        } else {
+            value as i64
+        }
+    }
+}
+
+impl IoDevice for Rtc {
+    fn port_in(&mut self, address: u16) -> u8 {
+        Rtc::port_in(self, address as u8).unwrap_or(0x00)
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        Rtc::port_out(self, address as u8, value);
+    }
+    fn tick(&mut self, cycles: u32, lines: &mut CpuLines) {
+        self.update(self.cycles + cycles as u64);
+        if let Some(number) = self.irq {
+            // The line stays active until the handler reads RTC_CTRL
+            if self.ctrl & RTC_ALARM != 0 && self.ctrl & RTC_INT_EN != 0 {
+                lines.assert_irq(number);
+            } else {
+                lines.deassert_irq(number);
+            }
+        }
+    }
+}
+
+// The date and time in the order of the registers, with the year
+// within the century
+fn fields_from_seconds(seconds: i64) -> [i64; 8] {
+    let days = seconds.div_euclid(SECONDS_PER_DAY);
+    let time = seconds.rem_euclid(SECONDS_PER_DAY);
+    let (year, month, day) = civil_from_days(days);
+    // 1970-01-01 was a Thursday, day 5 counting Sunday as 1
+    let dow = (days + 4).rem_euclid(7) + 1;
+    [time % 60, time / 60 % 60, time / 3600, dow, day, month, year % 100, year / 100]
+}
+
+fn seconds_from_fields(fields: &[i64; 8]) -> i64 {
+    let [sec, min, hrs, _, day, month, year, century] = *fields;
+    let days = days_from_civil(century * 100 + year, month.clamp(1, 12), day);
+    days * SECONDS_PER_DAY + hrs * 3600 + min * 60 + sec
+}
+
+// Days since 1970-01-01 of a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// Year, month and day of the days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
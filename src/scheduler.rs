@@ -0,0 +1,108 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+struct Entry<E> {
+    at: u64,
+    // Order of scheduling, for events at the same cycle
+    seq: u64,
+    event: E,
+}
+
+impl<E> PartialEq for Entry<E> {
+    fn eq(&self, other: &Self) -> bool {
+        self.at == other.at && self.seq == other.seq
+    }
+}
+
+impl<E> Eq for Entry<E> {}
+
+impl<E> PartialOrd for Entry<E> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E> Ord for Entry<E> {
+    // Reversed, BinaryHeap pops the greatest and we want the earliest
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.at.cmp(&self.at).then(other.seq.cmp(&self.seq))
+    }
+}
+
+/// Queue of the events of the machine, like timers or vsync, ordered
+/// by the cpu cycle they happen at
+///
+/// Use it with Cpu::run_to_event to run the cpu up to the next event,
+/// then handle the events due:
+///
+/// ```
+/// use ez80::*;
+///
+/// let mut machine = PlainMachine::new();
+/// let mut cpu = Cpu::new_ez80();
+/// let mut scheduler = Scheduler::new();
+/// scheduler.schedule(1000, "timer");
+///
+/// cpu.run_to_event(&mut machine, &scheduler, 5000);
+/// while let Some((_at, event)) = scheduler.pop_due(cpu.state.cycles) {
+///     assert_eq!("timer", event);
+/// }
+/// ```
+pub struct Scheduler<E> {
+    queue: BinaryHeap<Entry<E>>,
+    seq: u64,
+}
+
+impl<E> Scheduler<E> {
+    pub fn new() -> Scheduler<E> {
+        Scheduler {
+            queue: BinaryHeap::new(),
+            seq: 0,
+        }
+    }
+
+    /// Adds an event at the cycle count at. Events at the same cycle
+    /// are delivered in the order they are scheduled.
+    pub fn schedule(&mut self, at: u64, event: E) {
+        self.queue.push(Entry { at, seq: self.seq, event });
+        self.seq += 1;
+    }
+
+    /// Returns the cycle of the earliest event
+    pub fn next_at(&self) -> Option<u64> {
+        self.queue.peek().map(|e| e.at)
+    }
+
+    /// Removes and returns the earliest event if it is due at the
+    /// cycle count now
+    pub fn pop_due(&mut self, now: u64) -> Option<(u64, E)> {
+        if self.next_at()? <= now {
+            self.queue.pop().map(|e| (e.at, e.event))
+        } else {
+            None
+        }
+    }
+
+    /// Removes the events the predicate is true for
+    pub fn cancel<F: FnMut(&E) -> bool>(&mut self, mut predicate: F) {
+        self.queue.retain(|e| !predicate(&e.event));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+
+impl<E> Default for Scheduler<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,350 @@
+//! Built-in conformance check of the CPU core
+//!
+//! A set of small guest programs exercising ISA corners, mixed ADL
+//! mode, interrupts and NMI. Each program runs on a PlainMachine until
+//! HALT and the resulting state is compared with the expected values.
+//! Timers are peripherals of the hosting machine and are not covered.
+//!
+//! ```
+//! let report = ez80::selftest::run();
+//! println!("{}", report);
+//! assert!(report.passed());
+//! ```
+
+use std::fmt;
+
+use crate::cpu::Cpu;
+use crate::environment::Environment;
+use crate::machine::{Machine, PlainMachine};
+use crate::registers::*;
+
+const MAX_STEPS: u32 = 10_000;
+
+enum Event {
+    Interrupt(u32),
+    Nmi,
+}
+
+struct Vector {
+    name: &'static str,
+    adl: bool,
+    code: &'static [u8],
+    data: &'static [(u32, &'static [u8])],
+    // Event triggered after the number of instructions executed
+    event: Option<(u32, Event)>,
+    check: fn(&Cpu, &PlainMachine) -> Result<(), String>,
+}
+
+/// Outcome of one of the self-test programs
+#[derive(Clone, Debug)]
+pub struct TestResult {
+    pub name: &'static str,
+    /// None if the test passed, the mismatch found otherwise
+    pub failure: Option<String>,
+}
+
+/// Conformance report of the current build
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub results: Vec<TestResult>,
+}
+
+impl Report {
+    /// Returns true if all the tests passed
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|r| r.failure.is_none())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let passed = self.results.iter().filter(|r| r.failure.is_none()).count();
+        writeln!(f, "ez80 {} self-test: {}/{} passed", env!("CARGO_PKG_VERSION"), passed, self.results.len())?;
+        for r in &self.results {
+            match &r.failure {
+                None => writeln!(f, "  ok    {}", r.name)?,
+                Some(e) => writeln!(f, "  FAIL  {}: {}", r.name, e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs all the self-test programs with the eZ80 cpu
+pub fn run() -> Report {
+    Report {
+        results: VECTORS.iter().map(|v| TestResult {
+            name: v.name,
+            failure: run_vector(v).err(),
+        }).collect()
+    }
+}
+
+fn run_vector(v: &Vector) -> Result<(), String> {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(v.adl);
+    for (i, e) in v.code.iter().enumerate() {
+        sys.poke(i as u32, *e);
+    }
+    for (address, bytes) in v.data {
+        for (i, e) in bytes.iter().enumerate() {
+            sys.poke(address + i as u32, *e);
+        }
+    }
+
+    for step in 0..MAX_STEPS {
+        match v.event {
+            Some((at, Event::Interrupt(number))) if at == step => {
+                Environment::new(&mut cpu.state, &mut sys).interrupt(number);
+            }
+            Some((at, Event::Nmi)) if at == step => cpu.signal_nmi(),
+            _ => {}
+        }
+        cpu.execute_instruction(&mut sys);
+        if cpu.is_halted() {
+            return (v.check)(&cpu, &sys);
+        }
+    }
+    Err(format!("no HALT after {} instructions, PC=${:06x}", MAX_STEPS, cpu.state.pc()))
+}
+
+fn expect(what: &str, value: u32, expected: u32) -> Result<(), String> {
+    if value == expected {
+        Ok(())
+    } else {
+        Err(format!("{} is ${:x}, expected ${:x}", what, value, expected))
+    }
+}
+
+fn expect_flags(reg: &Registers, flags: &[(Flag, bool)]) -> Result<(), String> {
+    for (flag, expected) in flags {
+        if reg.get_flag(*flag) != *expected {
+            return Err(format!("flag {:?} is {}, expected {}", flag, !expected, expected));
+        }
+    }
+    Ok(())
+}
+
+const VECTORS: &[Vector] = &[
+    Vector {
+        name: "add a,n flags",
+        adl: false,
+        code: &[
+            0x3e, 0x7f, // LD A, $7f
+            0xc6, 0x01, // ADD A, $01
+            0x76,       // HALT
+        ],
+        data: &[],
+        event: None,
+        check: |cpu, _| {
+            let reg = &cpu.state.reg;
+            expect("A", reg.a() as u32, 0x80)?;
+            expect_flags(reg, &[(Flag::S, true), (Flag::Z, false), (Flag::H, true),
+                (Flag::P, true), (Flag::N, false), (Flag::C, false)])
+        },
+    },
+    Vector {
+        name: "daa",
+        adl: false,
+        code: &[
+            0x3e, 0x15, // LD A, $15
+            0xc6, 0x27, // ADD A, $27
+            0x27,       // DAA
+            0x76,       // HALT
+        ],
+        data: &[],
+        event: None,
+        check: |cpu, _| {
+            expect("A", cpu.state.reg.a() as u32, 0x42)?;
+            expect_flags(&cpu.state.reg, &[(Flag::C, false)])
+        },
+    },
+    Vector {
+        name: "neg",
+        adl: false,
+        code: &[
+            0x3e, 0x01, // LD A, $01
+            0xed, 0x44, // NEG
+            0x76,       // HALT
+        ],
+        data: &[],
+        event: None,
+        check: |cpu, _| {
+            expect("A", cpu.state.reg.a() as u32, 0xff)?;
+            expect_flags(&cpu.state.reg, &[(Flag::C, true), (Flag::N, true)])
+        },
+    },
+    Vector {
+        name: "index register halves",
+        adl: false,
+        code: &[
+            0xdd, 0x21, 0x34, 0x12, // LD IX, $1234
+            0xdd, 0x7c,             // LD A, IXH
+            0xdd, 0x6f,             // LD IXL, A
+            0x76,                   // HALT
+        ],
+        data: &[],
+        event: None,
+        check: |cpu, _| {
+            expect("A", cpu.state.reg.a() as u32, 0x12)?;
+            expect("IX", cpu.state.reg.get16(Reg16::IX) as u32, 0x1212)
+        },
+    },
+    Vector {
+        name: "ld rr,nn 24 bits",
+        adl: true,
+        code: &[
+            0x21, 0x56, 0x34, 0x12, // LD HL, $123456
+            0x76,                   // HALT
+        ],
+        data: &[],
+        event: None,
+        check: |cpu, _| {
+            expect("HL", cpu.state.reg.get24(Reg16::HL), 0x123456)?;
+            expect("PC", cpu.state.pc(), 0x000005)
+        },
+    },
+    Vector {
+        name: "sbc hl,de 24 bits",
+        adl: true,
+        code: &[
+            0x21, 0x00, 0x00, 0x00, // LD HL, $000000
+            0x11, 0x01, 0x00, 0x00, // LD DE, $000001
+            0xb7,                   // OR A
+            0xed, 0x52,             // SBC HL, DE
+            0x76,                   // HALT
+        ],
+        data: &[],
+        event: None,
+        check: |cpu, _| {
+            expect("HL", cpu.state.reg.get24(Reg16::HL), 0xffffff)?;
+            expect_flags(&cpu.state.reg, &[(Flag::C, true), (Flag::S, true), (Flag::Z, false)])
+        },
+    },
+    Vector {
+        name: "mlt",
+        adl: true,
+        code: &[
+            0x01, 0x0d, 0x0c, 0x00, // LD BC, $000c0d
+            0xed, 0x4c,             // MLT BC
+            0x76,                   // HALT
+        ],
+        data: &[],
+        event: None,
+        check: |cpu, _| expect("BC", cpu.state.reg.get16(Reg16::BC) as u32, 0x009c),
+    },
+    Vector {
+        name: "lea",
+        adl: true,
+        code: &[
+            0xdd, 0x21, 0x00, 0x10, 0x00, // LD IX, $001000
+            0xed, 0x22, 0x10,             // LEA HL, IX+$10
+            0xed, 0x12, 0xf0,             // LEA DE, IX-$10
+            0x76,                         // HALT
+        ],
+        data: &[],
+        event: None,
+        check: |cpu, _| {
+            expect("HL", cpu.state.reg.get24(Reg16::HL), 0x001010)?;
+            expect("DE", cpu.state.reg.get24(Reg16::DE), 0x000ff0)
+        },
+    },
+    Vector {
+        name: "ldir",
+        adl: true,
+        code: &[
+            0x21, 0x00, 0x01, 0x00, // LD HL, $000100
+            0x11, 0x00, 0x02, 0x00, // LD DE, $000200
+            0x01, 0x04, 0x00, 0x00, // LD BC, $000004
+            0xed, 0xb0,             // LDIR
+            0x76,                   // HALT
+        ],
+        data: &[(0x0100, &[0xca, 0xfe, 0xba, 0xbe])],
+        event: None,
+        check: |cpu, sys| {
+            expect("BC", cpu.state.reg.get24(Reg16::BC), 0)?;
+            expect("DE", cpu.state.reg.get24(Reg16::DE), 0x000204)?;
+            expect("($000200)", sys._peek24(0x000200), 0xbafeca)?;
+            expect("($000203)", sys.peek(0x000203) as u32, 0xbe)
+        },
+    },
+    Vector {
+        name: "call.is and ret.l between modes",
+        adl: true,
+        code: &[
+            0x31, 0x00, 0x00, 0x02, // LD SP, $020000
+            0x40, 0x31, 0x00, 0xff, // LD.SIS SP, $ff00
+            0x3e, 0x00,             // LD A, $00
+            0xed, 0x6d,             // LD MB, A
+            0x40, 0xcd, 0x20, 0x00, // CALL.IS $0020
+            0x76,                   // HALT
+        ],
+        data: &[(0x0020, &[
+            0x3c,                   // INC A
+            0x49, 0xc9,             // RET.L
+        ])],
+        event: None,
+        check: |cpu, _| {
+            expect("A", cpu.state.reg.a() as u32, 1)?;
+            expect("ADL", cpu.state.reg.adl as u32, 1)?;
+            expect("PC", cpu.state.pc(), 0x000011)?;
+            expect("SPL", cpu.state.reg.get24(Reg16::SP), 0x020000)?;
+            expect("SPS", cpu.state.reg.get16(Reg16::SP) as u32, 0xff00)
+        },
+    },
+    Vector {
+        name: "interrupt im 2 in mixed mode",
+        adl: true,
+        code: &[
+            0x31, 0x00, 0x00, 0x02, // LD SP, $020000
+            0xed, 0x7d,             // STMIX
+            0x3e, 0x01,             // LD A, $01
+            0xed, 0x47,             // LD I, A
+            0xed, 0x5e,             // IM 2
+            0x06, 0x00,             // LD B, $00
+            0xfb,                   // EI
+            0x78,                   // LD A, B
+            0xb7,                   // OR A
+            0x28, 0xfc,             // JR Z, $00000f
+            0x76,                   // HALT
+        ],
+        data: &[
+            (0x0110, &[0x40, 0x00]), // Vector $10 to $0040
+            (0x0040, &[
+                0x04,             // INC B
+                0x5b, 0xed, 0x4d, // RETI.L
+            ]),
+        ],
+        event: Some((20, Event::Interrupt(0x10))),
+        check: |cpu, _| {
+            expect("B", cpu.state.reg.get8(Reg8::B) as u32, 1)?;
+            expect("ADL", cpu.state.reg.adl as u32, 1)?;
+            expect("PC", cpu.state.pc(), 0x000014)?;
+            expect("SPL", cpu.state.reg.get24(Reg16::SP), 0x020000)
+        },
+    },
+    Vector {
+        name: "nmi and retn",
+        adl: false,
+        code: &[
+            0x31, 0x00, 0xff, // LD SP, $ff00
+            0x0e, 0x00,       // LD C, $00
+            0xfb,             // EI
+            0x79,             // LD A, C
+            0xb7,             // OR A
+            0x28, 0xfc,       // JR Z, $0006
+            0x76,             // HALT
+        ],
+        data: &[(0x0066, &[
+            0x0c,       // INC C
+            0xed, 0x45, // RETN
+        ])],
+        event: Some((20, Event::Nmi)),
+        check: |cpu, _| {
+            expect("C", cpu.state.reg.get8(Reg8::C) as u32, 1)?;
+            expect("IFF1", cpu.state.reg.get_iff1() as u32, 1)?;
+            expect("SP", cpu.state.reg.get16(Reg16::SP) as u32, 0xff00)
+        },
+    },
+];
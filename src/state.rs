@@ -1,8 +1,12 @@
+use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use super::registers::*;
 
 /// ez80 opcode "suffixes". we call them prefixes here
 /// because they appear before the opcode in machine code
 #[derive(Clone,Copy,Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum SizePrefix {
     None,
     LIL,
@@ -11,6 +15,30 @@ pub enum SizePrefix {
     SIS
 }
 
+/// Source of the host time read by the guest through the emulator
+/// services
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostClock {
+    /// The time of the host, in milliseconds since the Unix epoch
+    System,
+    /// A deterministic clock for reproducible runs: start milliseconds
+    /// at cycle 0, advancing one millisecond every cycles_per_ms cycles
+    Virtual { start: u64, cycles_per_ms: u64 },
+}
+
+impl HostClock {
+    /// Returns the time in milliseconds since the Unix epoch, when the
+    /// virtual clock of the cpu is at the given cycles
+    pub fn millis(&self, cycles: u64) -> u64 {
+        match *self {
+            HostClock::System => SystemTime::now().duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_millis() as u64),
+            HostClock::Virtual { start, cycles_per_ms } =>
+                start + cycles / cycles_per_ms.max(1),
+        }
+    }
+}
+
 /// Internal state of the CPU
 /// 
 /// Stores the state of the registers and additional hidden execution
@@ -30,6 +58,30 @@ pub struct State {
     pub displacement: i8, // Used for (IX+d) and (iY+d)
     pub sz_prefix: SizePrefix,
     pub instructions_executed: u64,
+    /// Bus cycles elapsed. A virtual clock that depends only on the
+    /// instructions executed, for deterministic timing.
+    pub cycles: u64,
+    /// Iterations of LDIR, LDDR, INIR, INDR, OTIR and OTDR done in a
+    /// single step. With 1, interrupts can happen between iterations
+    /// like on the hardware.
+    pub block_burst: u32,
+    /// The last instruction was EI: maskable interrupts are accepted
+    /// after the next one
+    pub ei_delay: bool,
+    /// Vector numbers of the interrupt lines held active, see
+    /// Cpu::assert_irq
+    pub irq_lines: BTreeSet<u32>,
+    /// First port of the emulator services, if enabled. See
+    /// Cpu::set_services_port.
+    pub services_port: Option<u16>,
+    /// Cycle counter or host time latched by the guest in the emulator
+    /// services
+    pub services_latch: u64,
+    /// Host time of the emulator services
+    pub host_clock: HostClock,
+    /// Bytes written by the guest to the console of the emulator
+    /// services, see Cpu::take_services_output
+    pub services_output: Vec<u8>,
 }
 
 impl State {
@@ -44,9 +96,37 @@ impl State {
             displacement: 0,
             sz_prefix: SizePrefix::None,
             instructions_executed: 0,
+            cycles: 0,
+            block_burst: 1,
+            ei_delay: false,
+            irq_lines: BTreeSet::new(),
+            services_port: None,
+            services_latch: 0,
+            host_clock: HostClock::System,
+            services_output: Vec::new(),
         }
     }
 
+    /// Applies the reset of the eZ80: PC, I, R and MBASE to zero, Z80
+    /// mode, interrupts disabled in mode 0. The other registers are
+    /// kept. The clock keeps counting.
+    pub fn reset(&mut self) {
+        self.reset_pending = false;
+        self.nmi_pending = false;
+        self.halted = false;
+        self.reg.adl = false;
+        self.reg.madl = false;
+        self.reg.mbase = 0x00;
+        self.set_pc(0x0000);
+        self.reg.set_i16(0x0000);
+        self.reg.set8(Reg8::R, 0x00);
+        self.reg.set_interrupts(false);
+        self.reg.set_interrupt_mode(0);
+        self.ei_delay = false;
+        self.index = Reg16::HL;
+        self.clear_sz_prefix();
+    }
+
     pub fn clear_sz_prefix(&mut self) {
         self.sz_prefix = SizePrefix::None;
     }
@@ -85,23 +165,45 @@ impl State {
         if self.reg.adl {
             self.reg.pc
         } else {
-            ((self.reg.mbase as u32) << 16) + (self.reg.pc & 0xffff) as u32
+            ((self.reg.mbase as u32) << 16) + (self.reg.pc & 0xffff)
         }
     }
 
     pub fn set_pc(&mut self, value: u32) {
         self.reg.pc = value & 0xffffff;
     }
+
+    /// Returns true if the cpu is stopped by HALT or SLP, waiting for
+    /// an interrupt
+    pub fn is_halted(&self) -> bool {
+        self.halted && !self.nmi_pending && !self.reset_pending && self.active_irq().is_none()
+    }
+
+    /// Whether a maskable interrupt would be accepted now: enabled, and
+    /// not right after EI
+    pub fn accepts_interrupt(&self) -> bool {
+        self.reg.get_iff1() && !self.ei_delay
+    }
+
+    /// Returns the interrupt line to accept now, the lowest vector
+    /// number has priority
+    pub(crate) fn active_irq(&self) -> Option<u32> {
+        if self.accepts_interrupt() {
+            self.irq_lines.iter().next().copied()
+        } else {
+            None
+        }
+    }
 }
 
 impl std::fmt::Display for SizePrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", match self {
-            &SizePrefix::LIL => ".LIL",
-            &SizePrefix::LIS => ".LIS",
-            &SizePrefix::SIL => ".SIL",
-            &SizePrefix::SIS => ".SIS",
-            &SizePrefix::None => "",
+            SizePrefix::LIL => ".LIL",
+            SizePrefix::LIS => ".LIS",
+            SizePrefix::SIL => ".SIL",
+            SizePrefix::SIS => ".SIS",
+            SizePrefix::None => "",
 
         })
     }
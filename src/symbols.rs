@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Names of the addresses of a program, to show them in traces,
+/// disassembly and backtraces
+///
+/// The symbol files have a symbol per line, with the address and the
+/// name in any of these forms:
+///
+/// ```text
+/// 0B3C2 vdp_protocol_handler
+/// vdp_protocol_handler = $0B3C2
+/// vdp_protocol_handler: EQU 0B3C2h
+/// ```
+///
+/// Addresses are hex, with or without a `$`, `0x` or `h` marker. Empty
+/// lines and lines starting with `;` or `#` are ignored.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    by_address: BTreeMap<u32, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    /// Loads a symbol file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Symbols> {
+        let text = fs::read_to_string(path)?;
+        Symbols::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Parses the text of a symbol file. Returns the first line not
+    /// understood as error.
+    pub fn parse(text: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+            match parse_line(line) {
+                Some((name, address)) => symbols.add(name, address),
+                None => return Err(format!("line {}: unknown symbol format: {}", number + 1, line)),
+            }
+        }
+        Ok(symbols)
+    }
+
+    /// Adds a symbol. The first name of an address is kept.
+    pub fn add(&mut self, name: &str, address: u32) {
+        self.by_address.entry(address).or_insert_with(|| name.to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_address.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_address.is_empty()
+    }
+
+    /// Returns the name of the address, if it has one
+    pub fn name(&self, address: u32) -> Option<&str> {
+        self.by_address.get(&address).map(|name| name.as_str())
+    }
+
+    /// Returns the closest symbol at or below the address and the
+    /// offset from it
+    pub fn lookup(&self, address: u32) -> Option<(&str, u32)> {
+        self.by_address.range(..=address).next_back()
+            .map(|(start, name)| (name.as_str(), address - start))
+    }
+
+    /// Returns the address as `name` or `name+0x12`, or as `$0b3c2`
+    /// when there is no symbol below it
+    pub fn describe(&self, address: u32) -> String {
+        match self.lookup(address) {
+            Some((name, 0)) => name.to_string(),
+            Some((name, offset)) => format!("{}+0x{:x}", name, offset),
+            None => format!("${:05x}", address),
+        }
+    }
+
+    /// Replaces the addresses in a disassembled instruction, like
+    /// `CALL $b3c2`, with their names. Only addresses with a symbol of
+    /// their own are replaced. The address operands are the targets of
+    /// JP, CALL, JR, DJNZ and RST, the memory operands like `($b3c2)`
+    /// and the immediates of `LD rr, nn`. 8 bit immediates, port
+    /// numbers and displacements are left as they are.
+    pub fn symbolize_asm(&self, asm: &str) -> String {
+        let (mnemonic, operands) = asm.split_once(' ').unwrap_or((asm, ""));
+        // Without the .LIL style suffix
+        let base = mnemonic.split('.').next().unwrap_or(mnemonic);
+        if base == "RST" {
+            let name = operands.strip_suffix('h')
+                .and_then(|vector| u32::from_str_radix(vector, 16).ok())
+                .and_then(|address| self.name(address));
+            return match name {
+                Some(name) => format!("{} {}", mnemonic, name),
+                None => asm.to_string(),
+            };
+        }
+        let jump = matches!(base, "JP" | "CALL" | "JR" | "DJNZ");
+        let port = matches!(base, "IN" | "OUT" | "IN0" | "OUT0" | "TSTIO");
+        let wide_load = base == "LD" && operands.split(',').next()
+            .is_some_and(|r| matches!(r.trim(), "BC" | "DE" | "HL" | "SP" | "IX" | "IY"));
+
+        let mut out = String::with_capacity(asm.len());
+        let mut rest = asm;
+        while let Some(pos) = rest.find('$') {
+            let (before, after) = rest.split_at(pos);
+            out.push_str(before);
+            let digits = after[1..].chars().take_while(|c| c.is_ascii_hexdigit()).count();
+            let token = &after[..digits + 1];
+            rest = &after[digits + 1..];
+
+            let displacement = before.ends_with('+') || before.ends_with('-');
+            let indirect = before.ends_with('(') && rest.starts_with(')');
+            let immediate = wide_load && before.ends_with(", ");
+            let address = !displacement && !port && (jump || indirect || immediate);
+            let name = match u32::from_str_radix(&token[1..], 16) {
+                Ok(value) if address => self.name(value),
+                _ => None,
+            };
+            out.push_str(name.unwrap_or(token));
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+fn parse_address(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let digits = if let Some(hex) = text.strip_prefix('$') {
+        hex
+    } else if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        hex
+    } else if let Some(hex) = text.strip_suffix('h').or_else(|| text.strip_suffix('H')) {
+        hex
+    } else {
+        text
+    };
+    u32::from_str_radix(digits, 16).ok()
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '@')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '@')
+}
+
+fn parse_line(line: &str) -> Option<(&str, u32)> {
+    // name = address
+    if let Some((name, address)) = line.split_once('=') {
+        let name = name.trim();
+        return if is_name(name) { Some((name, parse_address(address)?)) } else { None };
+    }
+
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        // name: EQU address, name EQU address
+        [name, equ, address] if equ.eq_ignore_ascii_case("equ") => {
+            let name = name.trim_end_matches(':');
+            if is_name(name) { Some((name, parse_address(address)?)) } else { None }
+        }
+        // address name
+        [address, name] if is_name(name) => Some((name, parse_address(address)?)),
+        _ => None,
+    }
+}
@@ -0,0 +1,779 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::rc::Rc;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+
+use super::registers::*;
+use super::symbols::Symbols;
+
+/// An instruction executed, as reported to a Tracer
+#[derive(Clone, Debug)]
+pub struct InstructionTrace {
+    /// Address of the instruction
+    pub pc: u32,
+    /// Length of the instruction in bytes, prefixes included
+    pub len: u32,
+    /// The instruction disassembled
+    pub asm: String,
+    /// Registers after the execution
+    pub reg: Registers,
+    /// Instructions executed so far, this one included
+    pub instructions_executed: u64,
+    /// Bus cycles elapsed after the execution
+    pub cycles: u64,
+}
+
+/// Receiver of the execution events of a Cpu
+///
+/// Install it with Cpu::set_tracer. The memory and port accesses of
+/// an instruction are reported before the instruction itself. Opcode
+/// fetches are not reported as memory reads.
+///
+/// To inspect a tracer while it is installed, share it as an
+/// `Rc<RefCell<T>>`, that implements Tracer as well.
+pub trait Tracer {
+    fn instruction(&mut self, _trace: &InstructionTrace) {}
+    fn memory_read(&mut self, _address: u32, _value: u8) {}
+    fn memory_write(&mut self, _address: u32, _value: u8) {}
+    fn port_read(&mut self, _address: u16, _value: u8) {}
+    fn port_write(&mut self, _address: u16, _value: u8) {}
+    /// An interrupt or NMI has been accepted. The handler is at address.
+    fn interrupt(&mut self, _address: u32) {}
+    /// A CALL or RST has entered the subroutine at address. Reported
+    /// after the instruction.
+    fn subroutine_call(&mut self, _address: u32) {}
+    /// The step at pc has ended without an instruction: a PC hook has
+    /// replaced it, or an illegal instruction has stopped the cpu.
+    /// cycles is the clock at the end of the step.
+    fn no_instruction(&mut self, _pc: u32, _cycles: u64) {}
+}
+
+impl<T: Tracer> Tracer for Rc<RefCell<T>> {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        self.borrow_mut().instruction(trace);
+    }
+    fn memory_read(&mut self, address: u32, value: u8) {
+        self.borrow_mut().memory_read(address, value);
+    }
+    fn memory_write(&mut self, address: u32, value: u8) {
+        self.borrow_mut().memory_write(address, value);
+    }
+    fn port_read(&mut self, address: u16, value: u8) {
+        self.borrow_mut().port_read(address, value);
+    }
+    fn port_write(&mut self, address: u16, value: u8) {
+        self.borrow_mut().port_write(address, value);
+    }
+    fn interrupt(&mut self, address: u32) {
+        self.borrow_mut().interrupt(address);
+    }
+    fn subroutine_call(&mut self, address: u32) {
+        self.borrow_mut().subroutine_call(address);
+    }
+    fn no_instruction(&mut self, pc: u32, cycles: u64) {
+        self.borrow_mut().no_instruction(pc, cycles);
+    }
+}
+
+/// Human readable log of the instructions, ports and interrupts
+pub struct LogTracer<W: Write> {
+    out: W,
+    symbols: Option<Symbols>,
+}
+
+impl<W: Write> LogTracer<W> {
+    pub fn new(out: W) -> LogTracer<W> {
+        LogTracer { out, symbols: None }
+    }
+
+    /// Returns a tracer showing the names of the addresses. The symbols
+    /// are printed as labels and replace the addresses in the instructions.
+    pub fn with_symbols(out: W, symbols: Symbols) -> LogTracer<W> {
+        LogTracer { out, symbols: Some(symbols) }
+    }
+
+    /// Returns the writer receiving the trace
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+}
+
+impl<W: Write> Tracer for LogTracer<W> {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        let reg = &trace.reg;
+        let pc = if reg.adl {
+            reg.pc
+        } else {
+            ((reg.mbase as u32) << 16) + (reg.pc & 0xffff)
+        };
+        let asm = match &self.symbols {
+            Some(symbols) => {
+                if let Some(name) = symbols.name(trace.pc) {
+                    let _ = writeln!(self.out, "{}:", name);
+                }
+                symbols.symbolize_asm(&trace.asm)
+            }
+            None => trace.asm.clone(),
+        };
+        let _ = writeln!(self.out, "==> {:06x}: {:20} PC:{:06x} AF:{:04x} BC:{:06x} DE:{:06x} HL:{:06x} SPS:{:04x} SPL:{:06x} IX:{:06x} IY:{:06x} MB {:02x} ADL {:01x} MADL {:01x} tick {}",
+            trace.pc,
+            asm,
+            pc,
+            reg.get16(Reg16::AF),
+            reg.get24(Reg16::BC),
+            reg.get24(Reg16::DE),
+            reg.get24(Reg16::HL),
+            reg.get16(Reg16::SP),
+            reg.get24(Reg16::SP),
+            reg.get24(Reg16::IX),
+            reg.get24(Reg16::IY),
+            reg.mbase,
+            reg.adl as i32,
+            reg.madl as i32,
+            trace.instructions_executed,
+        );
+    }
+
+    fn port_read(&mut self, address: u16, value: u8) {
+        let _ = writeln!(self.out, "    IN  ${:04x} -> ${:02x}", address, value);
+    }
+
+    fn port_write(&mut self, address: u16, value: u8) {
+        let _ = writeln!(self.out, "    OUT ${:04x} <- ${:02x}", address, value);
+    }
+
+    fn interrupt(&mut self, address: u32) {
+        match &self.symbols {
+            Some(symbols) => { let _ = writeln!(self.out, "    INT -> {}", symbols.describe(address)); }
+            None => { let _ = writeln!(self.out, "    INT -> ${:06x}", address); }
+        }
+    }
+}
+
+/// All the events, as one JSON object per line
+pub struct JsonTracer<W: Write> {
+    out: W,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(out: W) -> JsonTracer<W> {
+        JsonTracer { out }
+    }
+
+    /// Returns the writer receiving the trace
+    pub fn get_ref(&self) -> &W {
+        &self.out
+    }
+}
+
+impl<W: Write> Tracer for JsonTracer<W> {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        let reg = &trace.reg;
+        let asm = trace.asm.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(self.out, "{{\"event\":\"instruction\",\"pc\":{},\"asm\":\"{}\",\"af\":{},\"bc\":{},\"de\":{},\"hl\":{},\"ix\":{},\"iy\":{},\"sps\":{},\"spl\":{},\"mbase\":{},\"adl\":{},\"madl\":{},\"instructions\":{}}}",
+            trace.pc,
+            asm,
+            reg.get16(Reg16::AF),
+            reg.get24(Reg16::BC),
+            reg.get24(Reg16::DE),
+            reg.get24(Reg16::HL),
+            reg.get24(Reg16::IX),
+            reg.get24(Reg16::IY),
+            reg.get16(Reg16::SP),
+            reg.get24(Reg16::SP),
+            reg.mbase,
+            reg.adl,
+            reg.madl,
+            trace.instructions_executed,
+        );
+    }
+
+    fn memory_read(&mut self, address: u32, value: u8) {
+        let _ = writeln!(self.out, "{{\"event\":\"read\",\"address\":{},\"value\":{}}}", address, value);
+    }
+
+    fn memory_write(&mut self, address: u32, value: u8) {
+        let _ = writeln!(self.out, "{{\"event\":\"write\",\"address\":{},\"value\":{}}}", address, value);
+    }
+
+    fn port_read(&mut self, address: u16, value: u8) {
+        let _ = writeln!(self.out, "{{\"event\":\"in\",\"port\":{},\"value\":{}}}", address, value);
+    }
+
+    fn port_write(&mut self, address: u16, value: u8) {
+        let _ = writeln!(self.out, "{{\"event\":\"out\",\"port\":{},\"value\":{}}}", address, value);
+    }
+
+    fn interrupt(&mut self, address: u32) {
+        let _ = writeln!(self.out, "{{\"event\":\"interrupt\",\"address\":{}}}", address);
+    }
+}
+
+/// Keeps the last instructions executed, for post-mortem dumps
+pub struct RingTracer {
+    capacity: usize,
+    entries: VecDeque<InstructionTrace>,
+}
+
+impl RingTracer {
+    pub fn new(capacity: usize) -> RingTracer {
+        RingTracer {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the instructions kept, the oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &InstructionTrace> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Tracer for RingTracer {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(trace.clone());
+    }
+}
+
+impl fmt::Display for RingTracer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for trace in &self.entries {
+            writeln!(f, "{:06x}: {}", trace.pc, trace.asm)?;
+        }
+        Ok(())
+    }
+}
+
+/// Accesses to a page of memory counted by MemoryProfiler
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PageCounts {
+    pub reads: u64,
+    pub writes: u64,
+    /// Instructions starting in the page
+    pub executes: u64,
+}
+
+/// Counts the memory accesses per page and the calls per subroutine,
+/// to find the hotspots of a program
+pub struct MemoryProfiler {
+    page_bits: u32,
+    pages: HashMap<u32, PageCounts>,
+    calls: HashMap<u32, u64>,
+}
+
+impl MemoryProfiler {
+    /// Returns a profiler with pages of 2^page_bits bytes. With 0, the
+    /// accesses are counted by address.
+    pub fn new(page_bits: u32) -> MemoryProfiler {
+        MemoryProfiler {
+            page_bits,
+            pages: HashMap::new(),
+            calls: HashMap::new(),
+        }
+    }
+
+    fn page(&mut self, address: u32) -> &mut PageCounts {
+        self.pages.entry(address >> self.page_bits).or_default()
+    }
+
+    /// Returns the counts of the page containing address
+    pub fn counts(&self, address: u32) -> PageCounts {
+        self.pages.get(&(address >> self.page_bits)).copied().unwrap_or_default()
+    }
+
+    /// Returns the start address and counts of the pages accessed, in
+    /// address order
+    pub fn pages(&self) -> Vec<(u32, PageCounts)> {
+        let mut pages: Vec<(u32, PageCounts)> = self.pages.iter()
+            .map(|(page, counts)| (page << self.page_bits, *counts))
+            .collect();
+        pages.sort_by_key(|(address, _)| *address);
+        pages
+    }
+
+    /// Returns the subroutines called and how many times, the most
+    /// called first
+    pub fn calls(&self) -> Vec<(u32, u64)> {
+        let mut calls: Vec<(u32, u64)> = self.calls.iter()
+            .map(|(address, count)| (*address, *count))
+            .collect();
+        calls.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        calls
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.clear();
+        self.calls.clear();
+    }
+}
+
+impl Tracer for MemoryProfiler {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        self.page(trace.pc).executes += 1;
+    }
+    fn memory_read(&mut self, address: u32, _value: u8) {
+        self.page(address).reads += 1;
+    }
+    fn memory_write(&mut self, address: u32, _value: u8) {
+        self.page(address).writes += 1;
+    }
+    fn subroutine_call(&mut self, address: u32) {
+        *self.calls.entry(address).or_default() += 1;
+    }
+}
+
+impl fmt::Display for MemoryProfiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "page   executes reads writes")?;
+        for (address, counts) in self.pages() {
+            writeln!(f, "{:06x} {} {} {}", address, counts.executes, counts.reads, counts.writes)?;
+        }
+        writeln!(f, "subroutine calls")?;
+        for (address, count) in self.calls() {
+            writeln!(f, "{:06x} {}", address, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// A range of code executed, as reported by CodeCoverage
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoverageRange {
+    pub start: u32,
+    /// Address after the last byte
+    pub end: u32,
+    /// Times each instruction of the range has been executed
+    pub count: u64,
+}
+
+/// Records the instructions executed, to see which parts of a program
+/// a run has exercised
+#[derive(Default)]
+pub struct CodeCoverage {
+    // Length and count of the instructions, by address
+    instructions: BTreeMap<u32, (u32, u64)>,
+}
+
+impl CodeCoverage {
+    pub fn new() -> CodeCoverage {
+        CodeCoverage::default()
+    }
+
+    /// Returns the times the instruction at address has been executed
+    pub fn count(&self, address: u32) -> u64 {
+        self.instructions.get(&address).map_or(0, |(_, count)| *count)
+    }
+
+    /// Returns the code executed, in address order. Consecutive
+    /// instructions executed the same number of times are merged.
+    pub fn ranges(&self) -> Vec<CoverageRange> {
+        let mut ranges: Vec<CoverageRange> = Vec::new();
+        for (&address, &(len, count)) in &self.instructions {
+            match ranges.last_mut() {
+                Some(last) if last.end == address && last.count == count => {
+                    last.end = address + len;
+                }
+                _ => ranges.push(CoverageRange { start: address, end: address + len, count }),
+            }
+        }
+        ranges
+    }
+
+    /// Writes the ranges as text, a line "start end count" per range
+    /// with the addresses in hex
+    pub fn write_ranges(&self, out: &mut dyn Write) -> io::Result<()> {
+        for range in self.ranges() {
+            writeln!(out, "{:06x} {:06x} {}", range.start, range.end, range.count)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the ranges in the drcov format of DynamoRIO, read by
+    /// coverage viewers like Lighthouse. The whole address space is a
+    /// single module named module.
+    pub fn write_drcov(&self, out: &mut dyn Write, module: &str) -> io::Result<()> {
+        // drcov blocks are at most 64 KiB
+        let mut blocks = Vec::new();
+        for range in self.ranges() {
+            let mut start = range.start;
+            while start < range.end {
+                let size = (range.end - start).min(0xffff);
+                blocks.push((start, size as u16));
+                start += size;
+            }
+        }
+        writeln!(out, "DRCOV VERSION: 2")?;
+        writeln!(out, "DRCOV FLAVOR: ez80")?;
+        writeln!(out, "Module Table: version 2, count 1")?;
+        writeln!(out, "Columns: id, base, end, entry, checksum, timestamp, path")?;
+        writeln!(out, " 0, 0x0, 0x1000000, 0x0, 0x0, 0x0, {}", module)?;
+        writeln!(out, "BB Table: {} bbs", blocks.len())?;
+        for (start, size) in blocks {
+            out.write_all(&start.to_le_bytes())?;
+            out.write_all(&size.to_le_bytes())?;
+            out.write_all(&0u16.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.instructions.clear();
+    }
+}
+
+impl Tracer for CodeCoverage {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        let entry = self.instructions.entry(trace.pc).or_insert((trace.len, 0));
+        entry.0 = trace.len;
+        entry.1 += 1;
+    }
+}
+
+/// A port access recorded by IoLog
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IoAccess {
+    /// Address of the instruction doing the access
+    pub pc: u32,
+    /// Bus cycles elapsed after the instruction
+    pub cycles: u64,
+    pub port: u16,
+    pub value: u8,
+    /// true for port_out, false for port_in
+    pub write: bool,
+}
+
+/// Function called by IoLog on each access recorded
+pub type IoCallback = Box<dyn FnMut(&IoAccess)>;
+
+/// Accesses to a port counted by IoLog
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PortCounts {
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// Records the port accesses, to find the ports used by a program
+///
+/// The accesses are counted by port, passed to the callback if any,
+/// and the last ones are kept in a bounded buffer. With filters, only
+/// the ports in the filters are recorded.
+pub struct IoLog {
+    capacity: usize,
+    entries: VecDeque<IoAccess>,
+    // Accesses of the instruction not yet reported
+    pending: Vec<(u16, u8, bool)>,
+    filters: Vec<Range<u16>>,
+    counts: HashMap<u16, PortCounts>,
+    callback: Option<IoCallback>,
+}
+
+impl IoLog {
+    /// Returns a log keeping the last capacity accesses
+    pub fn new(capacity: usize) -> IoLog {
+        IoLog {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+            pending: Vec::new(),
+            filters: Vec::new(),
+            counts: HashMap::new(),
+            callback: None,
+        }
+    }
+
+    /// Records only the ports in range, in addition to the previous
+    /// filters
+    pub fn add_filter(&mut self, ports: Range<u16>) {
+        self.filters.push(ports);
+    }
+
+    pub fn clear_filters(&mut self) {
+        self.filters.clear();
+    }
+
+    /// Calls callback on each access recorded
+    pub fn set_callback(&mut self, callback: IoCallback) {
+        self.callback = Some(callback);
+    }
+
+    /// Returns the accesses kept, the oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &IoAccess> {
+        self.entries.iter()
+    }
+
+    /// Returns the ports accessed and their counts, in port order
+    pub fn ports(&self) -> Vec<(u16, PortCounts)> {
+        let mut ports: Vec<(u16, PortCounts)> = self.counts.iter()
+            .map(|(port, counts)| (*port, *counts))
+            .collect();
+        ports.sort_by_key(|(port, _)| *port);
+        ports
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.pending.clear();
+        self.counts.clear();
+    }
+
+    fn record(&mut self, port: u16, value: u8, write: bool) {
+        if self.filters.is_empty() || self.filters.iter().any(|f| f.contains(&port)) {
+            self.pending.push((port, value, write));
+        }
+    }
+
+    // Reports the accesses of the step that has ended
+    fn flush(&mut self, pc: u32, cycles: u64) {
+        for (port, value, write) in std::mem::take(&mut self.pending) {
+            let access = IoAccess { pc, cycles, port, value, write };
+            let counts = self.counts.entry(port).or_default();
+            if write {
+                counts.writes += 1;
+            } else {
+                counts.reads += 1;
+            }
+            if let Some(callback) = self.callback.as_mut() {
+                callback(&access);
+            }
+            if self.capacity > 0 {
+                if self.entries.len() == self.capacity {
+                    self.entries.pop_front();
+                }
+                self.entries.push_back(access);
+            }
+        }
+    }
+}
+
+impl Tracer for IoLog {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        self.flush(trace.pc, trace.cycles);
+    }
+    fn no_instruction(&mut self, pc: u32, cycles: u64) {
+        self.flush(pc, cycles);
+    }
+    fn port_read(&mut self, address: u16, value: u8) {
+        self.record(address, value, false);
+    }
+    fn port_write(&mut self, address: u16, value: u8) {
+        self.record(address, value, true);
+    }
+}
+
+impl fmt::Display for IoLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for access in &self.entries {
+            let dir = if access.write { "out" } else { "in " };
+            writeln!(f, "{:06x} {:>10} {} ${:04x} ${:02x}", access.pc, access.cycles, dir, access.port, access.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// What ThreadedTracer does when the queue is full
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait for the writer thread, slowing down the emulation
+    Block,
+    /// Discard the oldest event queued
+    DropOldest,
+}
+
+enum TraceEvent {
+    Instruction(InstructionTrace),
+    MemoryRead(u32, u8),
+    MemoryWrite(u32, u8),
+    PortRead(u16, u8),
+    PortWrite(u16, u8),
+    Interrupt(u32),
+    SubroutineCall(u32),
+    NoInstruction(u32, u64),
+}
+
+struct TraceQueue {
+    events: VecDeque<TraceEvent>,
+    closed: bool,
+    // The writer thread has panicked in the sink
+    failed: bool,
+    dropped: u64,
+}
+
+struct TraceChannel {
+    queue: Mutex<TraceQueue>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+}
+
+impl TraceChannel {
+    // The queue is left consistent by a panic, so a poisoned lock is fine
+    fn lock(&self) -> MutexGuard<'_, TraceQueue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+// Marks the channel failed when the writer thread unwinds, so that the
+// emulation does not wait for it forever
+struct WriterGuard(Arc<TraceChannel>);
+
+impl Drop for WriterGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            let mut queue = self.0.lock();
+            queue.failed = true;
+            queue.events.clear();
+            self.0.not_full.notify_all();
+        }
+    }
+}
+
+/// Forwards the events to a tracer running on its own thread
+///
+/// The events go through a bounded queue, so that a slow sink like a
+/// log file does not stall the emulation. The events queued are
+/// delivered when the ThreadedTracer is dropped. If the sink panics,
+/// the events are discarded from then on, see failed.
+pub struct ThreadedTracer {
+    channel: Arc<TraceChannel>,
+    policy: OverflowPolicy,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl ThreadedTracer {
+    pub fn new<T: Tracer + Send + 'static>(mut sink: T, capacity: usize, policy: OverflowPolicy) -> ThreadedTracer {
+        assert!(capacity > 0, "the trace queue needs some capacity");
+        let channel = Arc::new(TraceChannel {
+            queue: Mutex::new(TraceQueue {
+                events: VecDeque::with_capacity(capacity),
+                closed: false,
+                failed: false,
+                dropped: 0,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity,
+        });
+
+        let writer_channel = channel.clone();
+        let writer = thread::spawn(move || {
+            let guard = WriterGuard(writer_channel);
+            let channel = &guard.0;
+            loop {
+                let batch: Vec<TraceEvent> = {
+                    let mut queue = channel.lock();
+                    while queue.events.is_empty() && !queue.closed {
+                        queue = channel.not_empty.wait(queue).unwrap_or_else(PoisonError::into_inner);
+                    }
+                    if queue.events.is_empty() {
+                        break;
+                    }
+                    let batch = queue.events.drain(..).collect();
+                    channel.not_full.notify_all();
+                    batch
+                };
+                for event in batch {
+                    match event {
+                        TraceEvent::Instruction(trace) => sink.instruction(&trace),
+                        TraceEvent::MemoryRead(address, value) => sink.memory_read(address, value),
+                        TraceEvent::MemoryWrite(address, value) => sink.memory_write(address, value),
+                        TraceEvent::PortRead(address, value) => sink.port_read(address, value),
+                        TraceEvent::PortWrite(address, value) => sink.port_write(address, value),
+                        TraceEvent::Interrupt(address) => sink.interrupt(address),
+                        TraceEvent::SubroutineCall(address) => sink.subroutine_call(address),
+                        TraceEvent::NoInstruction(pc, cycles) => sink.no_instruction(pc, cycles),
+                    }
+                }
+            }
+        });
+
+        ThreadedTracer {
+            channel,
+            policy,
+            writer: Some(writer),
+        }
+    }
+
+    /// Returns the number of events discarded, with
+    /// OverflowPolicy::DropOldest or after the sink has panicked
+    pub fn dropped(&self) -> u64 {
+        self.channel.lock().dropped
+    }
+
+    /// Returns true when the sink has panicked on the writer thread
+    pub fn failed(&self) -> bool {
+        self.channel.lock().failed
+    }
+
+    fn send(&self, event: TraceEvent) {
+        let mut queue = self.channel.lock();
+        if queue.events.len() >= self.channel.capacity && !queue.failed {
+            match self.policy {
+                OverflowPolicy::Block => {
+                    while queue.events.len() >= self.channel.capacity && !queue.failed {
+                        queue = self.channel.not_full.wait(queue).unwrap_or_else(PoisonError::into_inner);
+                    }
+                }
+                OverflowPolicy::DropOldest => {
+                    queue.events.pop_front();
+                    queue.dropped += 1;
+                }
+            }
+        }
+        if queue.failed {
+            queue.dropped += 1;
+            return;
+        }
+        queue.events.push_back(event);
+        self.channel.not_empty.notify_one();
+    }
+}
+
+impl Drop for ThreadedTracer {
+    fn drop(&mut self) {
+        self.channel.lock().closed = true;
+        self.channel.not_empty.notify_all();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+impl Tracer for ThreadedTracer {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        self.send(TraceEvent::Instruction(trace.clone()));
+    }
+    fn memory_read(&mut self, address: u32, value: u8) {
+        self.send(TraceEvent::MemoryRead(address, value));
+    }
+    fn memory_write(&mut self, address: u32, value: u8) {
+        self.send(TraceEvent::MemoryWrite(address, value));
+    }
+    fn port_read(&mut self, address: u16, value: u8) {
+        self.send(TraceEvent::PortRead(address, value));
+    }
+    fn port_write(&mut self, address: u16, value: u8) {
+        self.send(TraceEvent::PortWrite(address, value));
+    }
+    fn interrupt(&mut self, address: u32) {
+        self.send(TraceEvent::Interrupt(address));
+    }
+    fn subroutine_call(&mut self, address: u32) {
+        self.send(TraceEvent::SubroutineCall(address));
+    }
+    fn no_instruction(&mut self, pc: u32, cycles: u64) {
+        self.send(TraceEvent::NoInstruction(pc, cycles));
+    }
+}
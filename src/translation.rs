@@ -0,0 +1,69 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Mapping of the cpu addresses to the addresses of the Machine
+///
+/// Install it with Cpu::set_address_translation to model banked memory
+/// or overlays. Without it, addresses reach the Machine unchanged.
+/// Breakpoints, watchpoints and traces use the cpu addresses.
+pub trait AddressTranslation {
+    fn translate(&self, address: u32) -> u32;
+}
+
+impl<T: AddressTranslation> AddressTranslation for Rc<T> {
+    fn translate(&self, address: u32) -> u32 {
+        (**self).translate(address)
+    }
+}
+
+// Smallest pages of a PageMap, 256 bytes, to keep the table small
+const MIN_PAGE_BITS: u32 = 8;
+
+/// Translation by pages of a fixed size over the 24 bit address space
+///
+/// All pages are initially mapped to themselves. The mapping can be
+/// changed while it is installed, for example by an IoDevice emulating
+/// a bank register, sharing the PageMap as an `Rc<PageMap>`.
+pub struct PageMap {
+    page_bits: u32,
+    pages: Vec<Cell<u32>>,
+}
+
+impl PageMap {
+    /// Returns an identity mapping with pages of 2^page_bits bytes,
+    /// from 256 bytes to the whole 16 MiB address space
+    pub fn new(page_bits: u32) -> PageMap {
+        assert!(page_bits >= MIN_PAGE_BITS, "pages of 2^{} bytes, smaller than 256 bytes", page_bits);
+        assert!(page_bits <= 24, "pages of 2^{} bytes, larger than the address space", page_bits);
+        PageMap {
+            page_bits,
+            pages: (0..1 << (24 - page_bits)).map(Cell::new).collect(),
+        }
+    }
+
+    /// Returns the number of pages
+    pub fn pages(&self) -> u32 {
+        self.pages.len() as u32
+    }
+
+    /// Maps the cpu page to the page target of the Machine. Both must
+    /// be below pages.
+    pub fn map(&self, page: u32, target: u32) {
+        assert!(page < self.pages(), "page ${:x} out of the {} pages", page, self.pages());
+        assert!(target < self.pages(), "target page ${:x} out of the {} pages", target, self.pages());
+        self.pages[page as usize].set(target);
+    }
+
+    /// Returns the page of the Machine seen on the cpu page
+    pub fn mapped(&self, page: u32) -> u32 {
+        self.pages[page as usize].get()
+    }
+}
+
+impl AddressTranslation for PageMap {
+    fn translate(&self, address: u32) -> u32 {
+        let address = address & 0xffffff;
+        let offset = address & ((1 << self.page_bits) - 1);
+        (self.pages[(address >> self.page_bits) as usize].get() << self.page_bits) | offset
+    }
+}
@@ -0,0 +1,403 @@
+use std::collections::VecDeque;
+
+use super::iodevice::{CpuLines, IoDevice};
+
+// Offsets of the registers from the base port
+const UART_RBR: u8 = 0; // UART_THR on writes, UART_BRG_L with DLAB
+const UART_IER: u8 = 1; // UART_BRG_H with DLAB
+const UART_IIR: u8 = 2; // UART_FCTL on writes
+const UART_LCTL: u8 = 3;
+const UART_MCTL: u8 = 4;
+const UART_LSR: u8 = 5;
+const UART_MSR: u8 = 6;
+const UART_SPR: u8 = 7;
+
+// UART_IER bits
+const IER_RIE: u8 = 0x01;
+const IER_TIE: u8 = 0x02;
+const IER_LSIE: u8 = 0x04;
+
+// UART_FCTL bits
+const FCTL_FIFOEN: u8 = 0x01;
+const FCTL_CLRRXF: u8 = 0x02;
+const FCTL_CLRTXF: u8 = 0x04;
+const FCTL_TRIG: u8 = 0xc0;
+
+// UART_LCTL bits
+const LCTL_DLAB: u8 = 0x80;
+const LCTL_PEN: u8 = 0x08;
+const LCTL_STOP: u8 = 0x04;
+const LCTL_CHAR: u8 = 0x03;
+
+// UART_LSR bits
+const LSR_DR: u8 = 0x01;
+const LSR_OE: u8 = 0x02;
+const LSR_PE: u8 = 0x04;
+const LSR_FE: u8 = 0x08;
+const LSR_THRE: u8 = 0x20;
+const LSR_TEMT: u8 = 0x40;
+const LSR_ERR: u8 = 0x80;
+
+// UART_IIR values, by priority
+const IIR_NONE: u8 = 0x01;
+const IIR_LINE_STATUS: u8 = 0x06;
+const IIR_RX_DATA: u8 = 0x04;
+const IIR_RX_TIMEOUT: u8 = 0x0c;
+const IIR_THRE: u8 = 0x02;
+const IIR_FIFO: u8 = 0xc0;
+
+const FIFO_SIZE: usize = 16;
+const OUTPUT_CAPACITY: usize = 256;
+
+/// A UART of the eZ80, on the 8 on-chip ports from $c0 for UART0 or
+/// $d0 for UART1
+///
+/// Attach it with Cpu::add_io_device. The host sends bytes with
+/// receive, that arrive at the baud rate set in UART_BRG, and gets the
+/// bytes transmitted by the guest with take_output. The receive FIFO
+/// of 16 bytes, or the single RBR without UART_FCTL FIFOEN, overruns
+/// when the guest does not read it in time. The transmit FIFO drains at
+/// the baud rate into an output buffer of bounded size: when the host
+/// does not take the output, the transmitter stalls and the guest sees
+/// THR full, instead of the bytes piling up. Line noise can be injected
+/// with set_line_noise, to check the parity and framing errors. The
+/// modem lines are not modelled: UART_MSR reads CTS, DSR and DCD
+/// active.
+pub struct Uart {
+    base: u8,
+    brg: u16,
+    ier: u8,
+    fctl: u8,
+    lctl: u8,
+    mctl: u8,
+    spr: u8,
+    // Bytes sent by the host, not yet on the line
+    input: VecDeque<u8>,
+    // With their PE and FE errors
+    rx: VecDeque<(u8, u8)>,
+    rbr: u8,
+    overrun: bool,
+    // A line status interrupt is pending, cleared by reading UART_LSR
+    line_error: bool,
+    // Cycles of the character on the line
+    rx_clock: u64,
+    // Cycles since the last character received or read
+    rx_idle: u64,
+    tx: VecDeque<u8>,
+    // The character on the line and its cycles so far
+    shifter: Option<u8>,
+    tx_clock: u64,
+    // The THR empty interrupt, cleared by reading UART_IIR or writing THR
+    thre: bool,
+    output: VecDeque<u8>,
+    output_capacity: usize,
+    noise: u32,
+    seed: u32,
+    irq: Option<u32>,
+}
+
+impl Uart {
+    /// Returns the uart on the ports base to base+7, as after reset
+    pub fn new(base: u8) -> Uart {
+        Uart {
+            base,
+            brg: 0x0002,
+            ier: 0x00,
+            fctl: 0x00,
+            lctl: 0x00,
+            mctl: 0x00,
+            spr: 0x00,
+            input: VecDeque::new(),
+            rx: VecDeque::with_capacity(FIFO_SIZE),
+            rbr: 0x00,
+            overrun: false,
+            line_error: false,
+            rx_clock: 0,
+            rx_idle: 0,
+            tx: VecDeque::with_capacity(FIFO_SIZE),
+            shifter: None,
+            tx_clock: 0,
+            thre: false,
+            output: VecDeque::new(),
+            output_capacity: OUTPUT_CAPACITY,
+            noise: 0,
+            seed: 0x2545f491,
+            irq: None,
+        }
+    }
+
+    /// Sets the interrupt line asserted while UART_IIR reports an
+    /// interrupt
+    pub fn set_irq(&mut self, number: u32) {
+        self.irq = Some(number);
+    }
+
+    /// Corrupts one in one_in of the characters received, flipping one
+    /// of their bits chosen from seed. 0 disables the noise.
+    pub fn set_line_noise(&mut self, one_in: u32, seed: u32) {
+        self.noise = one_in;
+        // xorshift does not leave 0
+        self.seed = seed.max(1);
+    }
+
+    /// Sends bytes from the host to the guest
+    pub fn receive(&mut self, bytes: &[u8]) {
+        self.input.extend(bytes);
+    }
+
+    /// Returns the bytes transmitted by the guest since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.drain(..).collect()
+    }
+
+    /// Sets the bytes transmitted kept until take_output, 256 by
+    /// default. At least 1.
+    pub fn set_output_capacity(&mut self, capacity: usize) {
+        self.output_capacity = capacity.max(1);
+    }
+
+    /// Returns the bus cycles on the line of a character, with its
+    /// start, parity and stop bits
+    pub fn char_cycles(&self) -> u64 {
+        16 * self.brg.max(1) as u64 * self.char_bits() as u64
+    }
+
+    /// Reads a uart register. Returns None for other ports.
+    pub fn port_in(&mut self, port: u8) -> Option<u8> {
+        let dlab = self.lctl & LCTL_DLAB != 0;
+        let value = match port.wrapping_sub(self.base) {
+            UART_RBR if dlab => self.brg as u8,
+            UART_RBR => {
+                if let Some((value, _)) = self.rx.pop_front() {
+                    self.rbr = value;
+                }
+                self.rx_idle = 0;
+                self.rbr
+            }
+            UART_IER if dlab => (self.brg >> 8) as u8,
+            UART_IER => self.ier,
+            UART_IIR => {
+                let value = self.iir();
+                if value & 0x0f == IIR_THRE {
+                    self.thre = false;
+                }
+                value
+            }
+            UART_LCTL => self.lctl,
+            UART_MCTL => self.mctl,
+            UART_LSR => {
+                let value = self.lsr();
+                self.overrun = false;
+                self.line_error = false;
+                value
+            }
+            UART_MSR => 0xb0,
+            UART_SPR => self.spr,
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Writes a uart register. Returns false for other ports.
+    pub fn port_out(&mut self, port: u8, value: u8) -> bool {
+        let dlab = self.lctl & LCTL_DLAB != 0;
+        match port.wrapping_sub(self.base) {
+            UART_RBR if dlab => self.brg = (self.brg & 0xff00) | value as u16,
+            UART_RBR => {
+                // Lost when the FIFO is full, like on the hardware
+                if self.tx.len() < self.fifo_size() {
+                    self.tx.push_back(value);
+                }
+                self.thre = false;
+            }
+            UART_IER if dlab => self.brg = (self.brg & 0x00ff) | ((value as u16) << 8),
+            UART_IER => {
+                // Enabling TIE with THR empty raises the interrupt
+                if value & IER_TIE != 0 && self.ier & IER_TIE == 0 && self.tx.is_empty() {
+                    self.thre = true;
+                }
+                self.ier = value & 0x1f;
+            }
+            UART_IIR => {
+                let fifo_changed = (value ^ self.fctl) & FCTL_FIFOEN != 0;
+                if fifo_changed || value & FCTL_CLRRXF != 0 {
+                    self.rx.clear();
+                }
+                if fifo_changed || value & FCTL_CLRTXF != 0 {
+                    self.tx.clear();
+                }
+                self.fctl = value & (FCTL_TRIG | FCTL_FIFOEN);
+            }
+            UART_LCTL => self.lctl = value,
+            UART_MCTL => self.mctl = value & 0x1f,
+            UART_LSR | UART_MSR => {}
+            UART_SPR => self.spr = value,
+            _ => return false,
+        }
+        true
+    }
+
+    fn char_bits(&self) -> u32 {
+        let data = 5 + (self.lctl & LCTL_CHAR) as u32;
+        let parity = if self.lctl & LCTL_PEN != 0 { 1 } else { 0 };
+        let stop = if self.lctl & LCTL_STOP != 0 { 2 } else { 1 };
+        1 + data + parity + stop
+    }
+
+    fn fifo_size(&self) -> usize {
+        if self.fctl & FCTL_FIFOEN != 0 { FIFO_SIZE } else { 1 }
+    }
+
+    fn trigger_level(&self) -> usize {
+        if self.fctl & FCTL_FIFOEN == 0 {
+            return 1;
+        }
+        match self.fctl >> 6 {
+            0 => 1,
+            1 => 4,
+            2 => 8,
+            _ => 14,
+        }
+    }
+
+    fn lsr(&self) -> u8 {
+        let mut value = 0;
+        if self.tx.is_empty() {
+            value |= LSR_THRE;
+            if self.shifter.is_none() {
+                value |= LSR_TEMT;
+            }
+        }
+        if let Some((_, errors)) = self.rx.front() {
+            value |= LSR_DR | errors;
+        }
+        if self.overrun {
+            value |= LSR_OE;
+        }
+        if self.fctl & FCTL_FIFOEN != 0 && self.rx.iter().any(|(_, errors)| *errors != 0) {
+            value |= LSR_ERR;
+        }
+        value
+    }
+
+    fn iir(&self) -> u8 {
+        let fifo = if self.fctl & FCTL_FIFOEN != 0 { IIR_FIFO } else { 0 };
+        let status = if self.ier & IER_LSIE != 0 && self.line_error {
+            IIR_LINE_STATUS
+        } else if self.ier & IER_RIE != 0 && self.rx.len() >= self.trigger_level() {
+            IIR_RX_DATA
+        } else if self.ier & IER_RIE != 0 && !self.rx.is_empty() && self.rx_idle >= 4 * self.char_cycles() {
+            IIR_RX_TIMEOUT
+        } else if self.ier & IER_TIE != 0 && self.thre {
+            IIR_THRE
+        } else {
+            IIR_NONE
+        };
+        fifo | status
+    }
+
+    // A character from the line. The noise may flip a data bit, the
+    // parity bit or the stop bit.
+    fn receive_char(&mut self, mut value: u8) {
+        let mut errors = 0;
+        if self.noise > 0 && self.random().is_multiple_of(self.noise) {
+            let data = 5 + (self.lctl & LCTL_CHAR) as u32;
+            let parity = self.lctl & LCTL_PEN != 0;
+            let bit = self.random() % (self.char_bits() - 1);
+            if bit < data {
+                value ^= 1 << bit;
+                if parity {
+                    errors = LSR_PE;
+                }
+            } else if parity && bit == data {
+                errors = LSR_PE;
+            } else {
+                errors = LSR_FE;
+            }
+        }
+        self.rx_idle = 0;
+        if self.rx.len() >= self.fifo_size() {
+            self.overrun = true;
+            self.line_error = true;
+            return;
+        }
+        if errors != 0 {
+            self.line_error = true;
+        }
+        self.rx.push_back((value, errors));
+    }
+
+    // Shifts out the characters of the transmit FIFO for the cycles
+    fn transmit(&mut self, cycles: u64) {
+        let char_cycles = self.char_cycles();
+        self.tx_clock += cycles;
+        loop {
+            if self.shifter.is_none() {
+                // Stalled while the host does not take the output
+                if self.output.len() >= self.output_capacity {
+                    self.tx_clock = 0;
+                    return;
+                }
+                self.shifter = self.tx.pop_front();
+                if self.shifter.is_none() {
+                    self.tx_clock = 0;
+                    return;
+                }
+                if self.tx.is_empty() {
+                    self.thre = true;
+                }
+            }
+            if self.tx_clock < char_cycles {
+                return;
+            }
+            self.tx_clock -= char_cycles;
+            if let Some(value) = self.shifter.take() {
+                self.output.push_back(value);
+            }
+        }
+    }
+
+    fn random(&mut self) -> u32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+}
+
+impl IoDevice for Uart {
+    fn port_in(&mut self, address: u16) -> u8 {
+        Uart::port_in(self, address as u8).unwrap_or(0x00)
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        Uart::port_out(self, address as u8, value);
+    }
+    fn tick(&mut self, cycles: u32, lines: &mut CpuLines) {
+        let char_cycles = self.char_cycles();
+        self.rx_idle += cycles as u64;
+        if self.input.is_empty() {
+            // The line is idle, the next character starts when sent
+            self.rx_clock = 0;
+        } else {
+            self.rx_clock += cycles as u64;
+            while self.rx_clock >= char_cycles {
+                self.rx_clock -= char_cycles;
+                if let Some(value) = self.input.pop_front() {
+                    self.receive_char(value);
+                }
+                if self.input.is_empty() {
+                    self.rx_clock = 0;
+                    break;
+                }
+            }
+        }
+        self.transmit(cycles as u64);
+        if let Some(number) = self.irq {
+            if self.iir() & IIR_NONE == 0 {
+                lines.assert_irq(number);
+            } else {
+                lines.deassert_irq(number);
+            }
+        }
+    }
+}
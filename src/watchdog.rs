@@ -0,0 +1,144 @@
+use super::iodevice::{CpuLines, IoDevice};
+
+const WDT_CTL: u8 = 0x93;
+const WDT_RR: u8 = 0x94;
+
+// WDT_CTL bits
+const WDT_EN: u8 = 0x80;
+const NMI_OUT: u8 = 0x40;
+const RST_FLAG: u8 = 0x20;
+const WDT_PERIOD: u8 = 0x03;
+
+/// What the watchdog does when it times out, selected with NMI_OUT
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchdogAction {
+    Nmi,
+    Reset,
+}
+
+/// Called with the action when the watchdog fires
+pub type WatchdogCallback = Box<dyn FnMut(WatchdogAction)>;
+
+/// The watchdog timer of the eZ80, on the on-chip ports $93 and $94
+///
+/// Attach it with Cpu::add_io_device on the ports $93..$95, to count
+/// the cycles of the cpu. When the firmware does not write $a5 and $5a
+/// to WDT_RR in time, it signals an NMI or a reset to the cpu.
+/// Only the system clock source is modelled: the timeout counts bus
+/// cycles whatever the WDT_CLK bits.
+pub struct Watchdog {
+    ctl: u8,
+    count: u64,
+    // $a5 has been written to WDT_RR
+    unlocked: bool,
+    callback: Option<WatchdogCallback>,
+}
+
+impl Watchdog {
+    /// Returns the watchdog disabled, as after reset
+    pub fn new() -> Watchdog {
+        Watchdog {
+            ctl: 0x00,
+            count: 0,
+            unlocked: false,
+            callback: None,
+        }
+    }
+
+    /// Sets a function called when the watchdog fires, before the cpu
+    /// is signalled
+    pub fn set_callback(&mut self, callback: WatchdogCallback) {
+        self.callback = Some(callback);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ctl & WDT_EN != 0
+    }
+
+    /// Returns the cycles to time out, from WDT_PERIOD
+    pub fn period(&self) -> u64 {
+        match self.ctl & WDT_PERIOD {
+            0 => 1 << 27,
+            1 => 1 << 25,
+            2 => 1 << 22,
+            _ => 1 << 18,
+        }
+    }
+
+    /// Reads a watchdog register. Returns None for other ports.
+    pub fn port_in(&self, port: u8) -> Option<u8> {
+        match port {
+            WDT_CTL => Some(self.ctl),
+            WDT_RR => Some(0x00),
+            _ => None,
+        }
+    }
+
+    /// Writes a watchdog register. Returns false for other ports. Once
+    /// enabled, WDT_CTL can't be changed until the watchdog resets the
+    /// cpu.
+    pub fn port_out(&mut self, port: u8, value: u8) -> bool {
+        match port {
+            WDT_CTL => {
+                if !self.is_enabled() {
+                    self.ctl = (self.ctl & RST_FLAG) | (value & !RST_FLAG);
+                    self.count = 0;
+                }
+                true
+            }
+            WDT_RR => {
+                if self.unlocked && value == 0x5a {
+                    self.count = 0;
+                }
+                self.unlocked = value == 0xa5;
+                true
+            }
+            _ => false,
+        }
+    }
+
+}
+
+impl IoDevice for Watchdog {
+    fn port_in(&mut self, address: u16) -> u8 {
+        Watchdog::port_in(self, address as u8).unwrap_or(0x00)
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        Watchdog::port_out(self, address as u8, value);
+    }
+    /// On a timeout, calls the callback and signals the cpu. A reset
+    /// disables the watchdog and sets RST_FLAG, an NMI restarts the
+    /// count.
+    fn tick(&mut self, cycles: u32, lines: &mut CpuLines) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.count += cycles as u64;
+        if self.count < self.period() {
+            return;
+        }
+        self.count = 0;
+        let action = if self.ctl & NMI_OUT != 0 {
+            WatchdogAction::Nmi
+        } else {
+            WatchdogAction::Reset
+        };
+        if let Some(callback) = self.callback.as_mut() {
+            callback(action);
+        }
+        match action {
+            WatchdogAction::Nmi => lines.signal_nmi(),
+            WatchdogAction::Reset => {
+                self.ctl = RST_FLAG;
+                self.unlocked = false;
+                lines.signal_reset();
+            }
+        }
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,351 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ez80::*;
+
+#[test]
+fn test_breakpoint() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x3c); // INC A
+    sys.poke(0x0001, 0x3c); // INC A
+    sys.poke(0x0002, 0x3c); // INC A
+    cpu.registers().set_a(0);
+    cpu.add_breakpoint(0x0001);
+
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+    assert_eq!(StepResult::Breakpoint(BreakReason::Breakpoint(0x0001)), cpu.execute_instruction(&mut sys).status);
+    assert_eq!(0x0001, cpu.state.pc());
+    assert_eq!(1, cpu.registers().a());
+
+    // Resume from the breakpoint
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+    assert_eq!(3, cpu.registers().a());
+}
+
+#[test]
+fn test_breakpoint_in_loop() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x3c); // INC A
+    sys.poke(0x0001, 0x18); // JR $0000
+    sys.poke(0x0002, 0xfd);
+    cpu.registers().set_a(0);
+    cpu.add_breakpoint(0x0000);
+
+    let mut hits = 0;
+    for _ in 0..10 {
+        if let StepResult::Breakpoint(_) = cpu.execute_instruction(&mut sys).status {
+            hits += 1;
+        }
+    }
+    // Each pass of the loop is a breakpoint + INC A + JR
+    assert_eq!(4, hits);
+    assert_eq!(3, cpu.registers().a());
+
+    cpu.remove_breakpoint(0x0000);
+    for _ in 0..10 {
+        assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+    }
+}
+
+#[test]
+fn test_conditional_breakpoint() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x3c); // INC A
+    sys.poke(0x0001, 0x18); // JR $0000
+    sys.poke(0x0002, 0xfd);
+    cpu.registers().set_a(0);
+    cpu.add_conditional_breakpoint(0x0001, |reg| reg.a() == 5);
+
+    let mut steps = 0;
+    while cpu.execute_instruction(&mut sys).status == StepResult::Continue {
+        steps += 1;
+    }
+    assert_eq!(9, steps);
+    assert_eq!(5, cpu.registers().a());
+    assert_eq!(0x0001, cpu.state.pc());
+}
+
+#[test]
+fn test_watchpoint_write() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    sys.poke(0x0000, 0x77); // LD (HL), A
+    sys.poke(0x0001, 0x7e); // LD A, (HL)
+    cpu.registers().set24(Reg16::HL, 0x012345);
+    cpu.registers().set_a(0x42);
+    cpu.add_watchpoint(0x012345..0x012346, WatchKind::Write);
+
+    assert_eq!(StepResult::Breakpoint(BreakReason::Write(0x012345)), cpu.execute_instruction(&mut sys).status);
+    // The instruction has been executed
+    assert_eq!(0x42, sys.peek(0x012345));
+    assert_eq!(0x0001, cpu.state.pc());
+
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+}
+
+#[test]
+fn test_watchpoint_read_stack() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    sys.poke(0x0000, 0xc5); // PUSH BC
+    sys.poke(0x0001, 0xd1); // POP DE
+    cpu.registers().set24(Reg16::SP, 0x1000);
+    cpu.add_watchpoint(0x0ffd..0x1000, WatchKind::Read);
+
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+    assert_eq!(StepResult::Breakpoint(BreakReason::Read(0x0ffd)), cpu.execute_instruction(&mut sys).status);
+
+    cpu.clear_watchpoints();
+    cpu.state.set_pc(0);
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+}
+
+#[test]
+fn test_watchpoint_ignores_fetch() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x00); // NOP
+    cpu.add_watchpoint(0x0000..0x0010, WatchKind::ReadWrite);
+
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+}
+
+#[test]
+fn test_illegal_instruction_stop() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_illegal_policy(IllegalPolicy::Stop);
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0xed); // invalid
+    sys.poke(0x0002, 0x05);
+
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+    let result = cpu.execute_instruction(&mut sys);
+    assert_eq!(StepResult::Breakpoint(BreakReason::IllegalInstruction(0x0001)), result.status);
+    assert!(result.illegal);
+    assert_eq!(0x0001, cpu.state.pc());
+
+    let illegal = cpu.last_illegal_instruction().unwrap();
+    assert_eq!(0x0001, illegal.pc);
+    assert_eq!(vec![0xed, 0x05], illegal.bytes);
+    assert_eq!(1, illegal.instructions_executed);
+    assert!(illegal.backtrace.is_empty());
+
+    // Resumes once, as a NOP
+    let result = cpu.execute_instruction(&mut sys);
+    assert_eq!(StepResult::Continue, result.status);
+    assert!(result.illegal);
+    assert_eq!(0x0003, cpu.state.pc());
+
+    // Stops again the next time
+    cpu.state.set_pc(0x0001);
+    assert_eq!(StepResult::Breakpoint(BreakReason::IllegalInstruction(0x0001)), cpu.execute_instruction(&mut sys).status);
+}
+
+#[test]
+fn test_illegal_instruction_backtrace() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_illegal_policy(IllegalPolicy::Stop);
+
+    sys.poke(0x0000, 0xcd); // CALL $0100
+    sys.poke(0x0001, 0x00);
+    sys.poke(0x0002, 0x01);
+    sys.poke(0x0100, 0xed); // invalid
+    sys.poke(0x0101, 0x05);
+    cpu.registers().set16(Reg16::SP, 0x1000);
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+
+    let illegal = cpu.last_illegal_instruction().unwrap();
+    assert_eq!(0x0100, illegal.pc);
+    assert_eq!(cpu.backtrace(), illegal.backtrace);
+    assert_eq!(1, illegal.backtrace.len());
+    assert_eq!(0x0100, illegal.backtrace[0].address);
+    assert_eq!(0x0003, illegal.backtrace[0].return_address);
+}
+
+#[test]
+fn test_illegal_instruction_skip_and_nop() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // invalid
+    sys.poke(0x0001, 0x05);
+    sys.poke(0x0002, 0xed); // invalid
+    sys.poke(0x0003, 0x06);
+
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(!result.illegal);
+    assert!(cpu.last_illegal_instruction().is_none());
+
+    cpu.set_illegal_policy(IllegalPolicy::Skip);
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(result.illegal);
+    assert_eq!(StepResult::Continue, result.status);
+    assert_eq!(0x0004, cpu.state.pc());
+    assert_eq!(0x0002, cpu.last_illegal_instruction().unwrap().pc);
+}
+
+#[test]
+fn test_pc_hook_emulates_call() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xcd); // CALL $0100
+    sys.poke(0x0001, 0x00);
+    sys.poke(0x0002, 0x01);
+    sys.poke(0x0003, 0x00); // NOP
+    sys.poke(0x0100, 0x76); // HALT, should not run
+    cpu.registers().set16(Reg16::SP, 0x1000);
+    cpu.set_pc_hook(0x0100, Box::new(|env: &mut Environment| {
+        env.state.reg.set_a(0x42);
+        env.subroutine_return();
+    }));
+
+    cpu.execute_instruction(&mut sys);
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(result.ret);
+    assert_eq!(0x0003, cpu.state.pc());
+    assert_eq!(0x42, cpu.registers().a());
+    assert!(!cpu.is_halted());
+}
+
+#[test]
+fn test_pc_hook_then_instruction() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x3c); // INC A
+    cpu.registers().set_a(0x00);
+    cpu.set_pc_hook(0x0000, Box::new(|env: &mut Environment| {
+        env.state.reg.set_a(0x10);
+    }));
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x11, cpu.registers().a());
+    assert_eq!(0x0001, cpu.state.pc());
+
+    cpu.remove_pc_hook(0x0000);
+    cpu.state.set_pc(0x0000);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x12, cpu.registers().a());
+}
+
+#[test]
+fn test_backtrace() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xcd); // CALL $0100
+    sys.poke(0x0001, 0x00);
+    sys.poke(0x0002, 0x01);
+    sys.poke(0x0100, 0x5b); // CALL.LIL $020000
+    sys.poke(0x0101, 0xcd);
+    sys.poke(0x0102, 0x00);
+    sys.poke(0x0103, 0x00);
+    sys.poke(0x0104, 0x02);
+    sys.poke(0x020000, 0x00); // NOP
+    sys.poke(0x020001, 0x49); // RET.L
+    sys.poke(0x020002, 0xc9);
+    cpu.registers().set16(Reg16::SP, 0x1000);
+    cpu.registers().set24(Reg16::SP, 0x011000);
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(vec![
+        StackFrame { address: 0x020000, return_address: 0x000105, adl: false, interrupt: false },
+        StackFrame { address: 0x000100, return_address: 0x000003, adl: false, interrupt: false },
+    ], cpu.backtrace());
+
+    cpu.execute_instruction(&mut sys);
+    cpu.registers().set_interrupts(true);
+    cpu.interrupt(&mut sys, 0);
+    assert_eq!(3, cpu.backtrace().len());
+    assert!(cpu.backtrace()[0].interrupt);
+    assert_eq!(0x020001, cpu.backtrace()[0].return_address);
+}
+
+#[test]
+fn test_backtrace_pc_hook() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xcd); // CALL $0100
+    sys.poke(0x0001, 0x00);
+    sys.poke(0x0002, 0x01);
+    sys.poke(0x0003, 0x18); // JR $0000
+    sys.poke(0x0004, 0xfb);
+    cpu.registers().set16(Reg16::SP, 0xff00);
+    cpu.set_pc_hook(0x0100, Box::new(|env: &mut Environment| {
+        env.subroutine_return();
+    }));
+
+    for _ in 0..30 {
+        cpu.execute_instruction(&mut sys);
+    }
+    assert_eq!(0xff00, cpu.registers().get16(Reg16::SP));
+    assert!(cpu.backtrace().is_empty());
+    assert_eq!(30, cpu.state.instructions_executed);
+}
+
+#[test]
+fn test_backtrace_runaway_recursion() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0xcd); // CALL $0000
+    sys.poke(0x0002, 0x00);
+    sys.poke(0x0003, 0x00);
+    cpu.registers().set16(Reg16::SP, 0x0000);
+
+    for _ in 0..5000 {
+        cpu.execute_instruction(&mut sys);
+    }
+    // The oldest frames are dropped
+    let backtrace = cpu.backtrace();
+    assert_eq!(1024, backtrace.len());
+    assert_eq!(StackFrame { address: 0x000000, return_address: 0x000004, adl: false, interrupt: false }, backtrace[0]);
+}
+
+#[test]
+fn test_illegal_handler() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let found = Rc::new(RefCell::new(Vec::new()));
+    let handler_found = found.clone();
+    cpu.set_illegal_handler(Box::new(move |illegal: &IllegalInstruction, reg: &Registers| {
+        handler_found.borrow_mut().push((illegal.pc, illegal.bytes.clone(), reg.pc, reg.a()));
+        if illegal.bytes[1] == 0x05 { IllegalPolicy::Nop } else { IllegalPolicy::Stop }
+    }));
+
+    sys.poke(0x0000, 0xed); // invalid, continue
+    sys.poke(0x0001, 0x05);
+    sys.poke(0x0002, 0xdd); // invalid, stop
+    sys.poke(0x0003, 0xed);
+    sys.poke(0x0004, 0x06);
+    cpu.registers().set_a(0x12);
+
+    assert_eq!(StepResult::Continue, cpu.execute_instruction(&mut sys).status);
+    assert_eq!(StepResult::Breakpoint(BreakReason::IllegalInstruction(0x0002)), cpu.execute_instruction(&mut sys).status);
+    assert_eq!(vec![
+        (0x0000, vec![0xed, 0x05], 0x0000, 0x12),
+        (0x0002, vec![0xdd, 0xed, 0x06], 0x0002, 0x12),
+    ], *found.borrow());
+}
@@ -0,0 +1,69 @@
+use ez80::*;
+
+struct ChipSelectMachine {
+    ram: RamMachine,
+    cs: ChipSelects,
+}
+
+impl Machine for ChipSelectMachine {
+    fn peek(&self, address: u32) -> u8 {
+        self.ram.peek(address)
+    }
+    fn poke(&mut self, address: u32, value: u8) {
+        self.ram.poke(address, value);
+    }
+    fn port_in(&mut self, address: u16) -> u8 {
+        self.ram.port_in(address)
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        self.ram.port_out(address, value);
+    }
+    fn internal_port_in(&mut self, address: u8) -> u8 {
+        self.cs.port_in(address).unwrap_or(0x00)
+    }
+    fn internal_port_out(&mut self, address: u8, value: u8) {
+        self.cs.port_out(address, value);
+    }
+    fn use_cycles(&self, _cycles: u32) {
+    }
+}
+
+#[test]
+fn test_chip_selects_programmed() {
+    let mut sys = ChipSelectMachine { ram: RamMachine::default(), cs: ChipSelects::new() };
+    let mut cpu = Cpu::new_ez80();
+
+    assert_eq!(Some(0), sys.cs.select(0x0b0000));
+    sys.ram.load(0x0000, &[
+        0x3e, 0x03, 0xed, 0x39, 0xa9, // LD A, $03; OUT0 ($A9), A
+        0x3e, 0x04, 0xed, 0x39, 0xab, // LD A, $04; OUT0 ($AB), A
+        0x3e, 0x07, 0xed, 0x39, 0xac, // LD A, $07; OUT0 ($AC), A
+        0x3e, 0x28, 0xed, 0x39, 0xad, // LD A, $28; OUT0 ($AD), A
+        0xed, 0x00, 0xad, // IN0 B, ($AD)
+    ]);
+    for _ in 0..9 {
+        cpu.execute_instruction(&mut sys);
+    }
+
+    assert_eq!(0x28, cpu.registers().get8(Reg8::B));
+    assert_eq!(Some(0), sys.cs.select(0x030000));
+    assert_eq!(Some(1), sys.cs.select(0x050000));
+    assert_eq!(None, sys.cs.select(0x0b0000));
+    assert_eq!(7, sys.cs.wait_states(0x010000));
+    assert_eq!(1, sys.cs.wait_states(0x070000));
+    assert_eq!(0x040000..0x080000, sys.cs.get(1).range());
+}
+
+#[test]
+fn test_chip_selects_wait_states() {
+    let mut sys = ChipSelectMachine { ram: RamMachine::default(), cs: ChipSelects::new() };
+    let mut cpu = Cpu::new_ez80();
+
+    // NOP in CS0, 7 wait states after reset
+    sys.cs.apply_wait_states(&mut cpu);
+    assert_eq!(8, cpu.execute_instruction(&mut sys).cycles);
+
+    sys.cs.port_out(0xaa, 0x08);
+    sys.cs.apply_wait_states(&mut cpu);
+    assert_eq!(1, cpu.execute_instruction(&mut sys).cycles);
+}
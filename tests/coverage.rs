@@ -0,0 +1,24 @@
+use ez80::*;
+
+#[test]
+fn test_ez80_coverage() {
+    let coverage = ez80_coverage();
+
+    assert!(coverage.iter().all(|o| o.status != OpcodeStatus::Undefined));
+    let otimr = coverage.iter().find(|o| o.prefix == "ed" && o.code == 0x93).unwrap();
+    assert_eq!(Some("OTIMR"), otimr.name.as_deref());
+    assert_eq!(OpcodeStatus::Implemented, otimr.status);
+    let invalid = coverage.iter().find(|o| o.prefix == "ed" && o.code == 0x05).unwrap();
+    assert_eq!(OpcodeStatus::Invalid, invalid.status);
+}
+
+#[test]
+fn test_ez80_coverage_csv() {
+    let coverage = ez80_coverage();
+    let mut out = Vec::new();
+    write_coverage_csv(&mut out, &coverage).unwrap();
+    let text = String::from_utf8(out).unwrap();
+
+    assert_eq!(coverage.len() + 1, text.lines().count());
+    assert!(text.contains("ed4c,implemented,\"MLT BC\"\n"));
+}
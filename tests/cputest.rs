@@ -2,7 +2,7 @@ use ez80::*;
 
 // Diagnostics II, version 1.2, CPU test by Supersoft Associates
 
-static CODE: &'static [u8] = include_bytes!("res/CPUTEST.COM");
+static CODE: &[u8] = include_bytes!("res/CPUTEST.COM");
 
 #[test]
 fn test_cpu_test_8080() {
@@ -19,9 +19,8 @@ fn cpu_test(mut cpu: Cpu) {
 
     // Load program
     let code = CODE;
-    let size = code.len();
-    for i in 0..size {
-        machine.poke(0x100 + i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        machine.poke(0x100 + i as u32, *e);
     }
 
     /*
@@ -32,8 +31,8 @@ fn cpu_test(mut cpu: Cpu) {
     */
     //let code = [0xD3, 0x00, 0xC9];
     let code = [0xC9];
-    for i in 0..code.len() {
-        machine.poke(5 + i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        machine.poke(5 + i as u32, *e);
     }
 
     cpu.state.set_pc(0x100);
@@ -51,7 +50,7 @@ fn cpu_test(mut cpu: Cpu) {
         }
 
         if cpu.state.pc() == 0x0000 {
-            println!("");
+            println!();
             break;
         }
 
@@ -68,5 +67,5 @@ fn cpu_test(mut cpu: Cpu) {
         }
     }
 
-    assert_eq!(true, msg.contains("CPU TESTS OK"));
+    assert!(msg.contains("CPU TESTS OK"));
 }
@@ -0,0 +1,556 @@
+use std::cell::Cell;
+
+use ez80::*;
+
+// Adds up the cycles reported with use_cycles
+struct CountingMachine {
+    ram: RamMachine,
+    used: Cell<u64>,
+}
+
+impl Machine for CountingMachine {
+    fn peek(&self, address: u32) -> u8 { self.ram.peek(address) }
+    fn poke(&mut self, address: u32, value: u8) { self.ram.poke(address, value); }
+    fn port_in(&mut self, _address: u16) -> u8 { 0xff }
+    fn port_out(&mut self, _address: u16, _value: u8) {}
+    fn use_cycles(&self, cycles: u32) {
+        self.used.set(self.used.get() + cycles as u64);
+    }
+}
+
+#[test]
+fn test_cycles_bus_accesses() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0x7e); // LD A, (HL)
+    sys.poke(0x0002, 0xc3); // JP $0010
+    sys.poke(0x0003, 0x10);
+    sys.poke(0x0004, 0x00);
+    cpu.registers().set16(Reg16::HL, 0x0100);
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(1, cpu.state.cycles);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(3, cpu.state.cycles);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(7, cpu.state.cycles);
+    assert_eq!(0x0010, cpu.state.pc());
+}
+
+#[test]
+fn test_cycles_halted() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x76); // HALT
+
+    cpu.execute_instruction(&mut sys);
+    assert!(cpu.is_halted());
+    assert_eq!(1, cpu.state.cycles);
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(3, cpu.state.cycles);
+    assert_eq!(1, cpu.state.instructions_executed);
+}
+
+#[test]
+fn test_cycles_deterministic() {
+    let run = || {
+        let mut sys = PlainMachine::new();
+        let mut cpu = Cpu::new_ez80();
+
+        sys.poke(0x0000, 0x06); // LD B, $40
+        sys.poke(0x0001, 0x40);
+        sys.poke(0x0002, 0x10); // DJNZ $0002
+        sys.poke(0x0003, 0xfe);
+        sys.poke(0x0004, 0x76); // HALT
+
+        while !cpu.is_halted() {
+            cpu.execute_instruction(&mut sys);
+        }
+        cpu.state.cycles
+    };
+
+    // LD B,n + 63 taken DJNZ + 1 not taken DJNZ + HALT
+    assert_eq!(2 + 63*3 + 2 + 1, run());
+    assert_eq!(run(), run());
+}
+
+#[test]
+fn test_run_for_cycles() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0x00); // NOP
+    sys.poke(0x0002, 0xc3); // JP $0010
+    sys.poke(0x0003, 0x10);
+    sys.poke(0x0004, 0x00);
+    sys.poke(0x0010, 0x76); // HALT
+
+    // The JP goes over the budget
+    let run = cpu.run_for_cycles(&mut sys, 4);
+    assert_eq!(RunResult { cycles: 6, instructions: 3, halt: false, status: StepResult::Continue, condition: false }, run);
+
+    // Halted, the clock keeps running
+    let run = cpu.run_for_cycles(&mut sys, 10);
+    assert_eq!(RunResult { cycles: 10, instructions: 1, halt: true, status: StepResult::Continue, condition: false }, run);
+    assert_eq!(16, cpu.state.cycles);
+}
+
+#[test]
+fn test_run_for_cycles_long_halt() {
+    let mut sys = CountingMachine { ram: RamMachine::new(0x100), used: Cell::new(0) };
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x76); // HALT
+
+    let long = 3 * (u32::MAX as u64) + 100;
+    let run = cpu.run_for_cycles(&mut sys, long);
+    assert!(run.halt);
+    assert_eq!(long, run.cycles);
+    // All but the bus cycle of the HALT fetch are idle cycles
+    assert_eq!(long - 1, sys.used.get());
+}
+
+#[test]
+fn test_run_for_cycles_breakpoint() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0x00); // NOP
+    cpu.add_breakpoint(0x0001);
+
+    let run = cpu.run_for_cycles(&mut sys, 100);
+    assert_eq!(RunResult { cycles: 1, instructions: 1, halt: false, status: StepResult::Breakpoint(BreakReason::Breakpoint(1)), condition: false }, run);
+}
+
+#[test]
+fn test_interrupt_wakes_halt() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    sys.poke(0x0000, 0xfb); // EI
+    sys.poke(0x0001, 0x76); // HALT
+    sys.poke(0x0002, 0x00); // NOP
+    sys.poke(0x0038, 0xfb); // EI
+    sys.poke(0x0039, 0xc9); // RET
+    sys.poke(0x0010, 0x38); // Vector $10 to $0038
+    sys.poke(0x0011, 0x00);
+    cpu.registers().set24(Reg16::SP, 0x1000);
+
+    let run = cpu.run_for_cycles(&mut sys, 1000);
+    assert!(run.halt);
+    assert_eq!(2, run.instructions);
+    assert_eq!(1000, cpu.state.cycles);
+    assert!(cpu.state.is_halted());
+
+    cpu.interrupt(&mut sys, 0x10);
+    assert!(!cpu.is_halted());
+    assert_eq!(0x0038, cpu.state.pc());
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+    // Returns after the HALT
+    assert_eq!(0x0002, cpu.state.pc());
+}
+
+#[test]
+fn test_slp() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // SLP
+    sys.poke(0x0001, 0x76);
+
+    cpu.execute_instruction(&mut sys);
+    assert!(cpu.is_halted());
+    assert_eq!(0x0002, cpu.state.pc());
+}
+
+#[test]
+fn test_cycles_memory_wait_states() {
+    let mut sys = CountingMachine { ram: RamMachine::new(0x4000), used: Cell::new(0) };
+    let mut cpu = Cpu::new_ez80();
+    cpu.add_memory_wait_states(0x0000..0x1000, 3); // flash
+    cpu.add_memory_wait_states(0x2000..0x3000, 1); // external RAM
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0x7e); // LD A, (HL)
+    sys.poke(0x0002, 0x77); // LD (HL), A
+    cpu.registers().set16(Reg16::HL, 0x2000);
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(4, cpu.state.cycles);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(4 + 4 + 2, cpu.state.cycles);
+    // The wait states are reported to the Machine
+    assert_eq!(3 + 3 + 1, sys.used.get());
+
+    cpu.clear_memory_wait_states();
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(10 + 2, cpu.state.cycles);
+    assert_eq!(7, sys.used.get());
+}
+
+struct Latch(u8);
+
+impl IoDevice for Latch {
+    fn port_in(&mut self, _address: u16) -> u8 { self.0 }
+    fn port_out(&mut self, _address: u16, value: u8) { self.0 = value; }
+}
+
+#[test]
+fn test_cycles_io_device_wait_states() {
+    let mut sys = CountingMachine { ram: RamMachine::default(), used: Cell::new(0) };
+    let mut cpu = Cpu::new_ez80();
+    cpu.add_io_device(0x0200..0x0210, 3, Latch(0));
+
+    sys.poke(0x0000, 0xed); // OUT (C), A
+    sys.poke(0x0001, 0x79);
+    sys.poke(0x0002, 0xed); // IN A, (C)
+    sys.poke(0x0003, 0x78);
+    cpu.registers().set16(Reg16::BC, 0x0208);
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(2 * (2 + 1 + 3), cpu.state.cycles);
+    // The wait states are reported to the Machine
+    assert_eq!(2 * 3, sys.used.get());
+}
+
+#[test]
+fn test_run_until() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x3c); // INC A
+    sys.poke(0x0001, 0x32); // LD ($1000), A
+    sys.poke(0x0002, 0x00);
+    sys.poke(0x0003, 0x10);
+    sys.poke(0x0004, 0x18); // JR $0000
+    sys.poke(0x0005, 0xfa);
+    cpu.registers().set_a(0x00);
+
+    let run = cpu.run_until(&mut sys, |_, m| m.peek(0x1000) == 3, 1000);
+    assert!(run.condition);
+    assert_eq!(8, run.instructions);
+    assert_eq!(0x0004, cpu.state.pc());
+
+    let run = cpu.run_until(&mut sys, |c, _| c.state.reg.a() == 0, 10);
+    assert!(!run.condition);
+    assert_eq!(10, run.instructions);
+}
+
+#[test]
+fn test_execute_with_fuel() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0x00); // NOP
+    sys.poke(0x0002, 0x00); // NOP
+    sys.poke(0x0003, 0x76); // HALT
+
+    let run = cpu.execute_with_fuel(&mut sys, 2);
+    assert_eq!(2, run.instructions);
+    assert_eq!(StepResult::Continue, run.status);
+    assert!(!run.halt);
+    assert_eq!(0x0002, cpu.state.pc());
+
+    let run = cpu.execute_with_fuel(&mut sys, 100);
+    assert_eq!(2, run.instructions);
+    assert!(run.halt);
+
+    let run = cpu.execute_with_fuel(&mut sys, 100);
+    assert_eq!(0, run.instructions);
+    assert_eq!(0, run.cycles);
+}
+
+#[test]
+fn test_measure() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x06); // LD B, $04
+    sys.poke(0x0001, 0x04);
+    sys.poke(0x0002, 0x10); // DJNZ $0002
+    sys.poke(0x0003, 0xfe);
+    sys.poke(0x0004, 0x76); // HALT
+
+    let run = cpu.measure(&mut sys, 0x0002, 0x0004, 100);
+    assert!(run.condition);
+    assert_eq!(4, run.instructions);
+    assert_eq!(3*3 + 2, run.cycles);
+
+    // Never reached
+    let run = cpu.measure(&mut sys, 0x0100, 0x0004, 100);
+    assert!(!run.condition);
+}
+
+#[test]
+fn test_measure_stopped_by_first_instruction() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_services_port(Some(0xfe00));
+
+    let code = [
+        0x01, 0x02, 0xfe, // LD BC, $FE02
+        0x3e, 0x05, // LD A, $05
+        0xed, 0x79, // OUT (C), A
+    ];
+    for (i, &value) in code.iter().enumerate() {
+        sys.poke(i as u32, value);
+    }
+
+    let run = cpu.measure(&mut sys, 0x0005, 0x0100, 100);
+    assert_eq!(StepResult::Breakpoint(BreakReason::Exit(5)), run.status);
+    assert_eq!(1, run.instructions);
+    assert_eq!(3, run.cycles);
+    assert!(!run.halt);
+    assert!(!run.condition);
+}
+
+#[test]
+fn test_block_burst() {
+    let run = |burst| {
+        let mut sys = PlainMachine::new();
+        let mut cpu = Cpu::new_ez80();
+        cpu.set_block_burst(burst);
+
+        sys.poke(0x0000, 0xed); // LDIR
+        sys.poke(0x0001, 0xb0);
+        for i in 0..100 {
+            sys.poke(0x1000 + i, i as u8);
+        }
+        cpu.registers().set16(Reg16::HL, 0x1000);
+        cpu.registers().set16(Reg16::DE, 0x2000);
+        cpu.registers().set16(Reg16::BC, 100);
+
+        let mut steps = 0;
+        while cpu.state.pc() == 0x0000 {
+            cpu.execute_instruction(&mut sys);
+            steps += 1;
+        }
+        assert_eq!(99, sys.peek(0x2063));
+        assert_eq!(0, cpu.registers().get16(Reg16::BC));
+        (steps, cpu.state.cycles, cpu.registers().get8(Reg8::R), cpu.state.instructions_executed)
+    };
+
+    let (steps, cycles, r, instructions) = run(1);
+    assert_eq!(100, steps);
+    assert_eq!((steps, cycles), (100, 100 * 4));
+    // Two fetches each, in the 7 bits of R
+    assert_eq!(200 % 0x80, r);
+    assert_eq!(100, instructions);
+    // Only the interrupt granularity changes
+    assert_eq!((4, cycles, r, instructions), run(32));
+}
+
+#[test]
+fn test_block_burst_suffixed_io() {
+    // .LIL INIR and .LIL OTIR
+    for opcode in [0xb2, 0xb3] {
+        let run = |burst| {
+            let mut sys = PlainMachine::new();
+            let mut cpu = Cpu::new_ez80();
+            cpu.set_block_burst(burst);
+
+            sys.poke(0x0000, 0x5b);
+            sys.poke(0x0001, 0xed);
+            sys.poke(0x0002, opcode);
+            cpu.registers().set24(Reg16::HL, 0x011000);
+            cpu.registers().set16(Reg16::BC, 0x1010);
+
+            let mut steps = 0;
+            while cpu.state.pc() == 0x0000 {
+                cpu.execute_instruction(&mut sys);
+                steps += 1;
+            }
+            assert_eq!(0x011010, cpu.registers().get24(Reg16::HL));
+            (steps, cpu.state.cycles, cpu.registers().get8(Reg8::R), cpu.state.instructions_executed)
+        };
+
+        let (steps, cycles, r, instructions) = run(1);
+        assert_eq!(16, steps);
+        assert_eq!((4, cycles, r, instructions), run(5));
+    }
+}
+
+#[test]
+fn test_scheduler_timer_interrupt() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let mut scheduler = Scheduler::new();
+
+    sys.poke(0x0000, 0xfb); // EI
+    sys.poke(0x0001, 0x76); // HALT
+    sys.poke(0x0002, 0x18); // JR $0000
+    sys.poke(0x0003, 0xfc);
+    sys.poke(0x0038, 0x3c); // INC A
+    sys.poke(0x0039, 0xfb); // EI
+    sys.poke(0x003a, 0xc9); // RET
+    sys.poke(0x0100, 0x38); // vector table
+    // The interrupts don't use IM 2, see Cpu::interrupt
+    cpu.registers().set8(Reg8::I, 0x01);
+    cpu.registers().set16(Reg16::SP, 0x1000);
+    cpu.registers().set_a(0);
+    scheduler.schedule(100, "timer");
+
+    let mut ticks = 0;
+    while cpu.state.cycles < 1000 {
+        cpu.run_to_event(&mut sys, &scheduler, 1000 - cpu.state.cycles);
+        while let Some((at, _)) = scheduler.pop_due(cpu.state.cycles) {
+            ticks += 1;
+            cpu.interrupt(&mut sys, 0);
+            scheduler.schedule(at + 100, "timer");
+        }
+    }
+
+    assert_eq!(10, ticks);
+    assert_eq!(9, cpu.registers().a());
+    // The last interrupt reads the vector and pushes PC
+    assert_eq!(1004, cpu.state.cycles);
+}
+
+#[test]
+fn test_scheduler_order() {
+    let mut scheduler = Scheduler::new();
+    scheduler.schedule(50, 'b');
+    scheduler.schedule(10, 'a');
+    scheduler.schedule(50, 'c');
+    scheduler.schedule(90, 'd');
+    scheduler.cancel(|e| *e == 'd');
+
+    assert_eq!(Some(10), scheduler.next_at());
+    assert_eq!(None, scheduler.pop_due(9));
+    assert_eq!(Some((10, 'a')), scheduler.pop_due(60));
+    assert_eq!(Some((50, 'b')), scheduler.pop_due(60));
+    assert_eq!(Some((50, 'c')), scheduler.pop_due(60));
+    assert!(scheduler.is_empty());
+}
+
+#[test]
+fn test_ei_delay() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_z80();
+
+    sys.poke(0x0000, 0xfb); // EI
+    sys.poke(0x0001, 0x00); // NOP
+    sys.poke(0x0002, 0x00); // NOP
+    sys.poke(0x0010, 0x34); // Vector $10 to $1234
+    sys.poke(0x0011, 0x12);
+    cpu.registers().set16(Reg16::SP, 0x1000);
+
+    cpu.execute_instruction(&mut sys);
+    assert!(!cpu.interrupt(&mut sys, 0x10));
+    assert_eq!(0x0001, cpu.state.pc());
+
+    cpu.execute_instruction(&mut sys);
+    assert!(cpu.interrupt(&mut sys, 0x10));
+    assert_eq!(0x1234, cpu.state.pc());
+    assert_eq!(0x0002, sys.peek(0x0ffe));
+}
+
+#[test]
+fn test_nested_interrupts() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    // Vsync handler at $0100, that lets the UART interrupt nest
+    sys.poke(0x0010, 0x00);
+    sys.poke(0x0011, 0x01);
+    sys.poke(0x0100, 0xfb); // EI
+    sys.poke(0x0101, 0x00); // NOP
+    sys.poke(0x0102, 0x00); // NOP
+    sys.poke(0x0103, 0xed); // RETI
+    sys.poke(0x0104, 0x4d);
+    // UART handler at $0200
+    sys.poke(0x0020, 0x00);
+    sys.poke(0x0021, 0x02);
+    sys.poke(0x0200, 0xfb); // EI
+    sys.poke(0x0201, 0xed); // RETI
+    sys.poke(0x0202, 0x4d);
+    // Main loop
+    sys.poke(0x1000, 0x18); // JR $1000
+    sys.poke(0x1001, 0xfe);
+    cpu.state.set_pc(0x1000);
+    cpu.registers().set24(Reg16::SP, 0x8000);
+    cpu.registers().set_interrupts(true);
+
+    assert!(cpu.interrupt(&mut sys, 0x10));
+    assert!(!cpu.registers().get_iff1());
+    assert!(!cpu.registers().get_iff2());
+    cpu.execute_instruction(&mut sys); // EI
+    assert!(!cpu.interrupt(&mut sys, 0x20));
+    cpu.execute_instruction(&mut sys); // NOP
+    assert!(cpu.interrupt(&mut sys, 0x20));
+    assert_eq!(2, cpu.backtrace().len());
+
+    // An NMI in the UART handler keeps IFF1 in IFF2 for RETN
+    cpu.execute_instruction(&mut sys); // EI
+    sys.poke(0x0066, 0xed); // RETN
+    sys.poke(0x0067, 0x45);
+    cpu.nmi(&mut sys);
+    assert!(!cpu.registers().get_iff1());
+    assert!(cpu.registers().get_iff2());
+    cpu.execute_instruction(&mut sys); // RETN
+    assert!(cpu.registers().get_iff1());
+    assert_eq!(0x0201, cpu.state.pc());
+
+    cpu.execute_instruction(&mut sys); // RETI
+    assert_eq!(0x0102, cpu.state.pc());
+    cpu.execute_instruction(&mut sys); // NOP
+    cpu.execute_instruction(&mut sys); // RETI
+    assert_eq!(0x1000, cpu.state.pc());
+    assert!(cpu.registers().get_iff1());
+    assert!(cpu.backtrace().is_empty());
+    assert_eq!(0x8000, cpu.registers().get24(Reg16::SP));
+}
+
+#[test]
+fn test_irq_lines() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0010, 0x00); // Vector $10 to $0100
+    sys.poke(0x0011, 0x01);
+    sys.poke(0x0020, 0x00); // Vector $20 to $0200
+    sys.poke(0x0021, 0x02);
+    sys.poke(0x0100, 0x00); // NOP
+    sys.poke(0x0101, 0xfb); // EI
+    sys.poke(0x0102, 0xed); // RETI
+    sys.poke(0x0103, 0x4d);
+    sys.poke(0x0200, 0x00); // NOP
+    sys.poke(0x1000, 0xfb); // EI
+    sys.poke(0x1001, 0x76); // HALT
+    cpu.state.set_pc(0x1000);
+    cpu.registers().set16(Reg16::SP, 0x8000);
+    cpu.assert_irq(0x20);
+    cpu.assert_irq(0x10);
+
+    cpu.execute_instruction(&mut sys); // EI
+    cpu.execute_instruction(&mut sys); // HALT, after EI
+    assert!(cpu.state.halted);
+
+    // The line $10 has priority, accepted in the HALT
+    cpu.execute_instruction(&mut sys);
+    assert!(!cpu.state.halted);
+    assert_eq!(0x0101, cpu.state.pc());
+    assert_eq!(0x0100, cpu.backtrace()[0].address);
+    cpu.deassert_irq(0x10);
+
+    cpu.execute_instruction(&mut sys); // EI
+    cpu.execute_instruction(&mut sys); // RETI
+    assert!(cpu.backtrace().is_empty());
+
+    // Then the line $20, still active
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x0201, cpu.state.pc());
+    assert_eq!(0x0200, cpu.backtrace()[0].address);
+}
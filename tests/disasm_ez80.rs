@@ -4,8 +4,8 @@ fn test_disasm_z80(code: &[u8], expected: &str) {
     let mut sys = PlainMachine::new();
     let mut cpu = Cpu::new_ez80();
 
-    for i in 0..code.len() {
-        sys.poke(i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        sys.poke(i as u32, *e);
     }
 
     let disasm = cpu.disasm_instruction(&mut sys);
@@ -36,3 +36,31 @@ fn test_disasm_suffix() {
 fn test_disasm_push_hl() {
     test_disasm_z80(&[0xe5], "PUSH HL");
 }
+
+#[test]
+fn test_disassemble_instruction() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    let code = [
+        0x21, 0x56, 0x34, 0x12, // LD HL, $123456
+        0x40, 0x21, 0x34, 0x12, // LD.SIS HL, $1234
+        0x5b, 0xdd, 0xe5,       // PUSH.LIL IX
+        0xdd, 0x36, 0x05, 0x42, // LD (IX+5), $42
+    ];
+    for (i, e) in code.iter().enumerate() {
+        sys.poke(0x020000 + i as u32, *e);
+    }
+
+    use ez80::disassembler::disassemble_instruction;
+    assert_eq!(("LD HL, $123456".to_string(), 4), disassemble_instruction(&mut sys, &cpu, None, 0x020000));
+    assert_eq!(("LD.SIS HL, $1234".to_string(), 4), disassemble_instruction(&mut sys, &cpu, None, 0x020004));
+    assert_eq!(("PUSH.LIL IX".to_string(), 3), disassemble_instruction(&mut sys, &cpu, None, 0x020008));
+    assert_eq!(("LD (IX+5), $42".to_string(), 4), disassemble_instruction(&mut sys, &cpu, None, 0x02000b));
+    assert_eq!(("LD HL, $3456".to_string(), 3), disassemble_instruction(&mut sys, &cpu, Some(false), 0x020000));
+
+    // The cpu is not modified
+    assert_eq!(0, cpu.state.pc());
+    assert!(cpu.state.reg.adl);
+}
@@ -4,8 +4,8 @@ fn test_disasm_z80(code: &[u8], expected: &str) {
     let mut sys = PlainMachine::new();
     let mut cpu = Cpu::new();
 
-    for i in 0..code.len() {
-        sys.poke(i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        sys.poke(i as u32, *e);
     }
 
     let disasm = cpu.disasm_instruction(&mut sys);
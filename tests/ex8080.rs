@@ -4,7 +4,7 @@ use ez80::*;
 8080/8085 CPU Exerciser by Ian Bartholomew and Frank Cringles
 */
 
-static CODE: &'static [u8] = include_bytes!("res/8080EX1.COM");
+static CODE: &[u8] = include_bytes!("res/8080EX1.COM");
 
 #[test]
 #[ignore]
@@ -14,9 +14,8 @@ fn test_ex8080() {
 
     // Load program
     let code = CODE;
-    let size = code.len();
-    for i in 0..size {
-        machine.poke(0x100 + i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        machine.poke(0x100 + i as u32, *e);
     }
 
     /*
@@ -27,8 +26,8 @@ fn test_ex8080() {
     */
     //let code = [0xD3, 0x00, 0xC9];
     let code = [0xC9];
-    for i in 0..code.len() {
-        machine.poke(5 + i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        machine.poke(5 + i as u32, *e);
     }
 
     // Patch to run a single test
@@ -49,18 +48,8 @@ fn test_ex8080() {
     loop {
         cpu.execute_instruction(&mut machine);
 
-        if trace && false {
-            // Test state
-            let addr = 0x1d80 as u32;
-            print!("Zex state 0x{:04x}: ", addr);
-            for i in 0..0x10 {
-                print!("{:02x} ", machine.peek(addr + i));
-            }
-            println!("");
-        }
-
         if cpu.state.pc() == 0x0000 {
-            println!("");
+            println!();
             break;
         }
 
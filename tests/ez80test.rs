@@ -154,11 +154,11 @@ fn test_madl() {
     sys.poke(0x0002, 0xed); // RSMIX
     sys.poke(0x0003, 0x7e);
 
-    assert_eq!(false, cpu.state.reg.madl);
+    assert!(!cpu.state.reg.madl);
     cpu.execute_instruction(&mut sys);
-    assert_eq!(true, cpu.state.reg.madl);
+    assert!(cpu.state.reg.madl);
     cpu.execute_instruction(&mut sys);
-    assert_eq!(false, cpu.state.reg.madl);
+    assert!(!cpu.state.reg.madl);
 }
 
 #[test]
@@ -203,9 +203,9 @@ fn test_tst_a_hl() {
 
     assert_eq!(2, cpu.state.pc());
     assert_eq!(0, cpu.registers().a());
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
-    assert_eq!(false, cpu.registers().get_flag(Flag::N));
-    assert_eq!(false, cpu.registers().get_flag(Flag::S));
+    assert!(!cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::N));
+    assert!(!cpu.registers().get_flag(Flag::S));
 
     cpu.state.reg.set8(Reg8::A, 0xff);
     cpu.state.reg.set24(Reg16::HL, 0);
@@ -215,9 +215,9 @@ fn test_tst_a_hl() {
 
     assert_eq!(2, cpu.state.pc());
     assert_eq!(0xff, cpu.registers().a());
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
-    assert_eq!(false, cpu.registers().get_flag(Flag::N));
-    assert_eq!(true, cpu.registers().get_flag(Flag::S));
+    assert!(!cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::N));
+    assert!(cpu.registers().get_flag(Flag::S));
 }
 
 #[test]
@@ -238,9 +238,9 @@ fn test_tst_a_n() {
 
     assert_eq!(3, cpu.state.pc());
     assert_eq!(0, cpu.registers().a());
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
-    assert_eq!(false, cpu.registers().get_flag(Flag::N));
-    assert_eq!(false, cpu.registers().get_flag(Flag::S));
+    assert!(!cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::N));
+    assert!(!cpu.registers().get_flag(Flag::S));
 
     cpu.state.reg.set8(Reg8::A, 0xff);
     cpu.state.set_pc(0);
@@ -249,9 +249,9 @@ fn test_tst_a_n() {
 
     assert_eq!(3, cpu.state.pc());
     assert_eq!(0xff, cpu.registers().a());
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
-    assert_eq!(false, cpu.registers().get_flag(Flag::N));
-    assert_eq!(true, cpu.registers().get_flag(Flag::S));
+    assert!(!cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::N));
+    assert!(cpu.registers().get_flag(Flag::S));
 }
 
 #[test]
@@ -272,9 +272,9 @@ fn test_tst_a_r() {
 
     assert_eq!(2, cpu.state.pc());
     assert_eq!(0, cpu.registers().a());
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
-    assert_eq!(false, cpu.registers().get_flag(Flag::N));
-    assert_eq!(false, cpu.registers().get_flag(Flag::S));
+    assert!(!cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::N));
+    assert!(!cpu.registers().get_flag(Flag::S));
 
     cpu.state.reg.set8(Reg8::A, 0xff);
     cpu.state.set_pc(0);
@@ -283,9 +283,9 @@ fn test_tst_a_r() {
 
     assert_eq!(2, cpu.state.pc());
     assert_eq!(0xff, cpu.registers().a());
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
-    assert_eq!(false, cpu.registers().get_flag(Flag::N));
-    assert_eq!(true, cpu.registers().get_flag(Flag::S));
+    assert!(!cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::N));
+    assert!(cpu.registers().get_flag(Flag::S));
 }
 
 #[test]
@@ -480,3 +480,63 @@ fn test_ld_sil() {
     assert_eq!(sys.peek(0x10006), 0xfe);
     assert_eq!(sys.peek(0x10007), 0x00);
 }
+
+#[test]
+fn test_rst_sis_ret_l() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    sys.poke(0x1000, 0x40); // RST.SIS 38h
+    sys.poke(0x1001, 0xff);
+    sys.poke(0x0038, 0x49); // RET.L
+    sys.poke(0x0039, 0xc9);
+    cpu.state.reg.pc = 0x1000;
+    cpu.state.reg.set24(Reg16::SP, 0x020000);
+    cpu.state.reg.set16(Reg16::SP, 0xff00);
+
+    cpu.execute_instruction(&mut sys);
+    assert!(!cpu.state.reg.adl);
+    assert_eq!(0x0038, cpu.state.pc());
+    // The mode byte and PC[23:16] on SPL, PC[15:0] on SPS
+    assert_eq!(0x01fffe, cpu.state.reg.get24(Reg16::SP));
+    assert_eq!(0x03, sys.peek(0x01fffe));
+    assert_eq!(0x00, sys.peek(0x01ffff));
+
+    cpu.execute_instruction(&mut sys);
+    assert!(cpu.state.reg.adl);
+    assert_eq!(0x001002, cpu.state.pc());
+    assert_eq!(0x020000, cpu.state.reg.get24(Reg16::SP));
+}
+
+#[test]
+fn test_jp_cc_lil_from_z80() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x5b); // JP.LIL Z, $013456
+    sys.poke(0x0001, 0xca);
+    sys.poke(0x0002, 0x56);
+    sys.poke(0x0003, 0x34);
+    sys.poke(0x0004, 0x01);
+    cpu.state.reg.set_flag(Flag::Z);
+
+    cpu.execute_instruction(&mut sys);
+    assert!(cpu.state.reg.adl);
+    assert_eq!(0x013456, cpu.state.pc());
+}
+
+#[test]
+fn test_jp_sis_hl_from_adl() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    sys.poke(0x0000, 0x40); // JP.SIS (HL)
+    sys.poke(0x0001, 0xe9);
+    cpu.state.reg.set24(Reg16::HL, 0x021234);
+
+    cpu.execute_instruction(&mut sys);
+    assert!(!cpu.state.reg.adl);
+    assert_eq!(0x001234, cpu.state.pc());
+}
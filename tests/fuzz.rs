@@ -0,0 +1,32 @@
+#![cfg(feature = "fuzz")]
+
+use ez80::fuzz;
+
+// Deterministic pseudo random bytes, a linear congruential generator
+fn random_bytes(seed: u32, len: usize) -> Vec<u8> {
+    let mut state = seed;
+    (0..len).map(|_| {
+        state = state.wrapping_mul(1103515245).wrapping_add(12345);
+        (state >> 16) as u8
+    }).collect()
+}
+
+#[test]
+fn test_fuzz_random_code() {
+    for seed in 0..200 {
+        let code = random_bytes(seed, 256);
+        fuzz::execute(&code);
+        fuzz::disassemble(&code);
+    }
+}
+
+#[test]
+fn test_fuzz_prefixes() {
+    // Prefixes at the end of the code, followed by the zeroed memory
+    for prefix in [0xcb, 0xdd, 0xed, 0xfd, 0x40, 0x49, 0x52, 0x5b] {
+        for second in 0..=255u8 {
+            fuzz::execute(&[prefix, second]);
+            fuzz::disassemble(&[prefix, 0xcb, second]);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use ez80::*;
+
+#[test]
+fn test_result_sequential() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x7e); // LD A, (HL)
+    cpu.registers().set16(Reg16::HL, 0x0100);
+
+    let result = cpu.execute_instruction(&mut sys);
+    assert_eq!(InstructionResult { cycles: 2, ..Default::default() }, result);
+}
+
+#[test]
+fn test_result_jump() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0x10); // DJNZ $0000
+    sys.poke(0x0001, 0xfe);
+    cpu.registers().set8(Reg8::B, 2);
+
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(result.branch);
+    assert!(!result.call);
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(!result.branch);
+}
+
+#[test]
+fn test_result_call_ret() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xcd); // CALL $0010
+    sys.poke(0x0001, 0x10);
+    sys.poke(0x0002, 0x00);
+    sys.poke(0x0010, 0xc0); // RET NZ
+    sys.poke(0x0011, 0xc9); // RET
+    cpu.registers().set16(Reg16::SP, 0x1000);
+    cpu.registers().set_flag(Flag::Z);
+
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(result.call && result.branch && !result.ret);
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(!result.ret && !result.branch);
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(result.ret && result.branch && !result.call);
+    assert_eq!(0x0003, cpu.state.pc());
+}
+
+#[test]
+fn test_result_block_repeat() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // LDIR
+    sys.poke(0x0001, 0xb0);
+    cpu.registers().set16(Reg16::BC, 2);
+
+    assert!(cpu.execute_instruction(&mut sys).branch);
+    assert!(!cpu.execute_instruction(&mut sys).branch);
+}
+
+#[test]
+fn test_result_io_halt() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xd3); // OUT ($10), A
+    sys.poke(0x0001, 0x10);
+    sys.poke(0x0002, 0x76); // HALT
+
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(result.io && !result.halt);
+    let result = cpu.execute_instruction(&mut sys);
+    assert!(result.halt && !result.io);
+    let result = cpu.execute_instruction(&mut sys);
+    assert_eq!(InstructionResult { cycles: 1, halt: true, ..Default::default() }, result);
+}
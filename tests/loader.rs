@@ -0,0 +1,173 @@
+use ez80::*;
+
+#[test]
+fn test_load_intel_hex() {
+    let mut sys = RamMachine::new(0x100000);
+    let hex = "\
+:020000040005F5
+:0300100021341286
+:0400000500050010E2
+:00000001FF
+";
+
+    let program = load_program(&mut sys, hex.as_bytes(), ProgramFormat::IntelHex).unwrap();
+    assert_eq!(Program { start: 0x050010, end: 0x050013, entry: 0x050010, adl: true, checksum: crc32(hex.as_bytes()) }, program);
+    assert_eq!(0x21, sys.peek(0x050010));
+    assert_eq!(0x12, sys.peek(0x050012));
+
+    let bad = ":0300100021341287\n";
+    assert!(load_program(&mut sys, bad.as_bytes(), ProgramFormat::IntelHex).is_err());
+}
+
+#[test]
+fn test_load_mos_bin() {
+    let mut sys = RamMachine::new(0x100000);
+    let mut cpu = Cpu::new_ez80();
+    let mut bin = vec![0u8; 0x50];
+    bin[0] = 0x3e; // LD A, $42
+    bin[1] = 0x42;
+    bin[0x40..0x45].copy_from_slice(b"MOS\x00\x01");
+
+    let program = load_program(&mut sys, &bin, ProgramFormat::MosBin).unwrap();
+    assert_eq!(0x040000, program.entry);
+    assert!(program.adl);
+
+    cpu.start_program(&program);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x42, cpu.registers().a());
+    assert_eq!(0x040002, cpu.state.pc());
+
+    assert!(load_program(&mut sys, &[0; 0x50], ProgramFormat::MosBin).is_err());
+}
+
+#[test]
+fn test_load_binary_z80() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    let program = load_program(&mut sys, &[0x3c], ProgramFormat::Binary(0x010100)).unwrap();
+    cpu.start_program(&program);
+    cpu.set_adl(false);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x010101, cpu.state.pc());
+}
+
+#[test]
+fn test_load_checked() {
+    let mut sys = RamMachine::new(0x100000);
+
+    assert_eq!(0xcbf43926, crc32(b"123456789"));
+    let program = load_program(&mut sys, b"123456789", ProgramFormat::Binary(0x1000)).unwrap();
+    assert_eq!(0xcbf43926, program.checksum);
+
+    let program = load_program_checked(&mut sys, &[0x3c], ProgramFormat::Binary(0x2000), crc32(&[0x3c])).unwrap();
+    assert_eq!(0x2001, program.end);
+    assert_eq!(0x3c, sys.peek(0x2000));
+
+    assert!(load_program_checked(&mut sys, &[0x3d], ProgramFormat::Binary(0x2000), crc32(&[0x3c])).is_err());
+    assert_eq!(0x3c, sys.peek(0x2000));
+}
+
+#[test]
+fn test_load_out_of_range() {
+    let mut sys = RamMachine::new(0x100000);
+
+    let program = load_program(&mut sys, &[0; 0x10], ProgramFormat::Binary(0xfffff0)).unwrap();
+    assert_eq!(0x1000000, program.end);
+    assert!(load_program(&mut sys, &[0; 0x11], ProgramFormat::Binary(0xfffff0)).is_err());
+    assert!(load_program(&mut sys, &[0; 0x20], ProgramFormat::Binary(0xfffffff0)).is_err());
+
+    let hex = "\
+:02000004FFFFFC
+:01FFFF00AA57
+:00000001FF
+";
+    assert!(load_program(&mut sys, hex.as_bytes(), ProgramFormat::IntelHex).is_err());
+}
+
+// A minimal ELF with a segment of 2 bytes of code and 2 of bss at
+// $040000, and the symbols main and counter
+fn test_elf() -> Vec<u8> {
+    fn u16le(v: &mut Vec<u8>, x: u16) { v.extend_from_slice(&x.to_le_bytes()); }
+    fn u32le(v: &mut Vec<u8>, x: u32) { v.extend_from_slice(&x.to_le_bytes()); }
+
+    let code_offset = 52 + 32;
+    let strtab_offset = code_offset + 2;
+    let strtab = b"\0main\0counter\0";
+    let symtab_offset = strtab_offset + strtab.len() as u32;
+    let shoff = symtab_offset + 3 * 16;
+
+    let mut elf = vec![0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    u16le(&mut elf, 2); // ET_EXEC
+    u16le(&mut elf, 220); // EM_Z80
+    u32le(&mut elf, 1);
+    u32le(&mut elf, 0x040000); // entry
+    u32le(&mut elf, 52); // phoff
+    u32le(&mut elf, shoff);
+    u32le(&mut elf, 0);
+    u16le(&mut elf, 52);
+    u16le(&mut elf, 32); // phentsize
+    u16le(&mut elf, 1); // phnum
+    u16le(&mut elf, 40); // shentsize
+    u16le(&mut elf, 3); // shnum
+    u16le(&mut elf, 0);
+
+    // PT_LOAD
+    for x in [1, code_offset, 0x040000, 0x040000, 2, 4, 5, 1] {
+        u32le(&mut elf, x);
+    }
+    elf.extend_from_slice(&[0x3e, 0x42]); // LD A, $42
+    elf.extend_from_slice(strtab);
+    // null symbol, main, counter
+    for (name, value, info, shndx) in [(0, 0, 0, 0), (1, 0x040000, 0x12, 1), (6, 0x040002, 0x11, 1)] {
+        u32le(&mut elf, name);
+        u32le(&mut elf, value);
+        u32le(&mut elf, 0);
+        elf.push(info);
+        elf.push(0);
+        u16le(&mut elf, shndx);
+    }
+    // sections: null, symtab, strtab
+    elf.extend_from_slice(&[0; 40]);
+    for x in [0, 2, 0, 0, symtab_offset, 3 * 16, 2, 1, 4, 16] {
+        u32le(&mut elf, x);
+    }
+    for x in [0, 3, 0, 0, strtab_offset, strtab.len() as u32, 0, 0, 1, 0] {
+        u32le(&mut elf, x);
+    }
+    elf
+}
+
+#[test]
+fn test_load_elf() {
+    let mut sys = RamMachine::new(0x100000);
+    let elf = test_elf();
+    sys.poke(0x040003, 0xff);
+
+    let program = load_program(&mut sys, &elf, ProgramFormat::Elf).unwrap();
+    assert_eq!(Program { start: 0x040000, end: 0x040004, entry: 0x040000, adl: true, checksum: crc32(&elf) }, program);
+    assert_eq!(0x3e, sys.peek(0x040000));
+    assert_eq!(0x00, sys.peek(0x040003));
+
+    let symbols = elf_symbols(&elf).unwrap();
+    assert_eq!(2, symbols.len());
+    assert_eq!("main", symbols.describe(0x040000));
+    assert_eq!("counter+0x1", symbols.describe(0x040003));
+
+    assert!(load_program(&mut sys, &elf[..60], ProgramFormat::Elf).is_err());
+}
+
+#[test]
+fn test_load_elf_bad_segment() {
+    let mut sys = RamMachine::new(0x100000);
+    let patched = |offset: usize, value: u32| {
+        let mut elf = test_elf();
+        elf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        elf
+    };
+
+    // memsz of 4G, memsz under filesz and a segment past $ffffff
+    assert!(load_program(&mut sys, &patched(72, 0xffffffff), ProgramFormat::Elf).is_err());
+    assert!(load_program(&mut sys, &patched(72, 1), ProgramFormat::Elf).is_err());
+    assert!(load_program(&mut sys, &patched(64, 0xfffffe), ProgramFormat::Elf).is_err());
+}
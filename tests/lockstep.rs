@@ -0,0 +1,54 @@
+use ez80::*;
+
+// This core as the reference, on its own machine
+struct Reference {
+    cpu: Cpu,
+    sys: RamMachine,
+}
+
+impl ReferenceCore for Reference {
+    fn step(&mut self) {
+        self.cpu.execute_instruction(&mut self.sys);
+    }
+    fn registers(&self) -> Registers {
+        self.cpu.registers_ref().clone()
+    }
+    fn cycles(&self) -> Option<u64> {
+        Some(self.cpu.state.cycles)
+    }
+}
+
+fn load(code: &[u8]) -> RamMachine {
+    let mut sys = RamMachine::default();
+    sys.load(0x0000, code);
+    sys
+}
+
+#[test]
+fn test_lockstep_match() {
+    let code = [0x3c, 0x18, 0xfd]; // INC A, JR $0000
+    let mut reference = Reference { cpu: Cpu::new_ez80(), sys: load(&code) };
+    let mut sys = load(&code);
+    let mut cpu = Cpu::new_ez80();
+
+    let run = cpu.run_lockstep(&mut sys, &mut reference, |a, b| a == b, 3, 10).unwrap();
+    assert_eq!(10, run.instructions);
+}
+
+#[test]
+fn test_lockstep_divergence() {
+    // NOP, NOP, NOP, INC A against NOP, NOP, NOP, DEC A
+    let mut reference = Reference { cpu: Cpu::new_ez80(), sys: load(&[0x00, 0x00, 0x00, 0x3d]) };
+    let mut sys = load(&[0x00, 0x00, 0x00, 0x3c]);
+    let mut cpu = Cpu::new_ez80();
+    cpu.registers().set_a(0x10);
+    reference.cpu.registers().set_a(0x10);
+
+    let divergence = cpu.run_lockstep(&mut sys, &mut reference, |a, b| a == b, 2, 10).unwrap_err();
+    assert_eq!(4, divergence.instructions);
+    assert_eq!(0x0003, divergence.pc);
+    assert_eq!(0x0f, divergence.expected.a());
+    assert_eq!(0x11, divergence.actual.a());
+    assert_eq!(Some(divergence.actual_cycles), divergence.expected_cycles);
+    assert!(divergence.to_string().starts_with("Divergence after 4 instructions, at $000003\n"));
+}
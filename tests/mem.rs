@@ -0,0 +1,58 @@
+use ez80::*;
+
+#[test]
+fn test_mem_fields() {
+    let mut sys = RamMachine::default();
+
+    mem::write_u24(&mut sys, 0x1000, 0x123456);
+    mem::write_u16(&mut sys, 0x1003, 0xabcd);
+    assert_eq!(vec![0x56, 0x34, 0x12, 0xcd, 0xab], mem::read_bytes(&sys, 0x1000, 5));
+    assert_eq!(0x123456, mem::read_u24(&sys, 0x1000));
+    assert_eq!(0xabcd, mem::read_field(&sys, 0x1000, 3, 2));
+    assert_eq!(0xcd123456, mem::read_u32(&sys, 0x1000));
+
+    // Usable through dyn Machine, as in a PC hook
+    let machine: &mut dyn Machine = &mut sys;
+    mem::put_cstring(machine, 0x2000, b"MOS");
+    assert_eq!(b"MOS".to_vec(), mem::get_cstring(machine, 0x2000));
+}
+
+#[test]
+fn test_mem_live_view() {
+    let mut sys = SharedRamMachine::default();
+    let mut cpu = Cpu::new();
+    let mut view = sys.view();
+
+    // INC (HL); JR $0000
+    mem::write_bytes(&mut view, 0x0000, &[0x34, 0x18, 0xfd]);
+    mem::memset(&mut view, 0x1000, 0xaa, 0x100);
+    mem::put_cstring(&mut view, 0x2000, b"HELLO");
+    cpu.registers().set16(Reg16::HL, 0x1000);
+
+    let viewer = std::thread::spawn(move || {
+        (mem::find(&view, 0x0000..0x3000, b"HELLO"), mem::find(&view, 0x10fe..0x1100, &[0xaa, 0xaa]))
+    });
+    for _ in 0..10 {
+        cpu.execute_instruction(&mut sys);
+    }
+    let (hello, fill) = viewer.join().unwrap();
+
+    assert_eq!(vec![0x2000], hello);
+    assert_eq!(vec![0x10fe], fill);
+    assert_eq!(0xaf, sys.peek(0x1000));
+}
+
+#[test]
+fn test_mem_search_wildcards() {
+    let mut sys = RamMachine::default();
+
+    // CALL $xxxx; LD A, $xx, twice
+    mem::write_bytes(&mut sys, 0x0100, &[0xcd, 0x34, 0x12, 0x3e, 0x01]);
+    mem::write_bytes(&mut sys, 0x0200, &[0xcd, 0x78, 0x56, 0x3e, 0x02]);
+    mem::write_bytes(&mut sys, 0x0300, &[0xcd, 0x78, 0x56, 0x3f, 0x02]);
+
+    let pattern = [Some(0xcd), None, None, Some(0x3e)];
+    assert_eq!(vec![0x0100, 0x0200], mem::search(&sys, 0x0000..0x1000, &pattern));
+    assert_eq!(vec![0x0200], mem::search(&sys, 0x0101..0x0204, &pattern));
+    assert!(mem::search(&sys, 0x0000..0x1000, &[]).is_empty());
+}
@@ -12,7 +12,7 @@ fn test_rrca_fast() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b11001001, cpu.registers().a());
-    assert_eq!(true, cpu.registers().get_flag(Flag::C));
+    assert!(cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -28,7 +28,7 @@ fn test_rrc_a() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b11001001, cpu.registers().a());
-    assert_eq!(true, cpu.registers().get_flag(Flag::C));
+    assert!(cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -44,7 +44,7 @@ fn test_rr_b() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b11001001, cpu.registers().get8(Reg8::B));
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -60,7 +60,7 @@ fn test_sra_c() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b11001001, cpu.registers().get8(Reg8::C));
-    assert_eq!(true, cpu.registers().get_flag(Flag::C));
+    assert!(cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -76,7 +76,7 @@ fn test_srl_d() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b01001001, cpu.registers().get8(Reg8::D));
-    assert_eq!(true, cpu.registers().get_flag(Flag::C));
+    assert!(cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -92,7 +92,7 @@ fn test_rlc_a() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b00100110, cpu.registers().a());
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -108,7 +108,7 @@ fn test_rl_b() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b00100111, cpu.registers().get8(Reg8::B));
-    assert_eq!(false, cpu.registers().get_flag(Flag::C));
+    assert!(!cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -124,7 +124,7 @@ fn test_sla_c() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b00100110, cpu.registers().get8(Reg8::C));
-    assert_eq!(true, cpu.registers().get_flag(Flag::C));
+    assert!(cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -140,7 +140,7 @@ fn test_sll_d() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b00100111, cpu.registers().get8(Reg8::D));
-    assert_eq!(true, cpu.registers().get_flag(Flag::C));
+    assert!(cpu.registers().get_flag(Flag::C));
 }
 
 #[test]
@@ -156,7 +156,7 @@ fn test_bit_a() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b00010010, cpu.registers().a());
-    assert_eq!(false, cpu.registers().get_flag(Flag::Z));
+    assert!(!cpu.registers().get_flag(Flag::Z));
 }
 
 #[test]
@@ -172,7 +172,7 @@ fn test_set_b() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b00010011, cpu.registers().get8(Reg8::B));
-    assert_eq!(false, cpu.registers().get_flag(Flag::Z));
+    assert!(!cpu.registers().get_flag(Flag::Z));
 }
 
 #[test]
@@ -188,7 +188,7 @@ fn test_res_c() {
     cpu.execute_instruction(&mut sys);
 
     assert_eq!(0b00010011, cpu.registers().get8(Reg8::C));
-    assert_eq!(false, cpu.registers().get_flag(Flag::Z));
+    assert!(!cpu.registers().get_flag(Flag::Z));
 }
 
 #[test]
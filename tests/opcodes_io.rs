@@ -29,3 +29,352 @@ fn test_in_e() {
 
     assert_eq!(0x8a, sys.port_in(0x6345));
 }
+
+struct SplitIoMachine {
+    mem: PlainMachine,
+    internal: [u8; 256],
+}
+
+impl Machine for SplitIoMachine {
+    fn peek(&self, address: u32) -> u8 { self.mem.peek(address) }
+    fn poke(&mut self, address: u32, value: u8) { self.mem.poke(address, value); }
+    fn use_cycles(&self, _cycles: u32) {}
+    fn port_in(&mut self, address: u16) -> u8 { self.mem.port_in(address) }
+    fn port_out(&mut self, address: u16, value: u8) { self.mem.port_out(address, value); }
+    fn internal_port_in(&mut self, address: u8) -> u8 { self.internal[address as usize] }
+    fn internal_port_out(&mut self, address: u8, value: u8) { self.internal[address as usize] = value; }
+}
+
+#[test]
+fn test_internal_external_io() {
+    let mut sys = SplitIoMachine { mem: PlainMachine::new(), internal: [0; 256] };
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // OUT0 ($9a), A
+    sys.poke(0x0001, 0x39);
+    sys.poke(0x0002, 0x9a);
+    sys.poke(0x0003, 0xed); // OUT (C), A
+    sys.poke(0x0004, 0x79);
+    sys.poke(0x0005, 0xed); // IN0 B, ($9b)
+    sys.poke(0x0006, 0x00);
+    sys.poke(0x0007, 0x9b);
+    cpu.registers().set_a(0x55);
+    cpu.registers().set16(Reg16::BC, 0x019a);
+    sys.internal[0x9b] = 0x77;
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x55, sys.internal[0x9a]);
+    assert_eq!(0x00, sys.mem.port_in(0x009a));
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x55, sys.mem.port_in(0x019a));
+    assert_eq!(0x55, sys.internal[0x9a]);
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x77, cpu.registers().get8(Reg8::B));
+}
+
+#[derive(Default)]
+struct ExpansionCard {
+    register: u8,
+}
+
+impl IoDevice for ExpansionCard {
+    fn port_in(&mut self, _address: u16) -> u8 { self.register }
+    fn port_out(&mut self, _address: u16, value: u8) { self.register = value; }
+}
+
+#[test]
+fn test_io_device() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let card = Rc::new(RefCell::new(ExpansionCard::default()));
+    cpu.add_io_device(0x0200..0x0210, 3, card.clone());
+
+    sys.poke(0x0000, 0xed); // OUT (C), A
+    sys.poke(0x0001, 0x79);
+    sys.poke(0x0002, 0xed); // OUT (C), A
+    sys.poke(0x0003, 0x79);
+    cpu.registers().set_a(0x5a);
+    cpu.registers().set16(Reg16::BC, 0x0208);
+
+    let result = cpu.execute_instruction(&mut sys);
+    assert_eq!(0x5a, card.borrow().register);
+    assert_eq!(0x00, sys.port_in(0x0208));
+    // 2 bytes fetched, the IO cycle and the wait states
+    assert_eq!(2 + 1 + 3, result.cycles);
+
+    cpu.remove_io_device(0x0200..0x0210);
+    let result = cpu.execute_instruction(&mut sys);
+    assert_eq!(0x5a, sys.port_in(0x0208));
+    assert_eq!(2 + 1, result.cycles);
+}
+
+// A timer counting the bus cycles, read on its port
+#[derive(Default)]
+struct CycleTimer {
+    cycles: u32,
+}
+
+impl IoDevice for CycleTimer {
+    fn port_in(&mut self, _address: u16) -> u8 { self.cycles as u8 }
+    fn port_out(&mut self, _address: u16, _value: u8) { self.cycles = 0; }
+    fn tick(&mut self, cycles: u32, _lines: &mut CpuLines) { self.cycles += cycles; }
+}
+
+#[test]
+fn test_io_device_tick() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let timer = Rc::new(RefCell::new(CycleTimer::default()));
+    cpu.add_io_device(0x0300..0x0301, 0, timer.clone());
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0x00); // NOP
+    sys.poke(0x0002, 0x76); // HALT
+
+    let mut cycles = 0;
+    for _ in 0..3 {
+        cycles += cpu.execute_instruction(&mut sys).cycles;
+    }
+    assert_eq!(cycles, timer.borrow().cycles);
+
+    // Halted
+    cpu.run_for_cycles(&mut sys, 100);
+    assert_eq!(cycles + 100, timer.borrow().cycles);
+}
+
+// Adds up all the ticks, for halts longer than u32::MAX cycles
+#[derive(Default)]
+struct TickCounter {
+    cycles: u64,
+}
+
+impl IoDevice for TickCounter {
+    fn port_in(&mut self, _address: u16) -> u8 { 0xff }
+    fn port_out(&mut self, _address: u16, _value: u8) {}
+    fn tick(&mut self, cycles: u32, _lines: &mut CpuLines) { self.cycles += cycles as u64; }
+}
+
+#[test]
+fn test_io_device_tick_long_halt() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let counter = Rc::new(RefCell::new(TickCounter::default()));
+    cpu.add_io_device(0x0300..0x0301, 0, counter.clone());
+
+    sys.poke(0x0000, 0x76); // HALT
+
+    cpu.run_for_cycles(&mut sys, 3 * (u32::MAX as u64) + 100);
+    assert_eq!(cpu.state.cycles, counter.borrow().cycles);
+}
+
+#[test]
+fn test_otimr() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // OTIMR
+    sys.poke(0x0001, 0x93);
+    sys.poke(0x1000, 0x11);
+    sys.poke(0x1001, 0x22);
+    cpu.registers().set16(Reg16::HL, 0x1000);
+    cpu.registers().set16(Reg16::BC, 0x0250);
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x0000, cpu.state.pc());
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x0002, cpu.state.pc());
+
+    assert_eq!(0x11, sys.port_in(0x0050));
+    assert_eq!(0x22, sys.port_in(0x0051));
+    assert_eq!(0x0052, cpu.registers().get16(Reg16::BC));
+    assert_eq!(0x1002, cpu.registers().get16(Reg16::HL));
+    assert!(cpu.registers().get_flag(Flag::Z));
+}
+
+#[test]
+fn test_indm() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // INDM
+    sys.poke(0x0001, 0x8a);
+    sys.port_out(0x0020, 0x85);
+    cpu.registers().set16(Reg16::HL, 0x1000);
+    cpu.registers().set16(Reg16::BC, 0x0120);
+
+    cpu.execute_instruction(&mut sys);
+
+    assert_eq!(0x85, sys.peek(0x1000));
+    assert_eq!(0x001f, cpu.registers().get16(Reg16::BC));
+    assert_eq!(0x0fff, cpu.registers().get16(Reg16::HL));
+    assert!(cpu.registers().get_flag(Flag::Z));
+    assert!(cpu.registers().get_flag(Flag::N));
+}
+
+#[test]
+fn test_ini2() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // INI2
+    sys.poke(0x0001, 0x84);
+    sys.port_out(0x1234, 0x42);
+    cpu.registers().set16(Reg16::HL, 0x1000);
+    cpu.registers().set16(Reg16::BC, 0x1234);
+
+    cpu.execute_instruction(&mut sys);
+
+    assert_eq!(0x42, sys.peek(0x1000));
+    assert_eq!(0x1135, cpu.registers().get16(Reg16::BC));
+    assert_eq!(0x1001, cpu.registers().get16(Reg16::HL));
+    assert!(!cpu.registers().get_flag(Flag::Z));
+}
+
+#[test]
+fn test_oti2r_adl() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    sys.poke(0x0000, 0xed); // OTI2R
+    sys.poke(0x0001, 0xb4);
+    sys.poke(0x010000, 0x11);
+    sys.poke(0x010001, 0x22);
+    cpu.registers().set24(Reg16::HL, 0x010000);
+    cpu.registers().set24(Reg16::DE, 0x000300);
+    cpu.registers().set24(Reg16::BC, 0x000002);
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+
+    assert_eq!(0x000002, cpu.state.pc());
+    assert_eq!(0x11, sys.port_in(0x0300));
+    assert_eq!(0x22, sys.port_in(0x0301));
+    assert_eq!(0x000302, cpu.registers().get24(Reg16::DE));
+    assert_eq!(0x010002, cpu.registers().get24(Reg16::HL));
+    assert_eq!(0x000000, cpu.registers().get24(Reg16::BC));
+}
+
+#[test]
+fn test_inirx() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // INIRX
+    sys.poke(0x0001, 0xc2);
+    sys.port_out(0x0300, 0x5a);
+    cpu.registers().set16(Reg16::HL, 0x1000);
+    cpu.registers().set16(Reg16::DE, 0x0300);
+    cpu.registers().set16(Reg16::BC, 0x0003);
+
+    for _ in 0..3 {
+        cpu.execute_instruction(&mut sys);
+    }
+
+    assert_eq!(0x0002, cpu.state.pc());
+    assert_eq!(0x5a, sys.peek(0x1000));
+    assert_eq!(0x5a, sys.peek(0x1002));
+    assert_eq!(0x0300, cpu.registers().get16(Reg16::DE));
+    assert_eq!(0x1003, cpu.registers().get16(Reg16::HL));
+    assert_eq!(0x0000, cpu.registers().get16(Reg16::BC));
+}
+
+#[test]
+fn test_tstio() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0000, 0xed); // TSTIO $0f
+    sys.poke(0x0001, 0x74);
+    sys.poke(0x0002, 0x0f);
+    sys.port_out(0x0040, 0xf0);
+    cpu.registers().set16(Reg16::BC, 0x1240);
+
+    cpu.execute_instruction(&mut sys);
+
+    assert_eq!(0x0003, cpu.state.pc());
+    assert!(cpu.registers().get_flag(Flag::Z));
+}
+
+#[test]
+fn test_services_ports() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_services_port(Some(0xfe00));
+
+    sys.poke(0x0000, 0xed); // IN A, (C)
+    sys.poke(0x0001, 0x78);
+    sys.poke(0x0002, 0xed); // OUT (C), A
+    sys.poke(0x0003, 0x79);
+    sys.poke(0x0004, 0x0c); // INC C
+    sys.poke(0x0005, 0xed); // IN D, (C)
+    sys.poke(0x0006, 0x50);
+    cpu.registers().set16(Reg16::BC, 0xfe00);
+    cpu.state.cycles = 0x1234;
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x5a, cpu.registers().a());
+
+    let before = cpu.state.cycles;
+    cpu.execute_instruction(&mut sys);
+    let latch = cpu.state.services_latch;
+    assert!(before < latch && latch <= cpu.state.cycles);
+    assert_eq!(0x00, sys.port_in(0xfe00));
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(latch as u8, cpu.registers().get8(Reg8::D));
+}
+
+#[test]
+fn test_services_console_exit() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_services_port(Some(0xfe00));
+
+    let code = [
+        0x01, 0x01, 0xfe, // LD BC, $FE01
+        0x3e, b'H', 0xed, 0x79, // LD A, 'H'; OUT (C), A
+        0x3e, b'i', 0xed, 0x79, // LD A, 'i'; OUT (C), A
+        0x0c, // INC C
+        0x3e, 0x03, 0xed, 0x79, // LD A, $03; OUT (C), A
+        0x18, 0xfe, // JR $
+    ];
+    for (i, &value) in code.iter().enumerate() {
+        sys.poke(i as u32, value);
+    }
+
+    let run = cpu.run_for_cycles(&mut sys, 1000);
+    assert_eq!(StepResult::Breakpoint(BreakReason::Exit(3)), run.status);
+    assert_eq!(b"Hi".to_vec(), cpu.take_services_output());
+    assert!(cpu.take_services_output().is_empty());
+}
+
+#[test]
+fn test_services_virtual_host_clock() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_services_port(Some(0xfe00));
+    cpu.set_host_clock(HostClock::Virtual { start: 1_000_000, cycles_per_ms: 10 });
+
+    sys.poke(0x0000, 0xed); // OUT (C), A
+    sys.poke(0x0001, 0x79);
+    cpu.registers().set16(Reg16::BC, 0xfe00);
+    cpu.registers().set_a(0x01);
+    cpu.state.cycles = 1000;
+
+    cpu.execute_instruction(&mut sys);
+    // 2 opcode fetches before the IO cycle
+    assert_eq!(1_000_000 + 1002 / 10, cpu.state.services_latch);
+}
@@ -126,3 +126,45 @@ fn test_call_ret() {
      cpu.execute_instruction(&mut sys);
     assert_eq!(0x0003, cpu.state.pc());
 }
+
+#[test]
+fn test_reset() {
+    let mut cpu = Cpu::new_ez80();
+
+    cpu.state.reg.adl = true;
+    cpu.state.reg.madl = true;
+    cpu.state.reg.mbase = 0x03;
+    cpu.state.set_pc(0x031234);
+    cpu.registers().set_interrupts(true);
+    cpu.registers().set8(Reg8::A, 0x55);
+
+    cpu.reset();
+    assert_eq!(0x0000, cpu.state.pc());
+    assert!(!cpu.state.reg.adl);
+    assert!(!cpu.state.reg.madl);
+    assert_eq!(0x00, cpu.state.reg.mbase);
+    assert!(!cpu.registers().get_iff1());
+    assert_eq!(0x55, cpu.registers().get8(Reg8::A));
+}
+
+#[test]
+fn test_nmi_retn() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+
+    sys.poke(0x0066, 0xed); // RETN
+    sys.poke(0x0067, 0x45);
+    cpu.registers().set16(Reg16::SP, 0x8000);
+    cpu.registers().set_interrupts(true);
+    cpu.state.set_pc(0x1234);
+
+    cpu.nmi(&mut sys);
+    assert_eq!(0x0066, cpu.state.pc());
+    assert!(!cpu.registers().get_iff1());
+    assert!(cpu.registers().get_iff2());
+    assert_eq!(0x7ffe, cpu.registers().get16(Reg16::SP));
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x1234, cpu.state.pc());
+    assert!(cpu.registers().get_iff1());
+}
@@ -93,3 +93,59 @@ fn test_ld_d_e() {
     assert_eq!(0xee, cpu.registers().get8(Reg8::D));
     assert_eq!(0xee, cpu.registers().get8(Reg8::E));
 }
+
+#[test]
+fn test_ld_i_hl_and_back() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    sys.poke(0x0000, 0xed); // LD I, HL
+    sys.poke(0x0001, 0xc7);
+    sys.poke(0x0002, 0xed); // LD HL, I
+    sys.poke(0x0003, 0xd7);
+    cpu.registers().set24(Reg16::HL, 0x12abcd);
+    cpu.registers().mbase = 0x05;
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0xabcd, cpu.registers().get_i16());
+    assert_eq!(0xcd, cpu.registers().get8(Reg8::I));
+
+    cpu.registers().set24(Reg16::HL, 0);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x05abcd, cpu.registers().get24(Reg16::HL));
+}
+
+#[test]
+fn test_r_refresh() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new();
+
+    sys.poke(0x0000, 0x00); // NOP
+    sys.poke(0x0001, 0xdd); // LD IX, $1234
+    sys.poke(0x0002, 0x21);
+    sys.poke(0x0003, 0x34);
+    sys.poke(0x0004, 0x12);
+    sys.poke(0x0005, 0xdd); // RLC (IX+$01)
+    sys.poke(0x0006, 0xcb);
+    sys.poke(0x0007, 0x01);
+    sys.poke(0x0008, 0x06);
+    sys.poke(0x0009, 0xed); // LD A, R
+    sys.poke(0x000a, 0x5f);
+    cpu.registers().set8(Reg8::R, 0xff);
+
+    // Bit 7 is kept
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x80, cpu.registers().get8(Reg8::R));
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x82, cpu.registers().get8(Reg8::R));
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x84, cpu.registers().get8(Reg8::R));
+
+    // LD A, R sees its own opcode fetches
+    cpu.registers().set_interrupts(true);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x86, cpu.registers().a());
+    assert!(cpu.registers().get_flag(Flag::S));
+    assert!(cpu.registers().get_flag(Flag::P));
+}
@@ -0,0 +1,76 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ez80::*;
+
+// The timers with the guest in a JR $ loop
+fn setup() -> (RamMachine, Cpu, Rc<RefCell<Prt>>) {
+    let mut sys = RamMachine::default();
+    let mut cpu = Cpu::new_ez80();
+    let prt = Rc::new(RefCell::new(Prt::new()));
+    cpu.add_io_device(0x80..0x92, 0, prt.clone());
+    sys.load(0x0000, &[0x18, 0xfe]); // JR $
+    (sys, cpu, prt)
+}
+
+fn read_dr(prt: &Rc<RefCell<Prt>>, port: u8) -> u16 {
+    let mut prt = prt.borrow_mut();
+    let low = prt.port_in(port).unwrap();
+    low as u16 | (prt.port_in(port + 1).unwrap() as u16) << 8
+}
+
+#[test]
+fn test_prt_counts_cycles() {
+    let (mut sys, mut cpu, prt) = setup();
+    prt.borrow_mut().set_irq(0, 0x0a);
+    prt.borrow_mut().port_out(0x81, 0x00); // TMR0_RR $0100
+    prt.borrow_mut().port_out(0x82, 0x01);
+    // IRQ_EN, continuous, /4, RST_EN, PRT_EN
+    prt.borrow_mut().port_out(0x80, 0x53);
+    let start = cpu.state.cycles;
+
+    cpu.run_for_cycles(&mut sys, 200);
+    let elapsed = cpu.state.cycles - start;
+    assert_eq!(0x100 - (elapsed / 4) as u16, read_dr(&prt, 0x81));
+    assert!(cpu.state.irq_lines.is_empty());
+
+    // Reloaded at the end of count
+    cpu.run_for_cycles(&mut sys, 0x400);
+    let elapsed = cpu.state.cycles - start;
+    assert_eq!(0x100 - (elapsed / 4 % 0x100) as u16, read_dr(&prt, 0x81));
+    assert!(cpu.state.irq_lines.contains(&0x0a));
+    assert_eq!(Some(0xd1), prt.borrow_mut().port_in(0x80));
+    assert_eq!(Some(0x51), prt.borrow_mut().port_in(0x80));
+    cpu.run_for_cycles(&mut sys, 10);
+    assert!(cpu.state.irq_lines.is_empty());
+
+    // The other timers are disabled
+    assert_eq!(0x0000, read_dr(&prt, 0x84));
+}
+
+#[test]
+fn test_prt_single_pass() {
+    let (mut sys, mut cpu, prt) = setup();
+    prt.borrow_mut().port_out(0x84, 0x10); // TMR1_RR $0010
+    prt.borrow_mut().port_out(0x85, 0x00);
+    // /16, RST_EN, PRT_EN
+    prt.borrow_mut().port_out(0x83, 0x07);
+
+    cpu.run_for_cycles(&mut sys, 16 * 0x10 + 10);
+    assert_eq!(Some(0x84), prt.borrow_mut().port_in(0x83));
+    assert_eq!(0x0000, read_dr(&prt, 0x84));
+}
+
+#[test]
+fn test_prt_fast_boot() {
+    let (mut sys, mut cpu, prt) = setup();
+    prt.borrow_mut().set_fast_boot(true);
+    prt.borrow_mut().port_out(0x81, 0xff); // TMR0_RR $ffff
+    prt.borrow_mut().port_out(0x82, 0xff);
+    // /256, RST_EN, PRT_EN
+    prt.borrow_mut().port_out(0x80, 0x0f);
+
+    // The end of count, after one clock instead of 65535
+    cpu.run_for_cycles(&mut sys, 256);
+    assert_eq!(Some(0x8c), prt.borrow_mut().port_in(0x80));
+}
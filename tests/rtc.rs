@@ -0,0 +1,153 @@
+use ez80::*;
+
+// 2023-11-14 22:13:20, a Tuesday
+const TIME: i64 = 1_700_000_000;
+
+struct RtcMachine {
+    ram: RamMachine,
+    rtc: Rtc,
+}
+
+impl Machine for RtcMachine {
+    fn peek(&self, address: u32) -> u8 {
+        self.ram.peek(address)
+    }
+    fn poke(&mut self, address: u32, value: u8) {
+        self.ram.poke(address, value);
+    }
+    fn port_in(&mut self, address: u16) -> u8 {
+        self.ram.port_in(address)
+    }
+    fn port_out(&mut self, address: u16, value: u8) {
+        self.ram.port_out(address, value);
+    }
+    fn internal_port_in(&mut self, address: u8) -> u8 {
+        self.rtc.port_in(address).unwrap_or(0x00)
+    }
+    fn internal_port_out(&mut self, address: u8, value: u8) {
+        self.rtc.port_out(address, value);
+    }
+    fn use_cycles(&self, _cycles: u32) {
+    }
+}
+
+#[test]
+fn test_rtc_frozen_read_by_guest() {
+    let mut sys = RtcMachine { ram: RamMachine::default(), rtc: Rtc::frozen(TIME) };
+    let mut cpu = Cpu::new_ez80();
+
+    sys.ram.load(0x0000, &[
+        0xed, 0x00, 0xe2, // IN0 B, ($E2)
+        0xed, 0x08, 0xe4, // IN0 C, ($E4)
+        0x3e, 0x20, 0xed, 0x39, 0xed, // LD A, $20; OUT0 ($ED), A
+        0xed, 0x10, 0xe0, // IN0 D, ($E0)
+    ]);
+    for _ in 0..5 {
+        cpu.execute_instruction(&mut sys);
+    }
+
+    assert_eq!(22, cpu.registers().get8(Reg8::B));
+    assert_eq!(14, cpu.registers().get8(Reg8::C));
+    // BCD
+    assert_eq!(0x20, cpu.registers().get8(Reg8::D));
+
+    // Frozen, the cycles do not move it
+    sys.rtc.update(1_000_000_000);
+    assert_eq!(TIME, sys.rtc.now());
+}
+
+#[test]
+fn test_rtc_registers() {
+    let mut rtc = Rtc::frozen(TIME);
+
+    let fields: Vec<u8> = (0xe0..=0xe7).map(|port| rtc.port_in(port).unwrap()).collect();
+    assert_eq!(vec![20, 13, 22, 3, 14, 11, 23, 20], fields);
+    assert_eq!(None, rtc.port_in(0xee));
+
+    // Locked, the time is kept
+    rtc.port_out(0xe2, 8);
+    assert_eq!(TIME, rtc.now());
+
+    rtc.port_out(0xed, 0x01);
+    rtc.port_out(0xe2, 8);
+    rtc.port_out(0xe6, 24);
+    rtc.port_out(0xe5, 2);
+    rtc.port_out(0xe4, 29);
+    assert_eq!(RtcTime::Frozen(1_709_194_400), rtc.time());
+    // 2024-02-29 08:13:20 was a Thursday
+    assert_eq!(Some(5), rtc.port_in(0xe3));
+}
+
+#[test]
+fn test_rtc_host_clock_offset_and_alarm() {
+    let clock = HostClock::Virtual { start: TIME as u64 * 1000, cycles_per_ms: 10 };
+    let mut rtc = Rtc::new(clock);
+    assert_eq!(TIME, rtc.now());
+
+    rtc.set_time(RtcTime::Offset(3600));
+    assert_eq!(Some(23), rtc.port_in(0xe2));
+
+    // Alarm on the seconds at 21, with its interrupt
+    rtc.port_out(0xe8, 21);
+    rtc.port_out(0xec, 0x01);
+    rtc.port_out(0xed, 0x40);
+    assert!(!rtc.update(5_000));
+    assert!(rtc.update(10_000));
+    assert_eq!(TIME + 3601, rtc.now());
+    assert!(!rtc.update(12_000));
+
+    assert_eq!(Some(0xc0), rtc.port_in(0xed));
+    assert_eq!(Some(0x40), rtc.port_in(0xed));
+}
+
+#[test]
+fn test_rtc_alarm_jumped_over() {
+    let clock = HostClock::Virtual { start: TIME as u64 * 1000, cycles_per_ms: 10 };
+    let mut rtc = Rtc::new(clock);
+
+    // Alarm on the seconds at 23, passed in a single update from 20 to 25
+    rtc.port_out(0xe8, 23);
+    rtc.port_out(0xec, 0x01);
+    rtc.port_out(0xed, 0x40);
+    assert!(rtc.update(50_000));
+    assert_eq!(Some(25), rtc.port_in(0xe0));
+    assert_eq!(Some(0xc0), rtc.port_in(0xed));
+
+    // A minute later, still from a single update
+    assert!(rtc.update(650_000));
+    assert!(!rtc.update(660_000));
+}
+
+#[test]
+fn test_rtc_io_device_irq() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut sys = RamMachine::default();
+    let mut cpu = Cpu::new_ez80();
+    let clock = HostClock::Virtual { start: TIME as u64 * 1000, cycles_per_ms: 10 };
+    let rtc = Rc::new(RefCell::new(Rtc::new(clock)));
+    rtc.borrow_mut().set_irq(0x2c);
+    cpu.add_io_device(0xe0..0xee, 0, rtc.clone());
+
+    sys.load(0x0000, &[
+        0x3e, 0x15, 0xed, 0x39, 0xe8, // LD A, 21; OUT0 ($E8), A
+        0x3e, 0x01, 0xed, 0x39, 0xec, // LD A, $01; OUT0 ($EC), A
+        0x3e, 0x40, 0xed, 0x39, 0xed, // LD A, $40; OUT0 ($ED), A
+        0x18, 0xfe, // JR $
+        0xed, 0x38, 0xed, // IN0 A, ($ED)
+        0x18, 0xfe, // JR $
+    ]);
+
+    cpu.run_for_cycles(&mut sys, 5_000);
+    assert!(cpu.state.irq_lines.is_empty());
+    cpu.run_for_cycles(&mut sys, 10_000);
+    assert!(cpu.state.irq_lines.contains(&0x2c));
+    assert_eq!(TIME + 1, rtc.borrow().now());
+
+    // Reading RTC_CTRL acknowledges the alarm
+    cpu.state.set_pc(0x0011);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0xc0, cpu.registers().a());
+    assert!(cpu.state.irq_lines.is_empty());
+}
@@ -0,0 +1,9 @@
+#![cfg(feature = "selftest")]
+
+use ez80::selftest;
+
+#[test]
+fn test_selftest() {
+    let report = selftest::run();
+    assert!(report.passed(), "{}", report);
+}
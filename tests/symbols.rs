@@ -0,0 +1,80 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ez80::*;
+
+const SYMBOLS: &str = "
+; test symbols
+0B3C2 vdp_protocol_handler
+main = $0100
+_putch: EQU 0200h
+";
+
+#[test]
+fn test_parse_symbols() {
+    let symbols = Symbols::parse(SYMBOLS).unwrap();
+
+    assert_eq!(3, symbols.len());
+    assert_eq!(Some("main"), symbols.name(0x0100));
+    assert_eq!(Some(("vdp_protocol_handler", 0x12)), symbols.lookup(0xb3d4));
+    assert_eq!("vdp_protocol_handler+0x12", symbols.describe(0xb3d4));
+    assert_eq!("_putch", symbols.describe(0x0200));
+    assert_eq!("$00010", symbols.describe(0x0010));
+
+    assert!(Symbols::parse("main = nowhere").is_err());
+}
+
+#[test]
+fn test_symbolize_asm() {
+    let symbols = Symbols::parse(SYMBOLS).unwrap();
+
+    assert_eq!("CALL _putch", symbols.symbolize_asm("CALL $200"));
+    assert_eq!("LD A, (IX+$100)", symbols.symbolize_asm("LD A, (IX+$100)"));
+    assert_eq!("JP $300", symbols.symbolize_asm("JP $300"));
+}
+
+#[test]
+fn test_symbolize_asm_address_operands() {
+    // ZDS maps usually have a symbol at 0
+    let symbols = Symbols::parse("_reset = $000000\n_uart = $000010\n_data = $040000").unwrap();
+
+    assert_eq!("LD A, $0", symbols.symbolize_asm("LD A, $0"));
+    assert_eq!("CP $0", symbols.symbolize_asm("CP $0"));
+    assert_eq!("LD (HL), $10", symbols.symbolize_asm("LD (HL), $10"));
+    assert_eq!("OUT ($10), A", symbols.symbolize_asm("OUT ($10), A"));
+    assert_eq!("IN0 A, ($10)", symbols.symbolize_asm("IN0 A, ($10)"));
+    assert_eq!("LD (IX+$0), $10", symbols.symbolize_asm("LD (IX+$0), $10"));
+
+    assert_eq!("JP _reset", symbols.symbolize_asm("JP $0"));
+    assert_eq!("CALL.LIL NZ, _data", symbols.symbolize_asm("CALL.LIL NZ, $40000"));
+    assert_eq!("JR _uart", symbols.symbolize_asm("JR $10"));
+    assert_eq!("RST _uart", symbols.symbolize_asm("RST 10h"));
+    assert_eq!("RST 38h", symbols.symbolize_asm("RST 38h"));
+    assert_eq!("LD A, (_data)", symbols.symbolize_asm("LD A, ($40000)"));
+    assert_eq!("LD (_uart), HL", symbols.symbolize_asm("LD ($10), HL"));
+    assert_eq!("LD HL, _data", symbols.symbolize_asm("LD HL, $40000"));
+    assert_eq!("LD.LIL BC, _reset", symbols.symbolize_asm("LD.LIL BC, $0"));
+}
+
+#[test]
+fn test_log_tracer_symbols() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let symbols = Symbols::parse(SYMBOLS).unwrap();
+    let log = Rc::new(RefCell::new(LogTracer::with_symbols(Vec::new(), symbols)));
+    cpu.set_tracer(log.clone());
+
+    sys.poke(0x0000, 0xc3); // JP $0100
+    sys.poke(0x0001, 0x00);
+    sys.poke(0x0002, 0x01);
+    sys.poke(0x0100, 0x00); // NOP
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+
+    let text = String::from_utf8(log.borrow().get_ref().clone()).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert!(lines[0].starts_with("==> 000000: JP main "));
+    assert_eq!("main:", lines[1]);
+    assert!(lines[2].starts_with("==> 000100: NOP "));
+}
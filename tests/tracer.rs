@@ -0,0 +1,297 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ez80::*;
+
+#[test]
+fn test_ring_tracer() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let ring = Rc::new(RefCell::new(RingTracer::new(2)));
+    cpu.set_tracer(ring.clone());
+
+    sys.poke(0x0000, 0x3e); // LD A, $12
+    sys.poke(0x0001, 0x12);
+    sys.poke(0x0002, 0x3c); // INC A
+    sys.poke(0x0003, 0x00); // NOP
+
+    for _ in 0..3 {
+        cpu.execute_instruction(&mut sys);
+    }
+
+    let ring = ring.borrow();
+    let entries: Vec<&InstructionTrace> = ring.entries().collect();
+    assert_eq!(2, entries.len());
+    assert_eq!(0x0002, entries[0].pc);
+    assert_eq!("INC A", entries[0].asm);
+    assert_eq!(0x13, entries[0].reg.a());
+    assert_eq!("000002: INC A\n000003: NOP\n", ring.to_string());
+}
+
+#[test]
+fn test_json_tracer() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let json = Rc::new(RefCell::new(JsonTracer::new(Vec::new())));
+    cpu.set_tracer(json.clone());
+
+    sys.poke(0x0000, 0x77); // LD (HL), A
+    sys.poke(0x0001, 0xd3); // OUT ($10), A
+    sys.poke(0x0002, 0x10);
+    cpu.registers().set16(Reg16::HL, 0x0100);
+    cpu.registers().set_a(0x42);
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+
+    let json = json.borrow();
+    let out = String::from_utf8(json.get_ref().clone()).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(4, lines.len());
+    assert_eq!("{\"event\":\"write\",\"address\":256,\"value\":66}", lines[0]);
+    assert!(lines[1].starts_with("{\"event\":\"instruction\",\"pc\":0,\"asm\":\"LD (HL), A\","));
+    assert_eq!("{\"event\":\"out\",\"port\":16912,\"value\":66}", lines[2]);
+    assert!(lines[3].starts_with("{\"event\":\"instruction\",\"pc\":1,\"asm\":\"OUT ($10), A\","));
+}
+
+#[derive(Default)]
+struct InterruptLog {
+    handlers: Vec<u32>,
+}
+
+impl Tracer for InterruptLog {
+    fn interrupt(&mut self, address: u32) {
+        self.handlers.push(address);
+    }
+}
+
+#[test]
+fn test_trace_interrupts() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_z80();
+    let log = Rc::new(RefCell::new(InterruptLog::default()));
+    cpu.set_tracer(log.clone());
+
+    sys.poke(0x0000, 0xfb); // EI
+    sys.poke(0x0001, 0xed); // IM 2
+    sys.poke(0x0002, 0x5e);
+    sys.poke(0x0010, 0x34); // Vector $10 to $1234
+    sys.poke(0x0011, 0x12);
+    cpu.registers().set16(Reg16::SP, 0x1000);
+
+    cpu.execute_instruction(&mut sys);
+    cpu.execute_instruction(&mut sys);
+    cpu.interrupt(&mut sys, 0x10);
+    assert_eq!(0x1234, cpu.state.pc());
+    cpu.signal_nmi();
+    cpu.execute_instruction(&mut sys);
+
+    assert_eq!(vec![0x1234, 0x0066], log.borrow().handlers);
+}
+
+struct SharedLog {
+    pcs: std::sync::Arc<std::sync::Mutex<Vec<u32>>>,
+}
+
+impl Tracer for SharedLog {
+    fn instruction(&mut self, trace: &InstructionTrace) {
+        self.pcs.lock().unwrap().push(trace.pc);
+    }
+}
+
+#[test]
+fn test_threaded_tracer() {
+    for policy in [OverflowPolicy::Block, OverflowPolicy::DropOldest] {
+        let mut sys = PlainMachine::new();
+        let mut cpu = Cpu::new_ez80();
+        let pcs = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let tracer = ThreadedTracer::new(SharedLog { pcs: pcs.clone() }, 4, policy);
+        cpu.set_tracer(tracer);
+
+        // 1000 NOPs
+        for _ in 0..1000 {
+            cpu.execute_instruction(&mut sys);
+        }
+        // Dropping the tracer waits for the writer
+        cpu.clear_tracer();
+
+        let pcs = pcs.lock().unwrap();
+        assert_eq!(999, *pcs.last().unwrap());
+        if policy == OverflowPolicy::Block {
+            assert_eq!(1000, pcs.len());
+            assert!(pcs.iter().enumerate().all(|(i, pc)| i as u32 == *pc));
+        } else {
+            assert!(pcs.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+}
+
+struct PanickingSink;
+
+impl Tracer for PanickingSink {
+    fn instruction(&mut self, _trace: &InstructionTrace) {
+        panic!("sink failure");
+    }
+}
+
+#[test]
+fn test_threaded_tracer_sink_panics() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let tracer = Rc::new(RefCell::new(ThreadedTracer::new(PanickingSink, 1, OverflowPolicy::Block)));
+    cpu.set_tracer(tracer.clone());
+
+    // Does not wait forever for the writer
+    for _ in 0..100 {
+        cpu.execute_instruction(&mut sys);
+    }
+    assert!(tracer.borrow().failed());
+    assert!(tracer.borrow().dropped() > 0);
+    cpu.clear_tracer();
+}
+
+#[test]
+fn test_memory_profiler() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let profiler = Rc::new(RefCell::new(MemoryProfiler::new(8)));
+    cpu.set_tracer(profiler.clone());
+
+    sys.poke(0x0000, 0xcd); // CALL $0100
+    sys.poke(0x0001, 0x00);
+    sys.poke(0x0002, 0x01);
+    sys.poke(0x0003, 0xcd); // CALL $0100
+    sys.poke(0x0004, 0x00);
+    sys.poke(0x0005, 0x01);
+    sys.poke(0x0100, 0x7e); // LD A, (HL)
+    sys.poke(0x0101, 0xc9); // RET
+    cpu.registers().set16(Reg16::HL, 0x2010);
+    cpu.registers().set16(Reg16::SP, 0x1000);
+
+    for _ in 0..6 {
+        cpu.execute_instruction(&mut sys);
+    }
+
+    let profiler = profiler.borrow();
+    assert_eq!(PageCounts { reads: 0, writes: 0, executes: 2 }, profiler.counts(0x0000));
+    assert_eq!(PageCounts { reads: 0, writes: 0, executes: 4 }, profiler.counts(0x0100));
+    assert_eq!(PageCounts { reads: 2, writes: 0, executes: 0 }, profiler.counts(0x2000));
+    // The stack
+    assert_eq!(PageCounts { reads: 4, writes: 4, executes: 0 }, profiler.counts(0x0f00));
+    assert_eq!(vec![(0x0100, 2)], profiler.calls());
+}
+
+#[test]
+fn test_io_log() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let log = Rc::new(RefCell::new(IoLog::new(8)));
+    log.borrow_mut().add_filter(0x4200..0x4220);
+    let seen = Rc::new(RefCell::new(0));
+    let counter = seen.clone();
+    log.borrow_mut().set_callback(Box::new(move |_| *counter.borrow_mut() += 1));
+    cpu.set_tracer(log.clone());
+
+    sys.poke(0x0000, 0x3e); // LD A, $42
+    sys.poke(0x0001, 0x42);
+    sys.poke(0x0002, 0xd3); // OUT ($10), A
+    sys.poke(0x0003, 0x10);
+    sys.poke(0x0004, 0xdb); // IN A, ($20)
+    sys.poke(0x0005, 0x20);
+    sys.poke(0x0006, 0xd3); // OUT ($10), A
+    sys.poke(0x0007, 0x10);
+
+    for _ in 0..4 {
+        cpu.execute_instruction(&mut sys);
+    }
+
+    let log = log.borrow();
+    let entries: Vec<IoAccess> = log.entries().copied().collect();
+    assert_eq!(1, entries.len());
+    assert_eq!(0x0002, entries[0].pc);
+    assert_eq!(0x4210, entries[0].port);
+    assert_eq!(0x42, entries[0].value);
+    assert!(entries[0].write);
+    assert!(entries[0].cycles > 0);
+    assert_eq!(vec![(0x4210, PortCounts { reads: 0, writes: 1 })], log.ports());
+    assert_eq!(1, *seen.borrow());
+}
+
+#[test]
+fn test_io_log_without_instruction() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    let log = Rc::new(RefCell::new(IoLog::new(8)));
+    cpu.set_tracer(log.clone());
+    cpu.set_illegal_policy(IllegalPolicy::Stop);
+
+    sys.poke(0x0000, 0xcd); // CALL $0100
+    sys.poke(0x0001, 0x00);
+    sys.poke(0x0002, 0x01);
+    sys.poke(0x0003, 0xed); // invalid
+    sys.poke(0x0004, 0x05);
+    cpu.registers().set16(Reg16::SP, 0x1000);
+    cpu.set_pc_hook(0x0100, Box::new(|env: &mut Environment| {
+        env.port_out(0x4210, 0x55);
+        env.subroutine_return();
+    }));
+    cpu.set_pc_hook(0x0003, Box::new(|env: &mut Environment| {
+        env.port_out(0x4211, 0x66);
+    }));
+
+    for _ in 0..3 {
+        cpu.execute_instruction(&mut sys);
+    }
+
+    let entries: Vec<(u32, u16)> = log.borrow().entries().map(|e| (e.pc, e.port)).collect();
+    assert_eq!(vec![(0x0100, 0x4210), (0x0003, 0x4211)], entries);
+
+    // An access not yet reported is dropped by clear
+    log.borrow_mut().port_write(0x4212, 0x77);
+    log.borrow_mut().clear();
+    log.borrow_mut().no_instruction(0x0000, 0);
+    assert_eq!(0, log.borrow().entries().count());
+    assert!(log.borrow().ports().is_empty());
+}
+
+#[test]
+fn test_code_coverage() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new();
+    let coverage = Rc::new(RefCell::new(CodeCoverage::new()));
+    cpu.set_tracer(coverage.clone());
+
+    sys.poke(0x0000, 0xdd); // LD IX, $1234
+    sys.poke(0x0001, 0x21);
+    sys.poke(0x0002, 0x34);
+    sys.poke(0x0003, 0x12);
+    sys.poke(0x0004, 0x06); // LD B, $03
+    sys.poke(0x0005, 0x03);
+    sys.poke(0x0006, 0x10); // DJNZ $0006
+    sys.poke(0x0007, 0xfe);
+    sys.poke(0x0008, 0x76); // HALT
+
+    for _ in 0..8 {
+        cpu.execute_instruction(&mut sys);
+    }
+
+    let coverage = coverage.borrow();
+    assert_eq!(3, coverage.count(0x0006));
+    assert_eq!(0, coverage.count(0x0007));
+    assert_eq!(vec![
+        CoverageRange { start: 0x0000, end: 0x0006, count: 1 },
+        CoverageRange { start: 0x0006, end: 0x0008, count: 3 },
+        CoverageRange { start: 0x0008, end: 0x0009, count: 1 },
+    ], coverage.ranges());
+
+    let mut text = Vec::new();
+    coverage.write_ranges(&mut text).unwrap();
+    assert_eq!("000000 000006 1\n000006 000008 3\n000008 000009 1\n", String::from_utf8(text).unwrap());
+
+    let mut drcov = Vec::new();
+    coverage.write_drcov(&mut drcov, "test.bin").unwrap();
+    let header = b"BB Table: 3 bbs\n";
+    let table = drcov.windows(header.len()).position(|w| w == header).unwrap() + header.len();
+    assert_eq!(&[6, 0, 0, 0, 2, 0, 0, 0], &drcov[table + 8..table + 16]);
+    assert_eq!(table + 3 * 8, drcov.len());
+}
@@ -0,0 +1,59 @@
+use std::rc::Rc;
+
+use ez80::*;
+
+#[test]
+fn test_page_map_identity() {
+    let map = PageMap::new(12);
+    assert_eq!(0x123456, map.translate(0x123456));
+    assert_eq!(0x123, map.mapped(0x123));
+    assert_eq!(0x1000, map.pages());
+}
+
+#[test]
+#[should_panic(expected = "page $1000 out of the 4096 pages")]
+fn test_page_map_out_of_range() {
+    let map = PageMap::new(12);
+    map.map(0x1000, 0x000);
+}
+
+#[test]
+fn test_translated_fetch_and_data() {
+    let mut sys = PlainMachine::new();
+    let mut cpu = Cpu::new_ez80();
+    cpu.set_adl(true);
+
+    let map = Rc::new(PageMap::new(16));
+    map.map(0x00, 0x02);
+    map.map(0x01, 0x03);
+    cpu.set_address_translation(map.clone());
+
+    sys.poke(0x020000, 0x3a); // LD A, ($010010)
+    sys.poke(0x020001, 0x10);
+    sys.poke(0x020002, 0x00);
+    sys.poke(0x020003, 0x01);
+    sys.poke(0x020004, 0x32); // LD ($010011), A
+    sys.poke(0x020005, 0x11);
+    sys.poke(0x020006, 0x00);
+    sys.poke(0x020007, 0x01);
+    sys.poke(0x030010, 0x42);
+
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x42, cpu.registers().a());
+    assert_eq!(0x000004, cpu.state.pc());
+
+    // Bank switch while installed
+    map.map(0x01, 0x01);
+    cpu.execute_instruction(&mut sys);
+    assert_eq!(0x42, sys.peek(0x010011));
+    assert_eq!(0x00, sys.peek(0x030011));
+
+    let (asm, len) = disassembler::disassemble_instruction(&mut sys, &cpu, None, 0x000000);
+    assert_eq!(("LD A, ($10010)".to_string(), 4), (asm, len));
+
+    cpu.clear_address_translation();
+    cpu.state.set_pc(0x000000);
+    cpu.execute_instruction(&mut sys);
+    // NOP in the untranslated memory
+    assert_eq!(0x000001, cpu.state.pc());
+}
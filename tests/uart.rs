@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ez80::*;
+
+const LSR: u8 = 0xc5;
+
+// UART0 at 1/16 of the clock, with the guest in a JR $ loop
+fn setup(lctl: u8) -> (RamMachine, Cpu, Rc<RefCell<Uart>>) {
+    let mut sys = RamMachine::default();
+    let mut cpu = Cpu::new_ez80();
+    let uart = Rc::new(RefCell::new(Uart::new(0xc0)));
+    {
+        let mut uart = uart.borrow_mut();
+        uart.port_out(0xc3, 0x80 | lctl);
+        uart.port_out(0xc0, 0x01);
+        uart.port_out(0xc1, 0x00);
+        uart.port_out(0xc3, lctl);
+    }
+    cpu.add_io_device(0xc0..0xc8, 0, uart.clone());
+    sys.load(0x0000, &[0x18, 0xfe]); // JR $
+    (sys, cpu, uart)
+}
+
+#[test]
+fn test_uart_receive_at_baud_rate() {
+    let (mut sys, mut cpu, uart) = setup(0x03);
+    // 8N1, with the start bit
+    assert_eq!(16 * 10, uart.borrow().char_cycles());
+
+    uart.borrow_mut().receive(b"ab");
+    cpu.run_for_cycles(&mut sys, 100);
+    assert_eq!(Some(0x60), uart.borrow_mut().port_in(LSR));
+    cpu.run_for_cycles(&mut sys, 100);
+    assert_eq!(Some(0x61), uart.borrow_mut().port_in(LSR));
+
+    // Without the FIFO, the second character overruns RBR
+    cpu.run_for_cycles(&mut sys, 200);
+    assert_eq!(Some(0x63), uart.borrow_mut().port_in(LSR));
+    assert_eq!(Some(0x61), uart.borrow_mut().port_in(LSR));
+    assert_eq!(Some(b'a'), uart.borrow_mut().port_in(0xc0));
+    assert_eq!(Some(0x60), uart.borrow_mut().port_in(LSR));
+}
+
+#[test]
+fn test_uart_fifo_trigger_level() {
+    let (mut sys, mut cpu, uart) = setup(0x03);
+    uart.borrow_mut().set_irq(0x18);
+    uart.borrow_mut().port_out(0xc2, 0x41); // FIFO, trigger level 4
+    uart.borrow_mut().port_out(0xc1, 0x01); // RIE
+
+    uart.borrow_mut().receive(b"hello");
+    cpu.run_for_cycles(&mut sys, 3 * 160 + 10);
+    assert!(cpu.state.irq_lines.is_empty());
+    assert_eq!(Some(0xc1), uart.borrow_mut().port_in(0xc2));
+    cpu.run_for_cycles(&mut sys, 160);
+    assert!(cpu.state.irq_lines.contains(&0x18));
+    assert_eq!(Some(0xc4), uart.borrow_mut().port_in(0xc2));
+
+    let read: Vec<u8> = (0..4).map(|_| uart.borrow_mut().port_in(0xc0).unwrap()).collect();
+    assert_eq!(b"hell".to_vec(), read);
+    cpu.run_for_cycles(&mut sys, 160);
+    assert!(cpu.state.irq_lines.is_empty());
+
+    // The last one, below the trigger level, times out
+    cpu.run_for_cycles(&mut sys, 4 * 160);
+    assert_eq!(Some(0xcc), uart.borrow_mut().port_in(0xc2));
+    assert!(cpu.state.irq_lines.contains(&0x18));
+}
+
+#[test]
+fn test_uart_line_noise() {
+    let run = || {
+        // 8 bits with parity
+        let (mut sys, mut cpu, uart) = setup(0x0b);
+        assert_eq!(16 * 11, uart.borrow().char_cycles());
+        uart.borrow_mut().port_out(0xc2, 0x01);
+        uart.borrow_mut().set_line_noise(1, 1234);
+        uart.borrow_mut().receive(&[0x55; 16]);
+        cpu.run_for_cycles(&mut sys, 16 * 176);
+
+        let mut uart = uart.borrow_mut();
+        let mut received = Vec::new();
+        for _ in 0..16 {
+            let lsr = uart.port_in(LSR).unwrap();
+            // Every character is corrupted
+            assert_eq!(0x81, lsr & 0x81);
+            assert_ne!(0, lsr & 0x0c);
+            received.push((uart.port_in(0xc0).unwrap(), lsr & 0x0c));
+        }
+        assert_eq!(Some(0x60), uart.port_in(LSR));
+        received
+    };
+
+    let received = run();
+    assert!(received.iter().any(|(value, _)| *value != 0x55));
+    // Same seed, same noise
+    assert_eq!(received, run());
+}
+
+#[test]
+fn test_uart_transmit_at_baud_rate() {
+    let (mut sys, mut cpu, uart) = setup(0x03);
+    uart.borrow_mut().port_out(0xc2, 0x01);
+    for value in b"abc" {
+        uart.borrow_mut().port_out(0xc0, *value);
+    }
+    assert_eq!(Some(0x00), uart.borrow_mut().port_in(LSR));
+
+    cpu.run_for_cycles(&mut sys, 170);
+    assert_eq!(b"a".to_vec(), uart.borrow_mut().take_output());
+    cpu.run_for_cycles(&mut sys, 160);
+    // The last one is on the line
+    assert_eq!(Some(0x20), uart.borrow_mut().port_in(LSR));
+    cpu.run_for_cycles(&mut sys, 160);
+    assert_eq!(b"bc".to_vec(), uart.borrow_mut().take_output());
+    assert_eq!(Some(0x60), uart.borrow_mut().port_in(LSR));
+}
+
+#[test]
+fn test_uart_transmit_backpressure() {
+    let (mut sys, mut cpu, uart) = setup(0x03);
+    uart.borrow_mut().port_out(0xc2, 0x01);
+    uart.borrow_mut().set_output_capacity(2);
+    for value in b"hello" {
+        uart.borrow_mut().port_out(0xc0, *value);
+    }
+
+    // The host does not take the output, the FIFO stays full
+    cpu.run_for_cycles(&mut sys, 100 * 160);
+    assert_eq!(Some(0x00), uart.borrow_mut().port_in(LSR));
+    assert_eq!(b"he".to_vec(), uart.borrow_mut().take_output());
+
+    cpu.run_for_cycles(&mut sys, 100 * 160);
+    assert_eq!(b"ll".to_vec(), uart.borrow_mut().take_output());
+    cpu.run_for_cycles(&mut sys, 100 * 160);
+    assert_eq!(b"o".to_vec(), uart.borrow_mut().take_output());
+    assert_eq!(Some(0x60), uart.borrow_mut().port_in(LSR));
+}
+
+#[test]
+fn test_uart_transmit_interrupt() {
+    let (mut sys, mut cpu, uart) = setup(0x03);
+    uart.borrow_mut().set_irq(0x18);
+    uart.borrow_mut().port_out(0xc2, 0x01);
+
+    // Enabled with THR empty
+    uart.borrow_mut().port_out(0xc1, 0x02);
+    cpu.run_for_cycles(&mut sys, 10);
+    assert!(cpu.state.irq_lines.contains(&0x18));
+    assert_eq!(Some(0xc2), uart.borrow_mut().port_in(0xc2));
+    assert_eq!(Some(0xc1), uart.borrow_mut().port_in(0xc2));
+    cpu.run_for_cycles(&mut sys, 10);
+    assert!(cpu.state.irq_lines.is_empty());
+
+    // Raised again when the FIFO empties into the line
+    uart.borrow_mut().port_out(0xc0, b'x');
+    uart.borrow_mut().port_out(0xc0, b'y');
+    cpu.run_for_cycles(&mut sys, 10);
+    assert!(cpu.state.irq_lines.is_empty());
+    cpu.run_for_cycles(&mut sys, 160);
+    assert!(cpu.state.irq_lines.contains(&0x18));
+    assert_eq!(b"x".to_vec(), uart.borrow_mut().take_output());
+}
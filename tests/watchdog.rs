@@ -0,0 +1,75 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use ez80::*;
+
+struct Setup {
+    sys: RamMachine,
+    cpu: Cpu,
+    wdt: Rc<RefCell<Watchdog>>,
+    fired: Rc<RefCell<Vec<WatchdogAction>>>,
+}
+
+fn setup(program: &[u8]) -> Setup {
+    let mut sys = RamMachine::default();
+    let mut cpu = Cpu::new_ez80();
+    let wdt = Rc::new(RefCell::new(Watchdog::new()));
+    let fired = Rc::new(RefCell::new(Vec::new()));
+    let log = fired.clone();
+    wdt.borrow_mut().set_callback(Box::new(move |action| log.borrow_mut().push(action)));
+    cpu.add_io_device(0x93..0x95, 0, wdt.clone());
+    sys.load(0x0000, program);
+    Setup { sys, cpu, wdt, fired }
+}
+
+// Runs for the cycles, or until the watchdog fires
+fn run(s: &mut Setup, cycles: u64) {
+    let start = s.cpu.state.cycles;
+    while s.cpu.state.cycles - start < cycles && s.fired.borrow().is_empty() {
+        s.cpu.execute_instruction(&mut s.sys);
+    }
+}
+
+#[test]
+fn test_watchdog_reset() {
+    let mut s = setup(&[
+        0x3e, 0x83, 0xed, 0x39, 0x93, // LD A, $83; OUT0 ($93), A
+        0x3e, 0x00, 0xed, 0x39, 0x93, // LD A, $00; OUT0 ($93), A
+        0x18, 0xfe, // JR $
+    ]);
+
+    run(&mut s, 1 << 20);
+    assert_eq!(vec![WatchdogAction::Reset], *s.fired.borrow());
+    assert!(s.cpu.state.cycles >= 1 << 18);
+    assert_eq!(Some(0x20), s.wdt.borrow().port_in(0x93));
+    assert!(!s.wdt.borrow().is_enabled());
+
+    // The reset is applied before the next instruction
+    s.cpu.execute_instruction(&mut s.sys);
+    assert_eq!(0x0002, s.cpu.state.pc());
+}
+
+#[test]
+fn test_watchdog_refreshed() {
+    let mut s = setup(&[
+        0x3e, 0xc3, 0xed, 0x39, 0x93, // LD A, $C3; OUT0 ($93), A
+        0x3e, 0xa5, 0xed, 0x39, 0x94, // LD A, $A5; OUT0 ($94), A
+        0x3e, 0x5a, 0xed, 0x39, 0x94, // LD A, $5A; OUT0 ($94), A
+        0x18, 0xf4, // JR $0005
+        0x18, 0xfe, // JR $
+    ]);
+
+    run(&mut s, 1 << 20);
+    assert!(s.fired.borrow().is_empty());
+    assert!(s.wdt.borrow().is_enabled());
+
+    // Not refreshed, NMI
+    s.cpu.state.set_pc(0x0011);
+    run(&mut s, 1 << 20);
+    assert_eq!(vec![WatchdogAction::Nmi], *s.fired.borrow());
+    assert!(s.cpu.state.nmi_pending);
+    assert!(s.wdt.borrow().is_enabled());
+
+    s.cpu.execute_instruction(&mut s.sys);
+    assert_eq!(0x0066, s.cpu.backtrace()[0].address);
+}
@@ -8,7 +8,7 @@ use ez80::*;
 //static CODE: &'static [u8] = include_bytes!("res/z80docflags.out");
 //static CODE: &'static [u8] = include_bytes!("res/z80flags.out");
 //static CODE: &'static [u8] = include_bytes!("res/z80memptr.out");
-static CODE: &'static [u8] = include_bytes!("res/z80full.out");
+static CODE: &[u8] = include_bytes!("res/z80full.out");
 
 const START: u16 = 0x8000;
 
@@ -20,9 +20,8 @@ fn z80test() {
 
     // Load program
     let code = CODE;
-    let size = code.len();
-    for i in 0..size {
-        machine.poke(START as u32 + i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        machine.poke(START as u32 + i as u32, *e);
     }
 
     // Do nothing on 0x1601 and RST 0x10
@@ -50,7 +49,7 @@ fn z80test() {
         cpu.execute_instruction(&mut machine);
 
         if cpu.state.pc() == 0x0000 {
-            println!("");
+            println!();
             break;
         }
 
@@ -58,9 +57,7 @@ fn z80test() {
             let mut ch = cpu.registers().get8(Reg8::A) as char;
             if ch == '\r' {
                 ch = '\n'
-            } else if ch as u8 == 23 {
-                ch = ' '
-            } else if ch as u8 == 26 {
+            } else if ch as u8 == 23 || ch as u8 == 26 {
                 ch = ' '
             } 
             //print!("{}[{}]", ch, ch as u8);
@@ -69,5 +66,5 @@ fn z80test() {
         }
     }
 
-    assert_eq!(true, msg.contains("CPU TESTS OK"));
+    assert!(msg.contains("CPU TESTS OK"));
 }
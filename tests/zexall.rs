@@ -1,7 +1,7 @@
 use ez80::*;
 
 //static ZEXDOC: &'static [u8] = include_bytes!("res/zexdoc.com");
-static ZEXALL: &'static [u8] = include_bytes!("res/zexall.com");
+static ZEXALL: &[u8] = include_bytes!("res/zexall.com");
 
 #[test]
 #[ignore]
@@ -12,9 +12,8 @@ fn test_zexall() {
     // Load program
     //let code = ZEXDOC;
     let code = ZEXALL;
-    let size = code.len();
-    for i in 0..size {
-        machine.poke(0x100 + i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        machine.poke(0x100 + i as u32, *e);
     }
 
     /*
@@ -25,8 +24,8 @@ fn test_zexall() {
         ret
     */
     let code = [0xD3, 0x00, 0xC9];
-    for i in 0..code.len() {
-        machine.poke(5 + i as u32, code[i]);
+    for (i, e) in code.iter().enumerate() {
+        machine.poke(5 + i as u32, *e);
     }
 
     // Patch to run a single test
@@ -49,16 +48,16 @@ fn test_zexall() {
 
         if trace {
             // Test state
-            let addr = 0x1d80 as u32;
+            let addr = 0x1d80_u32;
             print!("Zex state 0x{:04x}: ", addr);
             for i in 0..0x10 {
                 print!("{:02x} ", machine.peek(addr + i));
             }
-            println!("");
+            println!();
         }
 
         if cpu.state.pc() == 0x0000 {
-            println!("");
+            println!();
             break;
         }
 